@@ -1,14 +1,20 @@
 use std::path::PathBuf;
 
 use crate::error::Error;
-use crate::gpg::GitGpg;
-use git2::{build::CheckoutBuilder, ErrorClass, ErrorCode, Repository, Signature};
+use crate::gpg::{GitGpg, SignatureStatus};
+use crate::lock::RepoLock;
+use crate::refs::RefLayout;
+use git2::{
+    build::CheckoutBuilder, BranchType, Commit, ErrorClass, ErrorCode, Oid, Repository, Signature,
+    Tree,
+};
 
 pub struct Ctx {
     repo: git2::Repository,
     config: git2::Config,
     user: Signature<'static>,
     gpg: GitGpg,
+    refs: RefLayout,
 }
 
 impl Ctx {
@@ -28,17 +34,25 @@ impl Ctx {
             }
             Err(err) => return Err(err.into()),
         };
+
+        Self::from_repo(repo).map(Some)
+    }
+
+    /// The context of an opened repository.
+    pub fn from_repo(repo: Repository) -> Result<Self, Error> {
         let config = repo.config()?;
         let user = repo.signature()?.to_owned();
 
-        let gpg = GitGpg::from_config(&config);
+        let gpg = GitGpg::from_config(&config, &user);
+        let refs = RefLayout::from_config(&config)?;
 
-        Ok(Some(Self {
+        Ok(Self {
             repo,
             config,
             user,
             gpg,
-        }))
+            refs,
+        })
     }
 
     pub const fn repo(&self) -> &git2::Repository {
@@ -53,6 +67,59 @@ impl Ctx {
         &self.user
     }
 
+    /// Where the queues are stored, see [`RefLayout::from_config`].
+    pub const fn refs(&self) -> &RefLayout {
+        &self.refs
+    }
+
+    /// Acquire the repository lock.
+    ///
+    /// Must be held by any operation that modifies a queue, the lock is released
+    /// when the returned guard is dropped.
+    pub fn lock(&self) -> Result<RepoLock, Error> {
+        RepoLock::acquire(self.repo.path())
+    }
+
+    /// Create a new commit, signing it if `commit.gpgsign` is enabled.
+    ///
+    /// The committer is always the current user. If `update_ref` is given, the
+    /// reference is updated (or created) to point to the new commit.
+    pub fn commit(
+        &self,
+        update_ref: Option<&str>,
+        author: &Signature<'_>,
+        message: &str,
+        tree: &Tree<'_>,
+        parents: &[&Commit<'_>],
+    ) -> Result<Oid, Error> {
+        let oid = if self.gpg.sign_commits() {
+            let buffer = self
+                .repo
+                .commit_create_buffer(author, &self.user, message, tree, parents)?;
+            let signature = self.gpg.sign_buffer(&buffer)?;
+            let content = buffer.as_str().ok_or(Error::NonUtf8)?;
+            self.repo.commit_signed(content, &signature, None)?
+        } else {
+            self.repo
+                .commit(None, author, &self.user, message, tree, parents)?
+        };
+
+        if let Some(name) = update_ref {
+            self.repo.reference(name, oid, true, message)?;
+        }
+
+        Ok(oid)
+    }
+
+    /// Verify the signature of the given commit.
+    pub fn verify_commit(&self, oid: Oid) -> Result<SignatureStatus, Error> {
+        match self.repo.extract_signature(&oid, None) {
+            Ok((signature, data)) => self.gpg.verify(&signature, &data),
+            Err(err) if err.code() == ErrorCode::NotFound => Ok(SignatureStatus::Unsigned),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     pub fn current_branch(&self) -> Result<Option<git2::Branch<'_>>, Error> {
         let head = match self.repo.head() {
             Ok(h) => h,
@@ -92,18 +159,94 @@ impl Ctx {
         Ok(())
     }
 
+    /// Find a local branch by its short name, e.g. `main`, or its full
+    /// reference name, e.g. `refs/heads/main`.
     pub fn find_branch(&self, branch: &str) -> Result<Option<git2::Branch<'_>>, Error> {
-        match self.repo.find_reference(branch) {
-            Ok(branch_ref) => Ok(Some(git2::Branch::wrap(branch_ref))),
+        let found = match branch.strip_prefix("refs/heads/") {
+            Some(name) => self.repo.find_branch(name, BranchType::Local),
+            None => self.repo.find_branch(branch, BranchType::Local),
+        };
+        match found {
+            Ok(branch) => Ok(Some(branch)),
             Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
             Err(err) => Err(err.into()),
         }
     }
 
+    /// The status of the tracked files, in the index and the working tree.
     pub fn workdir_status(&self) -> Result<git2::Statuses<'_>, Error> {
         let status = self.repo.statuses(Some(
-            git2::StatusOptions::new().show(git2::StatusShow::Workdir),
+            git2::StatusOptions::new().show(git2::StatusShow::IndexAndWorkdir),
         ))?;
         Ok(status)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{base_branch, clone_of, setup};
+
+    #[test]
+    fn signed_commits() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let key = dir.path().join("key");
+        let status = std::process::Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "a@example.com", "-f"])
+            .arg(&key)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let public = std::fs::read_to_string(key.with_extension("pub")).unwrap();
+        let allowed = dir.path().join("allowed_signers");
+        std::fs::write(&allowed, format!("a@example.com {}", public)).unwrap();
+
+        let mut config = ctx.repo().config().unwrap();
+        config.set_bool("commit.gpgSign", true).unwrap();
+        config.set_str("gpg.format", "ssh").unwrap();
+        config
+            .set_str("user.signingKey", key.to_str().unwrap())
+            .unwrap();
+        config
+            .set_str("gpg.ssh.allowedSignersFile", allowed.to_str().unwrap())
+            .unwrap();
+        let workdir = ctx.repo().workdir().unwrap().to_path_buf();
+        let ctx = Ctx::from_repo(Repository::open(&workdir).unwrap()).unwrap();
+
+        let tree = ctx.repo().treebuilder(None).unwrap().write().unwrap();
+        let tree = ctx.repo().find_tree(tree).unwrap();
+        let author = Signature::now("B", "b@example.com").unwrap();
+        let oid = ctx
+            .commit(Some("refs/heads/signed"), &author, "signed", &tree, &[])
+            .unwrap();
+
+        let commit = ctx.repo().find_commit(oid).unwrap();
+        assert_eq!(commit.author().name(), Some("B"));
+        assert_eq!(commit.committer().name(), Some("A"));
+        assert_eq!(ctx.repo().refname_to_id("refs/heads/signed").unwrap(), oid);
+        // Git itself accepts the signature.
+        let status = std::process::Command::new("git")
+            .args(["verify-commit", "--raw", "signed"])
+            .current_dir(&workdir)
+            .output()
+            .unwrap();
+        assert!(
+            status.status.success(),
+            "{}",
+            String::from_utf8_lossy(&status.stderr)
+        );
+        assert_eq!(
+            ctx.verify_commit(oid).unwrap(),
+            SignatureStatus::Good("a@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn unsigned_commits() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let head = base_branch(&ctx).get().target().unwrap();
+        assert_eq!(ctx.verify_commit(head).unwrap(), SignatureStatus::Unsigned);
+    }
+}
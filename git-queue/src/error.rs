@@ -1,11 +1,28 @@
+use std::path::PathBuf;
+
+use crate::naming::NameError;
+use crate::spec::SpecError;
+
 #[derive(Debug)]
 pub enum Error {
     NotInRepository,
     NotInitialized,
     Inconsistency(&'static str),
-    InvalidName,
+    InvalidName(NameError),
     AlreadyExists(&'static str),
+    NestedQueue(String, String),
+    Diverged(String),
+    NotPublished(String),
+    BranchChanged(String),
     NonUtf8,
+    Locked(PathBuf),
+    Modified(&'static str),
+    Signing(String),
+    ApplyFailed(String),
+    Mail(String),
+    Review(String),
+    Config(String),
+    Spec(SpecError),
     Git(git2::Error),
 }
 
@@ -19,9 +36,37 @@ impl std::fmt::Display for Error {
                 "detected inconsistency in {}, did you run a git command manually?",
                 i
             ),
-            Self::InvalidName => f.write_str("the received name is invalid"),
+            Self::InvalidName(e) => e.fmt(f),
             Self::NonUtf8 => f.write_str("the received name is not valid UTF-8"),
             Self::AlreadyExists(b) => write!(f, "{} already exists", b),
+            Self::NestedQueue(q, e) => write!(
+                f,
+                "queue `{}` conflicts with queue `{}`, a queue can't be in the namespace of another",
+                q, e
+            ),
+            Self::Locked(p) => write!(
+                f,
+                "another process is modifying the repository, if that is not the case, remove `{}`",
+                p.display()
+            ),
+            Self::Diverged(q) => write!(
+                f,
+                "the log of queue `{}` diverged from the remote one",
+                q
+            ),
+            Self::NotPublished(q) => write!(f, "queue `{}` is not published in the remote", q),
+            Self::BranchChanged(b) => write!(
+                f,
+                "branch `{}` was changed in the remote since it was last pushed",
+                b
+            ),
+            Self::Modified(r) => write!(f, "{} was modified concurrently, try again", r),
+            Self::Signing(e) => write!(f, "failed to sign commit: {}", e),
+            Self::ApplyFailed(p) => write!(f, "patch `{}` does not apply", p),
+            Self::Mail(e) => write!(f, "failed to send email: {}", e),
+            Self::Review(e) => write!(f, "review provider failed: {}", e),
+            Self::Config(e) => write!(f, "invalid configuration: {}", e),
+            Self::Spec(e) => e.fmt(f),
             Self::Git(g) => g.fmt(f),
         }
     }
@@ -42,3 +87,25 @@ impl From<git2::Error> for Error {
         Self::Git(err)
     }
 }
+
+impl From<NameError> for Error {
+    fn from(err: NameError) -> Self {
+        Self::InvalidName(err)
+    }
+}
+
+impl From<SpecError> for Error {
+    fn from(err: SpecError) -> Self {
+        Self::Spec(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Git(git2::Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::Os,
+            err.to_string(),
+        ))
+    }
+}
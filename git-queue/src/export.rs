@@ -0,0 +1,632 @@
+//! # Patch Export
+//!
+//! Render patches in the same mbox format used by `git format-patch`, so they can
+//! be applied with `git am` or sent to a mailing list.
+//!
+//! A patch series written to a directory contains one `NNNN-<subject>.patch` file
+//! per patch, plus a quilt-style `series` file listing the patch files in the
+//! order they must be applied.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use git2::{DiffFormat, DiffStatsFormat, Oid, Repository};
+
+use crate::error::Error;
+use crate::queue::Queue;
+use crate::rfc2822::{encode_header, format_date, mailbox};
+
+/// Maximum length of a generated patch file name, the same default of Git.
+const FILE_NAME_MAX: usize = 64;
+
+/// A patch rendered as an email message.
+pub struct FormattedPatch {
+    name: String,
+    oid: Oid,
+    file_name: String,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl FormattedPatch {
+    /// Name of the patch in the queue.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The commit of the patch.
+    pub fn id(&self) -> Oid {
+        self.oid
+    }
+
+    /// File name of the patch when written to disk.
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// The message headers, already encoded.
+    pub fn headers(&self) -> &[(&'static str, String)] {
+        &self.headers
+    }
+
+    /// The value of the first header with the given name, if any.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Add a header to the message, after the existing ones.
+    pub fn add_header(&mut self, name: &'static str, value: String) {
+        self.headers.push((name, value));
+    }
+
+    /// Set a header, replacing the first one with the same name, if any.
+    pub fn set_header(&mut self, name: &'static str, value: String) {
+        match self
+            .headers
+            .iter_mut()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        {
+            Some((_, old)) => *old = value,
+            None => self.headers.push((name, value)),
+        }
+    }
+
+    /// Send the message as the given mailbox.
+    ///
+    /// Like `git send-email`, if the sender isn't the author, the author is kept
+    /// in a `From:` line at the start of the body, which `git am` uses instead of
+    /// the header.
+    pub fn set_sender(&mut self, sender: &str) {
+        match self.header("From").map(str::to_string) {
+            Some(author) if author != sender => {
+                let mut body = format!("From: {}\n\n", author).into_bytes();
+                body.append(&mut self.body);
+                self.body = body;
+            }
+            Some(_) => return,
+            None => {}
+        }
+        self.set_header("From", sender.to_string());
+    }
+
+    /// The message body, i.e. the commit message followed by the diff.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Render the message in the mbox format, as `git format-patch` does.
+    pub fn to_mbox(&self) -> Vec<u8> {
+        let mut mbox = format!("From {} Mon Sep 17 00:00:00 2001\n", self.oid).into_bytes();
+        mbox.append(&mut self.to_message());
+        mbox
+    }
+
+    /// Render the message as sent by email, i.e. without the mbox `From` line.
+    pub fn to_message(&self) -> Vec<u8> {
+        let mut message = String::new();
+        for (name, value) in &self.headers {
+            let _ = writeln!(message, "{}: {}", name, value);
+        }
+        message.push('\n');
+
+        let mut message = message.into_bytes();
+        message.extend_from_slice(&self.body);
+        message
+    }
+}
+
+/// Format the given patches as a numbered patch series.
+///
+/// Each patch is diffed against its parent commit.
+pub fn format_patches<'a>(
+    repo: &Repository,
+    patches: impl IntoIterator<Item = (&'a str, Oid)>,
+) -> Result<Vec<FormattedPatch>, Error> {
+    let patches: Vec<_> = patches.into_iter().collect();
+    let total = patches.len();
+
+    let mut formatted = Vec::with_capacity(total);
+    for (idx, (name, oid)) in patches.into_iter().enumerate() {
+        formatted.push(format_patch(repo, name, oid, idx + 1, total)?);
+    }
+
+    Ok(formatted)
+}
+
+/// The diff of a commit against its first parent, or the empty tree if it is a
+/// root commit.
+pub fn commit_diff<'r>(
+    repo: &'r Repository,
+    commit: &git2::Commit<'_>,
+) -> Result<git2::Diff<'r>, Error> {
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?)
+}
+
+/// The cumulative diff of a series of patches, from the parent of the `first`
+/// patch, or the empty tree if it is a root commit, to the `last` one.
+pub fn series_diff(repo: &Repository, first: Oid, last: Oid) -> Result<git2::Diff<'_>, Error> {
+    let first = repo.find_commit(first)?;
+    let base_tree = match first.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => None,
+        Err(err) => return Err(err.into()),
+    };
+    let top_tree = repo.find_commit(last)?.tree()?;
+
+    Ok(repo.diff_tree_to_tree(base_tree.as_ref(), Some(&top_tree), None)?)
+}
+
+fn format_patch(
+    repo: &Repository,
+    name: &str,
+    oid: Oid,
+    number: usize,
+    total: usize,
+) -> Result<FormattedPatch, Error> {
+    let commit = repo.find_commit(oid)?;
+    let diff = commit_diff(repo, &commit)?;
+
+    let subject = commit.summary().ok_or(Error::NonUtf8)?;
+    let message = commit.message().ok_or(Error::NonUtf8)?;
+    // The subject is the first paragraph, everything else is the body.
+    let description = message
+        .split_once("\n\n")
+        .map(|(_, rest)| rest.trim_matches('\n'))
+        .unwrap_or_default();
+
+    let mut body = String::new();
+    if !description.is_empty() {
+        body.push_str(description);
+        body.push('\n');
+    }
+    body.push_str("---\n");
+    let stats = diff
+        .stats()?
+        .to_buf(DiffStatsFormat::FULL | DiffStatsFormat::INCLUDE_SUMMARY, 72)?;
+    body.push_str(stats.as_str().ok_or(Error::NonUtf8)?);
+    body.push('\n');
+
+    let mut body = body.into_bytes();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            body.push(line.origin() as u8);
+        }
+        body.extend_from_slice(line.content());
+        true
+    })?;
+    body.extend_from_slice(
+        concat!("-- \ngit-queue ", env!("CARGO_PKG_VERSION"), "\n\n").as_bytes(),
+    );
+
+    let author = commit.author();
+    let mut headers = vec![
+        (
+            "From",
+            mailbox(
+                author.name().unwrap_or_default(),
+                author.email().unwrap_or_default(),
+            ),
+        ),
+        ("Date", format_date(author.when())),
+        (
+            "Subject",
+            encode_header(&format!("{} {}", subject_prefix(number, total), subject)),
+        ),
+    ];
+    if !body.is_ascii() {
+        headers.push(("MIME-Version", "1.0".to_string()));
+        headers.push(("Content-Type", "text/plain; charset=UTF-8".to_string()));
+        headers.push(("Content-Transfer-Encoding", "8bit".to_string()));
+    }
+
+    Ok(FormattedPatch {
+        name: name.to_string(),
+        oid,
+        file_name: patch_file_name(number, subject),
+        headers,
+        body,
+    })
+}
+
+/// Create the cover letter of a formatted patch series, numbered `0/n`.
+///
+/// The body has the given description, followed by the subjects of the patches
+/// grouped by author and the diffstat of the whole series. The cover letter has
+/// no patch, so its name is empty and its OID is zero.
+pub fn cover_letter(
+    repo: &Repository,
+    author: &git2::Signature<'_>,
+    subject: &str,
+    description: &str,
+    patches: &[FormattedPatch],
+) -> Result<FormattedPatch, Error> {
+    let mut body = String::new();
+    if !description.is_empty() {
+        body.push_str(description.trim_end_matches('\n'));
+        body.push_str("\n\n");
+    }
+
+    let mut shortlog: Vec<(String, Vec<String>)> = Vec::new();
+    for patch in patches {
+        let commit = repo.find_commit(patch.id())?;
+        let name = commit.author().name().unwrap_or_default().to_string();
+        let summary = commit.summary().ok_or(Error::NonUtf8)?.to_string();
+        match shortlog.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, subjects)) => subjects.push(summary),
+            None => shortlog.push((name, vec![summary])),
+        }
+    }
+    for (name, subjects) in &shortlog {
+        let _ = writeln!(body, "{} ({}):", name, subjects.len());
+        for subject in subjects {
+            let _ = writeln!(body, "  {}", subject);
+        }
+        body.push('\n');
+    }
+
+    if let (Some(first), Some(last)) = (patches.first(), patches.last()) {
+        let diff = series_diff(repo, first.id(), last.id())?;
+        let stats = diff
+            .stats()?
+            .to_buf(DiffStatsFormat::FULL | DiffStatsFormat::INCLUDE_SUMMARY, 72)?;
+        body.push_str(stats.as_str().ok_or(Error::NonUtf8)?);
+        body.push('\n');
+    }
+    body.push_str(concat!(
+        "-- \ngit-queue ",
+        env!("CARGO_PKG_VERSION"),
+        "\n\n"
+    ));
+
+    let now = git2::Time::new(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64),
+        0,
+    );
+    let total = patches.len();
+    let prefix = format!(
+        "[PATCH {:0width$}/{}]",
+        0,
+        total,
+        width = total.to_string().len()
+    );
+    let mut headers = vec![
+        (
+            "From",
+            mailbox(
+                author.name().unwrap_or_default(),
+                author.email().unwrap_or_default(),
+            ),
+        ),
+        ("Date", format_date(now)),
+        ("Subject", encode_header(&format!("{} {}", prefix, subject))),
+    ];
+    if !body.is_ascii() {
+        headers.push(("MIME-Version", "1.0".to_string()));
+        headers.push(("Content-Type", "text/plain; charset=UTF-8".to_string()));
+        headers.push(("Content-Transfer-Encoding", "8bit".to_string()));
+    }
+
+    Ok(FormattedPatch {
+        name: String::new(),
+        oid: Oid::zero(),
+        file_name: "0000-cover-letter.patch".to_string(),
+        headers,
+        body: body.into_bytes(),
+    })
+}
+
+/// Create the cover letter of a patch series of the given queue.
+///
+/// The first line of the queue description is the subject and the rest is the
+/// body. Queues without description use their name as subject.
+pub fn queue_cover_letter(
+    queue: &Queue<'_>,
+    patches: &[FormattedPatch],
+) -> Result<FormattedPatch, Error> {
+    let (subject, description) = match queue.description() {
+        Some(description) => {
+            let (subject, body) = description.split_once('\n').unwrap_or((description, ""));
+            (subject.trim(), body.trim_matches('\n'))
+        }
+        None => (queue.name(), ""),
+    };
+    let ctx = queue.ctx();
+
+    cover_letter(ctx.repo(), ctx.user(), subject, description, patches)
+}
+
+/// Write the formatted patches and the `series` file to the given directory.
+///
+/// The directory is created if it doesn't exist. A cover letter is written along
+/// the patches, but isn't listed in the `series` file. Returns the paths of the
+/// written files.
+pub fn write_series(dir: &Path, patches: &[FormattedPatch]) -> Result<Vec<PathBuf>, Error> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut paths = Vec::with_capacity(patches.len());
+    let mut series = String::new();
+    for patch in patches {
+        let path = dir.join(patch.file_name());
+        std::fs::write(&path, patch.to_mbox())?;
+        paths.push(path);
+
+        if !patch.id().is_zero() {
+            series.push_str(patch.file_name());
+            series.push('\n');
+        }
+    }
+
+    std::fs::write(dir.join("series"), series)?;
+
+    Ok(paths)
+}
+
+/// File name of a patch, in the same format of `git format-patch`.
+///
+/// The subject is sanitized to contain only alphanumeric characters, `.` and `_`,
+/// with everything else replaced by a single `-`.
+pub fn patch_file_name(number: usize, subject: &str) -> String {
+    const SUFFIX: &str = ".patch";
+
+    let mut name = format!("{:04}-", number);
+    let max = FILE_NAME_MAX - SUFFIX.len();
+    let mut pending_dash = false;
+    for c in subject.chars() {
+        if name.len() + usize::from(pending_dash) >= max {
+            break;
+        }
+
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            if pending_dash {
+                name.push('-');
+                pending_dash = false;
+            }
+            name.push(c);
+        } else {
+            // Don't start the subject part with a dash.
+            pending_dash = !name.ends_with('-');
+        }
+    }
+
+    // Like Git, don't end the name with a dot, as it would look like `foo..patch`.
+    while name.ends_with('.') || (name.len() > "0000-".len() && name.ends_with('-')) {
+        name.pop();
+    }
+
+    name.push_str(SUFFIX);
+    name
+}
+
+/// The `[PATCH n/m]` prefix of the subject of the n-th patch in a series.
+fn subject_prefix(number: usize, total: usize) -> String {
+    if total == 1 {
+        "[PATCH]".to_string()
+    } else {
+        let width = total.to_string().len();
+        format!("[PATCH {:0width$}/{}]", number, total, width = width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ctx::Ctx,
+        queue::Queue,
+        testing::{add_patch_with_message, base_branch, clone_of, setup},
+    };
+
+    /// A queue with two applied patches.
+    fn queue(ctx: &Ctx) -> Queue<'_> {
+        let mut queue = Queue::initialize(ctx, "q", base_branch(ctx))
+            .unwrap()
+            .unwrap();
+        add_patch_with_message(&mut queue, "first", "Add the first file\n\nWith a body.");
+        add_patch_with_message(&mut queue, "second", "Add the second file");
+        queue
+    }
+
+    #[test]
+    fn file_names() {
+        assert_eq!(patch_file_name(1, "Add a file"), "0001-Add-a-file.patch");
+        assert_eq!(
+            patch_file_name(2, "[PATCH] fix: the  thing..."),
+            "0002-PATCH-fix-the-thing.patch"
+        );
+        assert_eq!(patch_file_name(3, "Ünïcode"), "0003-n-code.patch");
+        assert_eq!(patch_file_name(12, "v1.2"), "0012-v1.2.patch");
+
+        let long = patch_file_name(1, &"a".repeat(100));
+        assert_eq!(long.len(), FILE_NAME_MAX);
+        assert!(long.ends_with("aaa.patch"));
+    }
+
+    #[test]
+    fn format_series() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let queue = queue(&ctx);
+
+        let patches = format_patches(ctx.repo(), queue.state().applied()).unwrap();
+        let names: Vec<_> = patches.iter().map(|p| p.name()).collect();
+        assert_eq!(names, ["first", "second"]);
+        assert_eq!(patches[0].id(), queue.state().patch("first").unwrap());
+        assert_eq!(patches[0].file_name(), "0001-Add-the-first-file.patch");
+
+        let first = &patches[0];
+        assert_eq!(first.header("From"), Some("A <a@example.com>"));
+        assert_eq!(
+            first.header("Subject"),
+            Some("[PATCH 1/2] Add the first file")
+        );
+        assert!(first.header("Date").is_some());
+        assert_eq!(first.header("MIME-Version"), None);
+        let body = String::from_utf8(first.body().to_vec()).unwrap();
+        assert!(
+            body.starts_with("With a body.\n---\n first | 1 +\n"),
+            "{}",
+            body
+        );
+        assert!(
+            body.contains("\n+++ b/first\n@@ -0,0 +1 @@\n+first\n"),
+            "{}",
+            body
+        );
+
+        let second = String::from_utf8(patches[1].to_mbox()).unwrap();
+        let mbox_line = format!("From {} Mon Sep 17 00:00:00 2001\n", patches[1].id());
+        assert!(second.starts_with(&mbox_line), "{}", second);
+        assert!(second.contains("\nSubject: [PATCH 2/2] Add the second file\n\n---\n"));
+
+        // A single patch isn't numbered.
+        let single = format_patches(ctx.repo(), queue.state().applied().take(1)).unwrap();
+        assert_eq!(
+            single[0].header("Subject"),
+            Some("[PATCH] Add the first file")
+        );
+    }
+
+    #[test]
+    fn write_series_for_git_am() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let queue = queue(&ctx);
+        let patches = format_patches(ctx.repo(), queue.state().applied()).unwrap();
+
+        let out = dir.path().join("out");
+        let paths = write_series(&out, &patches).unwrap();
+        assert_eq!(
+            paths,
+            [
+                out.join("0001-Add-the-first-file.patch"),
+                out.join("0002-Add-the-second-file.patch"),
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(out.join("series")).unwrap(),
+            "0001-Add-the-first-file.patch\n0002-Add-the-second-file.patch\n"
+        );
+
+        // Git applies the series on the base, recreating the patches.
+        let git = |args: &[&str]| {
+            let output = std::process::Command::new("git")
+                .args(args)
+                .current_dir(ctx.repo().workdir().unwrap())
+                .output()
+                .unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        };
+        git(&["checkout", "-q", "main"]);
+        let mut am = vec!["am", "-q"];
+        am.extend(paths.iter().map(|p| p.to_str().unwrap()));
+        git(&am);
+
+        let repo = ctx.repo();
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let top = repo.find_commit(queue.state().head()).unwrap();
+        assert_eq!(head.tree_id(), top.tree_id());
+        assert_eq!(head.message(), Some("Add the second file\n"));
+        let first = head.parent(0).unwrap();
+        assert_eq!(
+            first.message(),
+            Some("Add the first file\n\nWith a body.\n")
+        );
+        assert_eq!(first.author().name(), Some("A"));
+    }
+
+    #[test]
+    fn queue_cover_letters() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = queue(&ctx);
+        let patches = format_patches(ctx.repo(), queue.state().applied()).unwrap();
+
+        // Without a description, the subject is the queue name.
+        let cover = queue_cover_letter(&queue, &patches).unwrap();
+        assert_eq!(cover.header("Subject"), Some("[PATCH 0/2] q"));
+        assert_eq!(cover.header("From"), Some("A <a@example.com>"));
+        assert_eq!(cover.file_name(), "0000-cover-letter.patch");
+        assert!(cover.id().is_zero());
+        let body = String::from_utf8(cover.body().to_vec()).unwrap();
+        assert!(
+            body.starts_with(
+                "A (2):\n  Add the first file\n  Add the second file\n\n \
+                 first  | 1 +\n second | 1 +\n 2 files changed, 2 insertions(+)\n"
+            ),
+            "{}",
+            body
+        );
+
+        queue
+            .describe(Some("Add files\n\nTwo of them.\n".to_string()))
+            .unwrap();
+        let cover = queue_cover_letter(&queue, &patches).unwrap();
+        assert_eq!(cover.header("Subject"), Some("[PATCH 0/2] Add files"));
+        let body = String::from_utf8(cover.body().to_vec()).unwrap();
+        assert!(body.starts_with("Two of them.\n\nA (2):\n"), "{}", body);
+
+        // The cover letter is written, but isn't part of the series.
+        let out = dir.path().join("out");
+        let mut series = vec![cover];
+        series.extend(patches);
+        let paths = write_series(&out, &series).unwrap();
+        assert_eq!(paths[0], out.join("0000-cover-letter.patch"));
+        let listed = std::fs::read_to_string(out.join("series")).unwrap();
+        assert!(!listed.contains("cover-letter"));
+    }
+
+    #[test]
+    fn patch_and_series_diffs() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let queue = queue(&ctx);
+        let repo = ctx.repo();
+        let first = queue.state().patch("first").unwrap();
+        let second = queue.state().patch("second").unwrap();
+        let files = |diff: &git2::Diff<'_>| -> Vec<String> {
+            diff.deltas()
+                .map(|d| d.new_file().path().unwrap().display().to_string())
+                .collect()
+        };
+
+        // Each patch against its parent.
+        let diff = commit_diff(repo, &repo.find_commit(second).unwrap()).unwrap();
+        assert_eq!(files(&diff), ["second"]);
+        // A root commit against the empty tree.
+        let base = repo.find_commit(queue.state().base()).unwrap();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder
+            .insert("root", repo.blob(b"root").unwrap(), 0o100644)
+            .unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let root = repo
+            .commit(None, &base.author(), &base.author(), "root", &tree, &[])
+            .unwrap();
+        let diff = commit_diff(repo, &repo.find_commit(root).unwrap()).unwrap();
+        assert_eq!(files(&diff), ["root"]);
+
+        // The cumulative diff of a range of patches.
+        let diff = series_diff(repo, first, second).unwrap();
+        assert_eq!(files(&diff), ["first", "second"]);
+        assert_eq!(diff.stats().unwrap().insertions(), 2);
+        let diff = series_diff(repo, second, second).unwrap();
+        assert_eq!(files(&diff), ["second"]);
+        let diff = series_diff(repo, root, root).unwrap();
+        assert_eq!(files(&diff), ["root"]);
+    }
+}
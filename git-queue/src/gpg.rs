@@ -1,37 +1,588 @@
-use std::process::Command;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::error::Error;
+
+/// Signature formats supported by Git, selected by `gpg.format`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum GpgFormat {
+    OpenPgp,
+    X509,
+    Ssh,
+}
+
+impl GpgFormat {
+    const ALL: [Self; 3] = [Self::OpenPgp, Self::X509, Self::Ssh];
+
+    fn from_config(config: &git2::Config) -> Self {
+        match config.get_string("gpg.format").as_deref() {
+            Ok("x509") => Self::X509,
+            Ok("ssh") => Self::Ssh,
+            _ => Self::OpenPgp,
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::OpenPgp => "openpgp",
+            Self::X509 => "x509",
+            Self::Ssh => "ssh",
+        }
+    }
+
+    /// Detect the format of an armored signature.
+    fn of_signature(signature: &[u8]) -> Option<Self> {
+        if signature.starts_with(b"-----BEGIN PGP SIGNATURE-----") {
+            Some(Self::OpenPgp)
+        } else if signature.starts_with(b"-----BEGIN SIGNED MESSAGE-----") {
+            Some(Self::X509)
+        } else if signature.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+            Some(Self::Ssh)
+        } else {
+            None
+        }
+    }
+
+    const fn index(self) -> usize {
+        self as usize
+    }
+
+    const fn default_program(self) -> &'static str {
+        match self {
+            Self::OpenPgp => "gpg",
+            Self::X509 => "gpgsm",
+            Self::Ssh => "ssh-keygen",
+        }
+    }
+}
+
+/// The result of verifying a commit signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    /// The commit has no signature.
+    Unsigned,
+    /// Good signature, made by the given signer.
+    Good(String),
+    /// Bad, expired or revoked signature, made by the given signer.
+    Bad(String),
+    /// The signature could not be checked, e.g. the key isn't known.
+    Unknown,
+}
+
+impl std::fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsigned => f.write_str("unsigned"),
+            Self::Good(signer) => write!(f, "good signature from {}", signer),
+            Self::Bad(signer) if signer.is_empty() => f.write_str("bad signature"),
+            Self::Bad(signer) => write!(f, "bad signature from {}", signer),
+            Self::Unknown => f.write_str("unknown signature"),
+        }
+    }
+}
 
 pub(crate) struct GitGpg {
-    program: String,
+    format: GpgFormat,
+    programs: [String; 3],
     signkey: Option<String>,
+    sign_commits: bool,
+    allowed_signers: Option<PathBuf>,
 }
 
 impl GitGpg {
-    pub fn from_config(config: &git2::Config) -> Self {
-        // TODO: try to discover the key to use.
-        let signkey = config.get_string("user.signingkey").ok();
-
-        let program = match config.get_str("gpg.format") {
-            Ok("opengpg") => "gpg".to_string(),
-            Ok("x509") => "gpgsm".to_string(),
-            // Prioritize x509 format.
+    pub fn from_config(config: &git2::Config, user: &git2::Signature<'_>) -> Self {
+        let format = GpgFormat::from_config(config);
+
+        // We need the programs of all formats, as the commits we verify could be
+        // signed in any of them.
+        let programs = GpgFormat::ALL.map(|f| {
+            // `gpg.program` is the old name of `gpg.openpgp.program`.
             // Source:
             // https://github.com/git/git/blob/75ae10bc75336db031ee58d13c5037b929235912/gpg-interface.c#L422
-            _ => config
-                .get_string("gpg.x509.program")
-                .or_else(|_| config.get_string("gpg.opengpg.program"))
-                .or_else(|_| config.get_string("gpg.program"))
-                .unwrap_or_else(|_| "gpg".to_string()),
+            let mut program = config.get_string(&format!("gpg.{}.program", f.name())).ok();
+            if program.is_none() && f == GpgFormat::OpenPgp {
+                program = config.get_string("gpg.program").ok();
+            }
+            program.unwrap_or_else(|| f.default_program().to_string())
+        });
+
+        // Like Git, use the committer identity if no key was configured. SSH has
+        // no such fallback, as it needs the key file.
+        let signkey = config.get_string("user.signingkey").ok().or_else(|| {
+            if format == GpgFormat::Ssh {
+                return None;
+            }
+
+            match (user.name(), user.email()) {
+                (Some(name), Some(email)) => Some(format!("{} <{}>", name, email)),
+                _ => None,
+            }
+        });
+
+        let sign_commits = config.get_bool("commit.gpgsign").unwrap_or(false);
+        let allowed_signers = config
+            .get_string("gpg.ssh.allowedSignersFile")
+            .ok()
+            .map(|p| expand_home(&p));
+
+        Self {
+            format,
+            programs,
+            signkey,
+            sign_commits,
+            allowed_signers,
+        }
+    }
+
+    fn program(&self, format: GpgFormat) -> &str {
+        &self.programs[format.index()]
+    }
+
+    /// Should new commits be signed?
+    pub fn sign_commits(&self) -> bool {
+        self.sign_commits
+    }
+
+    /// Create a detached armored signature for the given buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Signing`] if no key is configured or the signing program
+    /// fails.
+    pub fn sign_buffer(&self, buffer: &[u8]) -> Result<String, Error> {
+        let key = self
+            .signkey
+            .as_deref()
+            .ok_or_else(|| Error::Signing("no key configured in `user.signingkey`".to_string()))?;
+
+        match self.format {
+            GpgFormat::OpenPgp | GpgFormat::X509 => self.gpg_sign(buffer, key),
+            GpgFormat::Ssh => self.ssh_sign(buffer, key),
+        }
+    }
+
+    fn gpg_sign(&self, buffer: &[u8], key: &str) -> Result<String, Error> {
+        let program = self.program(self.format);
+        let output = run_with_input(
+            Command::new(program).args(["--status-fd=2", "-bsau", key]),
+            buffer,
+        )
+        .map_err(|err| spawn_error(program, err))?;
+
+        // The exit status isn't enough, GPG may exit successfully without creating
+        // the signature. Git does the same check.
+        let status = String::from_utf8_lossy(&output.stderr);
+        let created = status
+            .lines()
+            .any(|l| l.starts_with("[GNUPG:] SIG_CREATED "));
+        if !output.status.success() || !created {
+            return Err(Error::Signing(format!(
+                "`{}` failed to sign the data: {}",
+                program,
+                status.trim()
+            )));
+        }
+
+        String::from_utf8(output.stdout)
+            .map_err(|_| Error::Signing("the signature is not valid UTF-8".to_string()))
+    }
+
+    fn ssh_sign(&self, buffer: &[u8], key: &str) -> Result<String, Error> {
+        let mut buffer_file = tempfile::NamedTempFile::new()?;
+        buffer_file.write_all(buffer)?;
+        buffer_file.flush()?;
+
+        // A literal public key must be written to a file, the private key is then
+        // looked up in the SSH agent.
+        let literal_key = key
+            .strip_prefix("key::")
+            .or_else(|| key.starts_with("ssh-").then_some(key));
+        let mut key_file = None;
+        let key_path = if let Some(literal) = literal_key {
+            let mut file = tempfile::NamedTempFile::new()?;
+            writeln!(file, "{}", literal)?;
+            let path = file.path().to_path_buf();
+            key_file = Some(file);
+            path
+        } else {
+            expand_home(key)
+        };
+
+        let program = self.program(GpgFormat::Ssh);
+        let mut cmd = Command::new(program);
+        cmd.args(["-Y", "sign", "-n", "git", "-f"]).arg(&key_path);
+        if key_file.is_some() {
+            cmd.arg("-U");
+        }
+        let output = cmd
+            .arg(buffer_file.path())
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|err| spawn_error(program, err))?;
+
+        if !output.status.success() {
+            return Err(Error::Signing(format!(
+                "`{}` failed to sign the data: {}",
+                program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        // ssh-keygen writes the signature next to the signed file.
+        let mut sig_path = OsString::from(buffer_file.path());
+        sig_path.push(".sig");
+        let signature = std::fs::read_to_string(&sig_path);
+        let _ = std::fs::remove_file(&sig_path);
+
+        Ok(signature?)
+    }
+
+    /// Verify a signature of the given data.
+    ///
+    /// The program used is chosen by the signature format, not by `gpg.format`.
+    /// If the verification program cannot be executed, the signature is reported
+    /// as [`SignatureStatus::Unknown`].
+    pub fn verify(&self, signature: &[u8], data: &[u8]) -> Result<SignatureStatus, Error> {
+        let format = match GpgFormat::of_signature(signature) {
+            Some(format) => format,
+            None => return Ok(SignatureStatus::Unknown),
+        };
+
+        let mut sig_file = tempfile::NamedTempFile::new()?;
+        sig_file.write_all(signature)?;
+        sig_file.flush()?;
+
+        let res = match format {
+            GpgFormat::OpenPgp | GpgFormat::X509 => self.gpg_verify(format, sig_file.path(), data),
+            GpgFormat::Ssh => self.ssh_verify(sig_file.path(), data),
+        };
+
+        match res {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                tracing::warn!(
+                    "`{}` not found, cannot verify signature",
+                    self.program(format)
+                );
+                Ok(SignatureStatus::Unknown)
+            }
+            res => Ok(res?),
+        }
+    }
+
+    fn gpg_verify(
+        &self,
+        format: GpgFormat,
+        sig_path: &Path,
+        data: &[u8],
+    ) -> std::io::Result<SignatureStatus> {
+        let output = run_with_input(
+            Command::new(self.program(format))
+                .args(["--status-fd=1", "--verify"])
+                .arg(sig_path)
+                .arg("-"),
+            data,
+        )?;
+
+        // Source: https://github.com/gpg/gnupg/blob/master/doc/DETAILS#general-status-codes
+        let status = String::from_utf8_lossy(&output.stdout);
+        for line in status.lines() {
+            let mut fields = match line.strip_prefix("[GNUPG:] ") {
+                Some(rest) => rest.splitn(3, ' '),
+                None => continue,
+            };
+            let (code, signer) = (fields.next(), fields.nth(1).unwrap_or_default());
+            match code {
+                Some("GOODSIG") => return Ok(SignatureStatus::Good(signer.to_string())),
+                Some("BADSIG" | "EXPSIG" | "EXPKEYSIG" | "REVKEYSIG") => {
+                    return Ok(SignatureStatus::Bad(signer.to_string()))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(SignatureStatus::Unknown)
+    }
+
+    fn ssh_verify(&self, sig_path: &Path, data: &[u8]) -> std::io::Result<SignatureStatus> {
+        let program = self.program(GpgFormat::Ssh);
+
+        if let Some(allowed) = &self.allowed_signers {
+            let output = Command::new(program)
+                .args(["-Y", "find-principals", "-f"])
+                .arg(allowed)
+                .arg("-s")
+                .arg(sig_path)
+                .stdin(Stdio::null())
+                .output()?;
+
+            let principals = String::from_utf8_lossy(&output.stdout);
+            if let Some(principal) = principals
+                .lines()
+                .next()
+                .filter(|_| output.status.success())
+            {
+                let output = run_with_input(
+                    Command::new(program)
+                        .args(["-Y", "verify", "-n", "git", "-f"])
+                        .arg(allowed)
+                        .args(["-I", principal, "-s"])
+                        .arg(sig_path),
+                    data,
+                )?;
+
+                return Ok(if output.status.success() {
+                    SignatureStatus::Good(principal.to_string())
+                } else {
+                    SignatureStatus::Bad(principal.to_string())
+                });
+            }
+        }
+
+        // Without a known principal, we can only check that the signature matches
+        // the data.
+        let output = run_with_input(
+            Command::new(program)
+                .args(["-Y", "check-novalidate", "-n", "git", "-s"])
+                .arg(sig_path),
+            data,
+        )?;
+
+        Ok(if output.status.success() {
+            SignatureStatus::Unknown
+        } else {
+            SignatureStatus::Bad(String::new())
+        })
+    }
+}
+
+fn run_with_input(cmd: &mut Command, input: &[u8]) -> std::io::Result<std::process::Output> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("child stdin is piped");
+    // The input is written by another thread while the output is read, as the
+    // program may fill the output pipes before reading all of it.
+    std::thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(input));
+        let output = child.wait_with_output()?;
+        match writer.join().expect("writing the input doesn't panic") {
+            // The program exited without reading everything, its status tells why.
+            Err(err) if err.kind() != std::io::ErrorKind::BrokenPipe => Err(err),
+            _ => Ok(output),
+        }
+    })
+}
+
+fn spawn_error(program: &str, err: std::io::Error) -> Error {
+    Error::Signing(format!("failed to run `{}`: {}", program, err))
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn user() -> git2::Signature<'static> {
+        git2::Signature::now("A", "a@example.com").unwrap()
+    }
+
+    /// A configuration file in `dir` with the given values.
+    fn config(dir: &TempDir, values: &[(&str, &str)]) -> git2::Config {
+        let mut config = git2::Config::open(&dir.path().join("config")).unwrap();
+        for (key, value) in values {
+            config.set_str(key, value).unwrap();
+        }
+        config
+    }
+
+    /// Generate an SSH key in `dir`, returning the path of the private key.
+    fn ssh_key(dir: &TempDir) -> PathBuf {
+        let key = dir.path().join("key");
+        let status = Command::new("ssh-keygen")
+            .args(["-q", "-t", "ed25519", "-N", "", "-C", "a@example.com", "-f"])
+            .arg(&key)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        key
+    }
+
+    /// An executable shell script in `dir`, to stand in for a signing program.
+    fn script(dir: &TempDir, name: &str, body: &str) -> String {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.path().join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn sign_with_ssh_key() {
+        let dir = TempDir::new().unwrap();
+        let key = ssh_key(&dir);
+        let gpg = GitGpg::from_config(
+            &config(
+                &dir,
+                &[
+                    ("gpg.format", "ssh"),
+                    ("user.signingKey", key.to_str().unwrap()),
+                    ("commit.gpgSign", "true"),
+                ],
+            ),
+            &user(),
+        );
+        assert!(gpg.sign_commits());
+
+        let signature = gpg.sign_buffer(b"data").unwrap();
+        assert!(signature.starts_with("-----BEGIN SSH SIGNATURE-----"));
+        let sig_path = dir.path().join("data.sig");
+        std::fs::write(&sig_path, &signature).unwrap();
+        let output = run_with_input(
+            Command::new("ssh-keygen")
+                .args(["-Y", "check-novalidate", "-n", "git", "-s"])
+                .arg(&sig_path),
+            b"data",
+        )
+        .unwrap();
+        assert!(output.status.success());
+    }
+
+    #[test]
+    fn ssh_needs_a_key() {
+        let dir = TempDir::new().unwrap();
+        let gpg = GitGpg::from_config(&config(&dir, &[("gpg.format", "ssh")]), &user());
+        assert!(!gpg.sign_commits());
+        assert!(matches!(gpg.sign_buffer(b"data"), Err(Error::Signing(_))));
+    }
+
+    #[test]
+    fn sign_with_gpg() {
+        let dir = TempDir::new().unwrap();
+        let args = dir.path().join("args");
+        let gpg = script(
+            &dir,
+            "gpg",
+            &format!(
+                "echo \"$@\" > {}\ncat > /dev/null\n\
+                 echo '[GNUPG:] SIG_CREATED D 22 8 00 1 FPR' >&2\n\
+                 echo '-----BEGIN PGP SIGNATURE-----'",
+                args.display()
+            ),
+        );
+        let signer = |program: &str| {
+            GitGpg::from_config(&config(&dir, &[("gpg.openpgp.program", program)]), &user())
         };
 
-        Self { program, signkey }
+        // The committer identity is the default key.
+        let signature = signer(&gpg).sign_buffer(b"data").unwrap();
+        assert_eq!(signature, "-----BEGIN PGP SIGNATURE-----\n");
+        assert_eq!(
+            std::fs::read_to_string(&args).unwrap(),
+            "--status-fd=2 -bsau A <a@example.com>\n"
+        );
+
+        // A successful exit isn't enough, the signature must be created.
+        let silent = script(&dir, "silent", "cat > /dev/null");
+        assert!(matches!(
+            signer(&silent).sign_buffer(b"data"),
+            Err(Error::Signing(_))
+        ));
+        assert!(matches!(
+            signer("/nonexistent/gpg").sign_buffer(b"data"),
+            Err(Error::Signing(_))
+        ));
+    }
+
+    #[test]
+    fn verify_ssh_signatures() {
+        let dir = TempDir::new().unwrap();
+        let key = ssh_key(&dir);
+        let key = key.to_str().unwrap();
+        let public = std::fs::read_to_string(format!("{}.pub", key)).unwrap();
+        let allowed = dir.path().join("allowed_signers");
+        std::fs::write(&allowed, format!("a@example.com {}", public)).unwrap();
+
+        let signer = GitGpg::from_config(
+            &config(&dir, &[("gpg.format", "ssh"), ("user.signingKey", key)]),
+            &user(),
+        );
+        let signature = signer.sign_buffer(b"data").unwrap();
+
+        let gpg = GitGpg::from_config(
+            &config(
+                &dir,
+                &[("gpg.ssh.allowedSignersFile", allowed.to_str().unwrap())],
+            ),
+            &user(),
+        );
+        let good = SignatureStatus::Good("a@example.com".to_string());
+        let bad = SignatureStatus::Bad("a@example.com".to_string());
+        assert_eq!(gpg.verify(signature.as_bytes(), b"data").unwrap(), good);
+        assert_eq!(gpg.verify(signature.as_bytes(), b"other").unwrap(), bad);
+
+        // Without allowed signers only the data is checked, the signer is unknown.
+        let dir = TempDir::new().unwrap();
+        let gpg = GitGpg::from_config(&config(&dir, &[]), &user());
+        let status = gpg.verify(signature.as_bytes(), b"data").unwrap();
+        assert_eq!(status, SignatureStatus::Unknown);
+        let status = gpg.verify(signature.as_bytes(), b"other").unwrap();
+        assert_eq!(status, SignatureStatus::Bad(String::new()));
     }
 
-    pub fn sign_buffer(&self, buffer: &[u8]) {
-        let key = self.signkey.as_deref().unwrap();
-        let cmd = Command::new(&self.program).args([
-            "--status-fdd=2",
-            "-bsau",
-            &self.signkey.as_deref().unwrap(),
-        ]);
+    #[test]
+    fn verify_gpg_signatures() {
+        let dir = TempDir::new().unwrap();
+        let signature = b"-----BEGIN PGP SIGNATURE-----\n";
+        let verify = |status: &str| {
+            let body = format!("cat > /dev/null\necho '{}'", status);
+            let program = script(&dir, "gpg", &body);
+            let gpg = GitGpg::from_config(&config(&dir, &[("gpg.program", &program)]), &user());
+            gpg.verify(signature, b"data").unwrap()
+        };
+
+        assert_eq!(
+            verify("[GNUPG:] GOODSIG 0123456789ABCDEF A <a@example.com>"),
+            SignatureStatus::Good("A <a@example.com>".to_string())
+        );
+        assert_eq!(
+            verify("[GNUPG:] EXPKEYSIG 0123456789ABCDEF A <a@example.com>"),
+            SignatureStatus::Bad("A <a@example.com>".to_string())
+        );
+        assert_eq!(verify("[GNUPG:] ERRSIG"), SignatureStatus::Unknown);
+
+        // Missing programs and unrecognized signatures can't be verified.
+        let gpg = GitGpg::from_config(
+            &config(&dir, &[("gpg.program", "/nonexistent/gpg")]),
+            &user(),
+        );
+        let status = gpg.verify(signature, b"data").unwrap();
+        assert_eq!(status, SignatureStatus::Unknown);
+        let status = gpg.verify(b"not a signature", b"data").unwrap();
+        assert_eq!(status, SignatureStatus::Unknown);
+    }
+
+    #[test]
+    fn run_with_large_input_and_output() {
+        // Fills stderr before reading stdin, like a signer with a lot of status.
+        let script = "head -c 1000000 /dev/zero >&2; wc -c";
+        let input = vec![b'x'; 1_000_000];
+        let output = run_with_input(Command::new("sh").args(["-c", script]), &input).unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stderr.len(), 1_000_000);
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "1000000");
     }
 }
@@ -0,0 +1,831 @@
+//! # Patch Import
+//!
+//! Parse patches in the mbox format, as written by `git format-patch` or sent to
+//! mailing lists, and plain quilt patches, and import them as new patches on top
+//! of a queue.
+//!
+//! The patches are applied one by one. When one of them fails to apply, the import
+//! stops and the patches not yet imported, starting with the failed one, are saved
+//! in `<git dir>/qg/import`, one mbox file per patch. After the user manually
+//! applies the failed patch in the index, the import can be resumed with
+//! [`resume`], which creates the patch from the index and continues with the
+//! remaining ones.
+
+use std::path::{Path, PathBuf};
+
+use git2::{Diff, Signature};
+
+use crate::{
+    ctx::Ctx,
+    error::Error,
+    queue::Queue,
+    rfc2822::{decode_header, encode_header, format_date, mailbox, parse_date, parse_mailbox},
+};
+
+/// A patch parsed from an email message or patch file.
+pub struct MailPatch {
+    author: Option<(String, String)>,
+    date: Option<git2::Time>,
+    subject: String,
+    body: String,
+    diff: Vec<u8>,
+}
+
+impl MailPatch {
+    /// The subject of the patch, without `[PATCH]` prefixes.
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// The commit message of the patch.
+    pub fn message(&self) -> String {
+        if self.body.is_empty() {
+            format!("{}\n", self.subject)
+        } else {
+            format!("{}\n\n{}\n", self.subject, self.body)
+        }
+    }
+
+    /// The patch author, or the current user if the patch has no author.
+    pub fn author(&self, ctx: &Ctx) -> Result<Signature<'static>, Error> {
+        let (name, email) = match &self.author {
+            Some(author) => author,
+            None => return Ok(ctx.user().clone()),
+        };
+
+        let signature = match self.date {
+            Some(date) => Signature::new(name, email, &date)?,
+            None => Signature::now(name, email)?,
+        };
+        Ok(signature)
+    }
+
+    /// Render this patch as a mbox message.
+    pub fn to_mbox(&self) -> Vec<u8> {
+        let mut mbox = String::from(
+            "From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001\n",
+        );
+        if let Some((name, email)) = &self.author {
+            mbox.push_str(&format!("From: {}\n", mailbox(name, email)));
+        }
+        if let Some(date) = self.date {
+            mbox.push_str(&format!("Date: {}\n", format_date(date)));
+        }
+        mbox.push_str(&format!("Subject: {}\n\n", encode_header(&self.subject)));
+        if !self.body.is_empty() {
+            mbox.push_str(&self.body);
+            mbox.push('\n');
+        }
+        mbox.push_str("---\n");
+
+        let mut mbox = mbox.into_bytes();
+        mbox.extend_from_slice(&self.diff);
+        mbox
+    }
+
+    fn apply(&self, queue: &mut Queue<'_>, name: &str) -> Result<(), Error> {
+        let ctx = queue.ctx();
+        let repo = ctx.repo();
+
+        let diff = Diff::from_buffer(&normalize_diff(&self.diff))
+            .map_err(|_| Error::ApplyFailed(self.subject.clone()))?;
+        let head = repo.find_commit(queue.state().head())?;
+        let mut index = repo
+            .apply_to_tree(&head.tree()?, &diff, None)
+            .map_err(|_| Error::ApplyFailed(self.subject.clone()))?;
+        let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+
+        queue.new_patch(name, &self.author(ctx)?, &self.message(), &tree)?;
+        Ok(())
+    }
+}
+
+/// Read the patches in a file.
+///
+/// The file can be a mbox with one or more messages, a single email message or a
+/// plain patch without headers. In the latter case, the file name is used as the
+/// subject if the patch has no description.
+pub fn read_file(path: &Path) -> Result<Vec<MailPatch>, Error> {
+    let data = std::fs::read(path)?;
+    let fallback = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok(split_mbox(&data)
+        .into_iter()
+        .map(|message| parse_message(message, &fallback))
+        .collect())
+}
+
+/// Read the patch files listed in a quilt `series` file.
+///
+/// Paths are relative to the directory of the series file, comments and
+/// options after the file name (e.g. `-p1`) are ignored.
+pub fn read_series(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let content = std::fs::read_to_string(path)?;
+
+    Ok(content
+        .lines()
+        .map(|l| l.split('#').next().unwrap_or_default())
+        .filter_map(|l| l.split_whitespace().next())
+        .map(|file| dir.join(file))
+        .collect())
+}
+
+/// Read the patch files in a directory.
+///
+/// If the directory contains a `series` file, it defines the patches and their
+/// order, otherwise all `.patch` and `.diff` files are used, sorted by name.
+pub fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let series = dir.join("series");
+    if series.is_file() {
+        return read_series(&series);
+    }
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_patch = path
+            .extension()
+            .is_some_and(|ext| ext == "patch" || ext == "diff");
+        if is_patch && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Import the given patches on top of the queue.
+///
+/// Returns the names of the created patches. If a patch fails to apply, the
+/// remaining patches are saved and [`Error::ApplyFailed`] is returned, the
+/// import can then be resumed with [`resume`] or [`skip`].
+///
+/// # Errors
+///
+/// Returns [`Error::AlreadyExists`] if there is an import in progress.
+pub fn import(queue: &mut Queue<'_>, patches: Vec<MailPatch>) -> Result<Vec<String>, Error> {
+    if in_progress(queue.ctx()) {
+        return Err(Error::AlreadyExists("import in progress"));
+    }
+
+    apply_all(queue, patches)
+}
+
+/// Is there an import in progress?
+pub fn in_progress(ctx: &Ctx) -> bool {
+    state_dir(ctx).is_dir()
+}
+
+/// The queue of the import in progress, if any.
+pub fn pending_queue(ctx: &Ctx) -> Result<Option<String>, Error> {
+    if !in_progress(ctx) {
+        return Ok(None);
+    }
+
+    let queue = std::fs::read_to_string(state_dir(ctx).join("queue"))?;
+    Ok(Some(queue.trim().to_string()))
+}
+
+/// Path of the saved patch that failed to apply, if any.
+pub fn failed_patch_path(ctx: &Ctx) -> Result<Option<PathBuf>, Error> {
+    Ok(saved_patches(ctx)?.into_iter().next())
+}
+
+/// Resume the import in progress.
+///
+/// The patch that failed to apply is created from the current index, which must
+/// not have conflicts, then the remaining patches are applied.
+pub fn resume(queue: &mut Queue<'_>) -> Result<Vec<String>, Error> {
+    let mut patches = load(queue.ctx())?.into_iter();
+    let failed = match patches.next() {
+        Some(failed) => failed,
+        None => return Ok(vec![]),
+    };
+
+    let ctx = queue.ctx();
+    let repo = ctx.repo();
+    let mut index = repo.index()?;
+    if index.has_conflicts() {
+        return Err(Error::ApplyFailed(failed.subject.clone()));
+    }
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let name = queue.unique_patch_name(failed.subject(), &[])?;
+    queue.new_patch(&name, &failed.author(ctx)?, &failed.message(), &tree)?;
+    clear(queue.ctx())?;
+
+    let mut names = vec![name];
+    names.extend(apply_all(queue, patches.collect())?);
+    Ok(names)
+}
+
+/// Skip the patch that failed to apply and resume the import.
+pub fn skip(queue: &mut Queue<'_>) -> Result<Vec<String>, Error> {
+    let patches = load(queue.ctx())?.into_iter().skip(1).collect();
+    clear(queue.ctx())?;
+
+    apply_all(queue, patches)
+}
+
+/// Discard the import in progress.
+///
+/// Patches already imported are kept in the queue.
+pub fn abort(ctx: &Ctx) -> Result<(), Error> {
+    clear(ctx)
+}
+
+fn apply_all(queue: &mut Queue<'_>, patches: Vec<MailPatch>) -> Result<Vec<String>, Error> {
+    let mut names: Vec<String> = Vec::with_capacity(patches.len());
+    for (idx, patch) in patches.iter().enumerate() {
+        let name = queue.unique_patch_name(patch.subject(), &names)?;
+        match patch.apply(queue, &name) {
+            Ok(()) => names.push(name),
+            Err(err @ Error::ApplyFailed(_)) => {
+                save(queue, &patches[idx..])?;
+                return Err(err);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(names)
+}
+
+fn state_dir(ctx: &Ctx) -> PathBuf {
+    ctx.repo().path().join("qg").join("import")
+}
+
+fn saved_patches(ctx: &Ctx) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(state_dir(ctx))? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|n| n != "queue") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+fn save(queue: &Queue<'_>, patches: &[MailPatch]) -> Result<(), Error> {
+    let dir = state_dir(queue.ctx());
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join("queue"), queue.name())?;
+
+    for (idx, patch) in patches.iter().enumerate() {
+        std::fs::write(dir.join(format!("{:04}", idx + 1)), patch.to_mbox())?;
+    }
+
+    Ok(())
+}
+
+fn load(ctx: &Ctx) -> Result<Vec<MailPatch>, Error> {
+    let mut patches = Vec::new();
+    for path in saved_patches(ctx)? {
+        patches.extend(read_file(&path)?);
+    }
+
+    Ok(patches)
+}
+
+fn clear(ctx: &Ctx) -> Result<(), Error> {
+    match std::fs::remove_dir_all(state_dir(ctx)) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Split a mbox in its messages.
+///
+/// If the data doesn't start with a mbox separator, it's a single message.
+fn split_mbox(data: &[u8]) -> Vec<&[u8]> {
+    if !data.starts_with(b"From ") {
+        return vec![data];
+    }
+
+    let mut messages = Vec::new();
+    let mut start = 0;
+    let mut prev_blank = true;
+    let mut pos = 0;
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        if prev_blank && line.starts_with(b"From ") {
+            if pos > start {
+                messages.push(&data[start..pos]);
+            }
+            // The message starts after the separator line.
+            start = pos + line.len();
+        }
+        prev_blank = line == b"\n" || line == b"\r\n";
+        pos += line.len();
+    }
+    messages.push(&data[start..]);
+
+    messages
+}
+
+fn parse_message(data: &[u8], fallback_subject: &str) -> MailPatch {
+    let diff_start = find_diff_start(data);
+    let (text, diff) = data.split_at(diff_start);
+    let text = String::from_utf8_lossy(text);
+
+    let mut author = None;
+    let mut date = None;
+    let mut subject = None;
+
+    let mut lines = text.lines().peekable();
+    if has_headers(&text) {
+        for (name, value) in parse_headers(&mut lines) {
+            match name.to_ascii_lowercase().as_str() {
+                "from" => author = Some(parse_mailbox(&value)),
+                "date" => date = parse_date(&value),
+                "subject" => subject = Some(strip_subject_prefix(&decode_header(&value))),
+                _ => {}
+            }
+        }
+    }
+
+    // In-body headers override the message ones, they are used when the sender
+    // isn't the author of the patch.
+    while lines.peek().is_some_and(|l| l.trim().is_empty()) {
+        lines.next();
+    }
+    if lines.peek().is_some_and(|l| is_in_body_header(l)) {
+        for (name, value) in parse_headers(&mut lines) {
+            match name.to_ascii_lowercase().as_str() {
+                "from" => author = Some(parse_mailbox(&value)),
+                "date" => date = parse_date(&value),
+                "subject" => subject = Some(strip_subject_prefix(&value)),
+                _ => {}
+            }
+        }
+    }
+
+    // The description ends at the `---` separator before the diffstat.
+    let mut description: Vec<_> = lines
+        .take_while(|l| l.trim_end() != "---")
+        .map(str::trim_end)
+        .collect();
+    while description.first().is_some_and(|l| l.is_empty()) {
+        description.remove(0);
+    }
+    while description.last().is_some_and(|l| l.is_empty()) {
+        description.pop();
+    }
+
+    let subject = match subject {
+        Some(subject) => subject,
+        // Plain patches use the first paragraph of the description as subject.
+        None if !description.is_empty() => {
+            let end = description
+                .iter()
+                .position(|l| l.is_empty())
+                .unwrap_or(description.len());
+            let subject = description[..end].join(" ");
+            description.drain(..end);
+            while description.first().is_some_and(|l| l.is_empty()) {
+                description.remove(0);
+            }
+            subject
+        }
+        None => fallback_subject.to_string(),
+    };
+
+    MailPatch {
+        author,
+        date,
+        subject,
+        body: description.join("\n"),
+        diff: strip_signature(diff).to_vec(),
+    }
+}
+
+fn parse_headers<'a>(
+    lines: &mut std::iter::Peekable<impl Iterator<Item = &'a str>>,
+) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines.by_ref() {
+        if line.trim().is_empty() {
+            break;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            // Folded header, continuation of the previous one.
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    headers
+}
+
+/// Does the message start with email headers?
+///
+/// Plain patches may start with something like `Fix: ...`, so we only consider
+/// it a header block if it has the headers we care about.
+fn has_headers(text: &str) -> bool {
+    let mut known = false;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            break;
+        } else if line.starts_with([' ', '\t']) {
+            continue;
+        }
+
+        match line.split_once(':') {
+            Some((name, _))
+                if !name.is_empty()
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') =>
+            {
+                known |= ["from", "subject"].contains(&name.to_ascii_lowercase().as_str());
+            }
+            _ => return false,
+        }
+    }
+
+    known
+}
+
+fn is_in_body_header(line: &str) -> bool {
+    ["From:", "Date:", "Subject:"]
+        .iter()
+        .any(|h| line.starts_with(h))
+}
+
+/// Remove the `[PATCH ...]` and similar prefixes from a subject.
+fn strip_subject_prefix(subject: &str) -> String {
+    let mut subject = subject.trim();
+    loop {
+        if let Some(rest) = subject.strip_prefix('[') {
+            match rest.split_once(']') {
+                Some((_, rest)) => subject = rest.trim_start(),
+                None => break,
+            }
+        } else if subject
+            .get(..3)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("re:"))
+        {
+            subject = subject[3..].trim_start();
+        } else {
+            break;
+        }
+    }
+
+    subject.to_string()
+}
+
+/// Find where the diff starts in a message.
+fn find_diff_start(data: &[u8]) -> usize {
+    let mut pos = 0;
+    let mut lines = data.split_inclusive(|&b| b == b'\n').peekable();
+    while let Some(line) = lines.next() {
+        let is_start = line.starts_with(b"diff ")
+            || line.starts_with(b"Index: ")
+            || (line.starts_with(b"--- ")
+                && lines.peek().is_some_and(|next| next.starts_with(b"+++ ")));
+        if is_start {
+            return pos;
+        }
+        pos += line.len();
+    }
+
+    data.len()
+}
+
+/// Remove the email signature (after a `-- ` line) after the diff.
+fn strip_signature(diff: &[u8]) -> &[u8] {
+    const SEPARATOR: &[u8] = b"\n-- \n";
+
+    match diff.windows(SEPARATOR.len()).rposition(|w| w == SEPARATOR) {
+        // A removed `- ` line looks like the separator, so only strip it if there
+        // are no hunks after it.
+        Some(pos)
+            if !diff[pos..]
+                .split(|&b| b == b'\n')
+                .any(|l| l.starts_with(b"@@") || l.starts_with(b"diff ")) =>
+        {
+            &diff[..=pos]
+        }
+        _ => diff,
+    }
+}
+
+/// Convert plain unified diffs (e.g. from quilt) to the Git diff format, which is
+/// the only one understood by libgit2.
+///
+/// File headers without a `diff --git` line get one, with paths stripped of their
+/// first component, like `patch -p1`.
+fn normalize_diff(diff: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(diff.len());
+    let mut in_git_header = false;
+    let mut lines = diff.split_inclusive(|&b| b == b'\n').peekable();
+
+    while let Some(line) = lines.next() {
+        if line.starts_with(b"diff --git ") {
+            in_git_header = true;
+        } else if line.starts_with(b"@@") {
+            in_git_header = false;
+        } else if line.starts_with(b"Index: ") || line.starts_with(b"=====") {
+            // Quilt and Subversion headers.
+            continue;
+        } else if !in_git_header
+            && line.starts_with(b"--- ")
+            && lines.peek().is_some_and(|n| n.starts_with(b"+++ "))
+        {
+            let new_line = lines.next().unwrap_or_default();
+            let old = header_path(&line[4..]);
+            let new = header_path(&new_line[4..]);
+            let path = if new == "/dev/null" { &old } else { &new };
+
+            normalized.extend_from_slice(format!("diff --git a/{0} b/{0}\n", path).as_bytes());
+            if old == "/dev/null" {
+                normalized.extend_from_slice(b"new file mode 100644\n");
+            } else if new == "/dev/null" {
+                normalized.extend_from_slice(b"deleted file mode 100644\n");
+            }
+
+            let side = |marker: &str, prefix: char, p: &str| {
+                if p == "/dev/null" {
+                    format!("{} /dev/null\n", marker)
+                } else {
+                    format!("{} {}/{}\n", marker, prefix, p)
+                }
+            };
+            normalized.extend_from_slice(side("---", 'a', &old).as_bytes());
+            normalized.extend_from_slice(side("+++", 'b', &new).as_bytes());
+            continue;
+        }
+
+        normalized.extend_from_slice(line);
+    }
+
+    normalized
+}
+
+/// The path in a `---`/`+++` line, without timestamp and first component.
+fn header_path(value: &[u8]) -> String {
+    let value = String::from_utf8_lossy(value);
+    let path = value.trim_end().split('\t').next().unwrap_or_default();
+    if path == "/dev/null" {
+        return path.to_string();
+    }
+
+    path.split_once('/')
+        .map_or(path, |(_, rest)| rest)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{base_branch, clone_of, setup};
+    use tempfile::TempDir;
+
+    const PATCH: &str = "\
+From 1234567890abcdef1234567890abcdef12345678 Mon Sep 17 00:00:00 2001
+From: Jane Doe <jane@example.com>
+Date: Mon, 1 Mar 2021 10:00:00 +0100
+Subject: [PATCH 1/2] Fix the thing
+
+Longer description
+of the fix.
+---
+ file | 2 +-
+ 1 file changed, 1 insertion(+), 1 deletion(-)
+
+diff --git a/file b/file
+index 1111111..2222222 100644
+--- a/file
++++ b/file
+@@ -1 +1 @@
+-old
++new
+-- 
+2.30.0
+
+From 1234567890abcdef1234567890abcdef12345679 Mon Sep 17 00:00:00 2001
+From: Jane Doe <jane@example.com>
+Date: Mon, 1 Mar 2021 10:05:00 +0100
+Subject: [PATCH 2/2] Add another
+ thing
+
+diff --git a/other b/other
+new file mode 100644
+--- /dev/null
++++ b/other
+@@ -0,0 +1 @@
++other
+";
+
+    #[test]
+    fn split_mbox_messages() {
+        let messages = split_mbox(PATCH.as_bytes());
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with(b"From: Jane"));
+        assert!(messages[1].starts_with(b"From: Jane"));
+    }
+
+    #[test]
+    fn split_plain_patch() {
+        let data = b"Fix it\n\ndiff --git a/f b/f\n";
+        assert_eq!(split_mbox(data), vec![&data[..]]);
+    }
+
+    #[test]
+    fn parse_format_patch_message() {
+        let messages = split_mbox(PATCH.as_bytes());
+        let patch = parse_message(messages[0], "fallback");
+        assert_eq!(
+            patch.author,
+            Some(("Jane Doe".to_string(), "jane@example.com".to_string()))
+        );
+        assert!(patch.date.is_some());
+        assert_eq!(patch.subject(), "Fix the thing");
+        assert_eq!(
+            patch.message(),
+            "Fix the thing\n\nLonger description\nof the fix.\n"
+        );
+        assert!(patch.diff.starts_with(b"diff --git a/file b/file\n"));
+        assert!(patch.diff.ends_with(b"+new\n"));
+
+        let patch = parse_message(messages[1], "fallback");
+        assert_eq!(patch.subject(), "Add another thing");
+        assert_eq!(patch.message(), "Add another thing\n");
+    }
+
+    #[test]
+    fn parse_in_body_headers() {
+        let message = "\
+From: Sender <sender@example.com>
+Subject: [PATCH] Sent subject
+
+From: Author <author@example.com>
+Subject: Real subject
+
+Body.
+---
+diff --git a/f b/f
+";
+        let patch = parse_message(message.as_bytes(), "fallback");
+        assert_eq!(
+            patch.author,
+            Some(("Author".to_string(), "author@example.com".to_string()))
+        );
+        assert_eq!(patch.message(), "Real subject\n\nBody.\n");
+    }
+
+    #[test]
+    fn parse_plain_patch() {
+        let message = "Fix: the subject\nspans lines\n\nBody.\n\ndiff --git a/f b/f\n";
+        let patch = parse_message(message.as_bytes(), "fallback");
+        assert_eq!(patch.author, None);
+        assert_eq!(patch.message(), "Fix: the subject spans lines\n\nBody.\n");
+
+        let patch = parse_message(b"diff --git a/f b/f\n", "fallback");
+        assert_eq!(patch.subject(), "fallback");
+    }
+
+    #[test]
+    fn strip_subject_prefixes() {
+        assert_eq!(strip_subject_prefix("[PATCH] Fix"), "Fix");
+        assert_eq!(strip_subject_prefix("[PATCH v2 1/3] Fix"), "Fix");
+        assert_eq!(strip_subject_prefix("Re: [PATCH] Fix"), "Fix");
+        assert_eq!(strip_subject_prefix("RE: re: Fix"), "Fix");
+        assert_eq!(
+            strip_subject_prefix("[PATCH unterminated"),
+            "[PATCH unterminated"
+        );
+        assert_eq!(strip_subject_prefix("Fix"), "Fix");
+    }
+
+    #[test]
+    fn strip_subject_prefix_non_ascii() {
+        assert_eq!(strip_subject_prefix("éé fix thing"), "éé fix thing");
+        assert_eq!(strip_subject_prefix("[PATCH] éé fix thing"), "éé fix thing");
+        assert_eq!(strip_subject_prefix("é"), "é");
+    }
+
+    /// The patches of [`PATCH`] in reverse order, the second fails to apply.
+    fn reversed_patches() -> Vec<MailPatch> {
+        let mut patches: Vec<_> = split_mbox(PATCH.as_bytes())
+            .into_iter()
+            .map(|message| parse_message(message, "fallback"))
+            .collect();
+        patches.reverse();
+        patches
+    }
+
+    #[test]
+    fn import_and_resume() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+
+        let res = import(&mut queue, reversed_patches());
+        assert!(matches!(res, Err(Error::ApplyFailed(s)) if s == "Fix the thing"));
+        let names: Vec<_> = queue.state().applied().map(|(n, _)| n).collect();
+        assert_eq!(names, ["add-another-thing"]);
+        let added = ctx
+            .repo()
+            .find_commit(queue.state().patch("add-another-thing").unwrap())
+            .unwrap();
+        assert_eq!(added.author().name(), Some("Jane Doe"));
+        assert_eq!(added.author().when().seconds(), 1614589500);
+        assert_eq!(added.message(), Some("Add another thing\n"));
+
+        assert!(in_progress(&ctx));
+        assert_eq!(pending_queue(&ctx).unwrap().as_deref(), Some("q"));
+        let failed = failed_patch_path(&ctx).unwrap().unwrap();
+        assert_eq!(read_file(&failed).unwrap()[0].subject(), "Fix the thing");
+        assert!(matches!(
+            import(&mut queue, vec![]),
+            Err(Error::AlreadyExists(_))
+        ));
+
+        // The failed patch is created from the index, as fixed by the user.
+        let repo = ctx.repo();
+        let mut index = repo.index().unwrap();
+        index.read_tree(&added.tree().unwrap()).unwrap();
+        let blob = repo.blob(b"new\n").unwrap();
+        let entry = git2::IndexEntry {
+            ctime: git2::IndexTime::new(0, 0),
+            mtime: git2::IndexTime::new(0, 0),
+            dev: 0,
+            ino: 0,
+            mode: 0o100644,
+            uid: 0,
+            gid: 0,
+            file_size: 4,
+            id: blob,
+            flags: 0,
+            flags_extended: 0,
+            path: b"file".to_vec(),
+        };
+        index.add(&entry).unwrap();
+        index.write().unwrap();
+
+        assert_eq!(resume(&mut queue).unwrap(), ["fix-the-thing"]);
+        assert!(!in_progress(&ctx));
+        let names: Vec<_> = queue.state().applied().map(|(n, _)| n).collect();
+        assert_eq!(names, ["add-another-thing", "fix-the-thing"]);
+        let fixed = repo.find_commit(queue.state().head()).unwrap();
+        assert_eq!(
+            fixed.message(),
+            Some("Fix the thing\n\nLonger description\nof the fix.\n")
+        );
+        let tree = fixed.tree().unwrap();
+        assert!(tree.get_name("file").is_some());
+        assert!(tree.get_name("other").is_some());
+    }
+
+    #[test]
+    fn skip_and_abort() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+
+        assert!(import(&mut queue, reversed_patches()).is_err());
+        assert_eq!(skip(&mut queue).unwrap(), Vec::<String>::new());
+        assert!(!in_progress(&ctx));
+        assert_eq!(queue.state().applied().count(), 1);
+
+        assert!(import(&mut queue, reversed_patches()).is_err());
+        abort(&ctx).unwrap();
+        assert!(!in_progress(&ctx));
+        assert_eq!(pending_queue(&ctx).unwrap(), None);
+        // Patches already imported are kept.
+        assert_eq!(queue.state().applied().count(), 2);
+    }
+
+    #[test]
+    fn read_series_and_dirs() {
+        let dir = TempDir::new().unwrap();
+        for file in ["b.patch", "a.diff", "c.txt"] {
+            std::fs::write(dir.path().join(file), "").unwrap();
+        }
+        let files = read_dir(dir.path()).unwrap();
+        assert_eq!(
+            files,
+            [dir.path().join("a.diff"), dir.path().join("b.patch")]
+        );
+
+        let series = "# Quilt series\nb.patch -p1\n\nsub/a.diff # comment\n";
+        std::fs::write(dir.path().join("series"), series).unwrap();
+        let expected = [dir.path().join("b.patch"), dir.path().join("sub/a.diff")];
+        assert_eq!(read_dir(dir.path()).unwrap(), expected);
+        assert_eq!(read_series(&dir.path().join("series")).unwrap(), expected);
+    }
+}
@@ -1,8 +1,27 @@
 pub use self::error::Error;
-pub use git2::{ErrorClass, ErrorCode};
+pub use git2::{ErrorClass, ErrorCode, Oid};
 
 pub mod ctx;
 pub mod error;
-pub(crate) mod gpg;
+pub mod export;
+pub mod gpg;
+pub mod import;
+pub mod lock;
+pub mod mail;
+pub mod naming;
 pub mod objcache;
+pub mod prompt;
 pub mod queue;
+pub mod range_diff;
+pub mod refs;
+pub mod remote;
+pub mod report;
+pub mod review;
+pub mod rfc2822;
+pub mod spec;
+pub mod status;
+pub mod stgit;
+pub mod sync;
+#[cfg(test)]
+mod testing;
+pub mod trailers;
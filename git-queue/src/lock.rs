@@ -0,0 +1,173 @@
+//! # Repository Lock
+//!
+//! Commands that mutate a queue must hold the repository lock while doing so. The
+//! lock is a file in `<git dir>/qg/lock`, created exclusively, containing the PID
+//! of the process holding it. It is removed when the [`RepoLock`] guard is dropped.
+//! If the process holding it was killed instead, the lock is taken over once the
+//! process doesn't exist anymore.
+//!
+//! Read-only operations do not need the lock, as every state change is published
+//! atomically by updating the queuelog reference.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::Error;
+
+/// Guard of the repository lock, releases the lock when dropped.
+#[derive(Debug)]
+pub struct RepoLock {
+    path: PathBuf,
+}
+
+impl RepoLock {
+    /// Try to acquire the lock for the repository in `git_dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Locked`] if the lock is being held by another process.
+    pub(crate) fn acquire(git_dir: &Path) -> Result<Self, Error> {
+        let dir = git_dir.join("qg");
+        std::fs::create_dir_all(&dir)?;
+
+        let path = dir.join("lock");
+        match Self::create(&path) {
+            Err(Error::Locked(path)) if take_over(&path)? => Self::create(&path),
+            result => result,
+        }
+    }
+
+    fn create(path: &Path) -> Result<Self, Error> {
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                // Without the PID the lock can't be taken over if this process is
+                // killed, but it is still held.
+                let _ = writeln!(file, "{}", std::process::id());
+                Ok(Self {
+                    path: path.to_path_buf(),
+                })
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                Err(Error::Locked(path.to_path_buf()))
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Remove the lock at `path` if the process holding it doesn't exist anymore,
+/// returning whether the lock can be created again.
+fn take_over(path: &Path) -> Result<bool, Error> {
+    let pid = match read_pid(path) {
+        Some(pid) if !is_running(pid) => pid,
+        _ => return Ok(false),
+    };
+
+    // Move the lock aside first, so that if another process took it over and
+    // created a new one meanwhile, the new lock can be put back.
+    let stale = path.with_file_name(format!("lock.stale.{}", std::process::id()));
+    match std::fs::rename(path, &stale) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(true),
+        Err(err) => return Err(err.into()),
+    }
+    if read_pid(&stale) != Some(pid) {
+        // Fails if yet another process holds the lock now, which is fine.
+        let _ = std::fs::hard_link(&stale, path);
+        std::fs::remove_file(&stale)?;
+        return Ok(false);
+    }
+    tracing::warn!("taking over the lock of process {}, which is gone", pid);
+    std::fs::remove_file(&stale)?;
+
+    Ok(true)
+}
+
+fn read_pid(path: &Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Is there a process with the given PID? If this can't be known, it is
+/// assumed there is.
+fn is_running(pid: u32) -> bool {
+    if !cfg!(unix) {
+        return true;
+    }
+    match Command::new("kill").arg("-0").arg(pid.to_string()).output() {
+        // The process may belong to another user, which can't be signalled.
+        Ok(output) => {
+            output.status.success()
+                || String::from_utf8_lossy(&output.stderr).contains("not permitted")
+        }
+        Err(_) => true,
+    }
+}
+
+impl Drop for RepoLock {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            tracing::warn!("failed to remove lock `{}`: {}", self.path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_lock(dir: &TempDir, content: &str) {
+        std::fs::create_dir_all(dir.path().join("qg")).unwrap();
+        std::fs::write(dir.path().join("qg/lock"), content).unwrap();
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = TempDir::new().unwrap();
+        let lock = RepoLock::acquire(dir.path()).unwrap();
+        assert!(matches!(
+            RepoLock::acquire(dir.path()),
+            Err(Error::Locked(_))
+        ));
+        drop(lock);
+        assert!(!dir.path().join("qg/lock").exists());
+        RepoLock::acquire(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn lock_of_running_process_is_kept() {
+        let dir = TempDir::new().unwrap();
+        write_lock(&dir, &format!("{}\n", std::process::id()));
+        assert!(matches!(
+            RepoLock::acquire(dir.path()),
+            Err(Error::Locked(_))
+        ));
+
+        // Without a PID, it can't be known whether the lock is stale.
+        write_lock(&dir, "");
+        assert!(matches!(
+            RepoLock::acquire(dir.path()),
+            Err(Error::Locked(_))
+        ));
+    }
+
+    #[test]
+    fn lock_of_dead_process_is_taken_over() {
+        let dir = TempDir::new().unwrap();
+        let mut child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        write_lock(&dir, &format!("{}\n", pid));
+
+        let lock = RepoLock::acquire(dir.path()).unwrap();
+        assert_eq!(read_pid(lock.path()), Some(std::process::id()));
+        assert_eq!(std::fs::read_dir(dir.path().join("qg")).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,695 @@
+//! # Sending Patches by Email
+//!
+//! Send a formatted patch series as a thread of email messages, like
+//! `git send-email` does. The following configuration is honored:
+//!
+//!   * `sendemail.from`: the sender, defaults to the committer identity.
+//!   * `sendemail.to` and `sendemail.cc`: recipients added to every series, can be
+//!     given multiple times.
+//!   * `sendemail.smtpServer`: host name of the SMTP server, or the absolute path
+//!     of a `sendmail`-like program.
+//!   * `sendemail.smtpServerPort`: port of the SMTP server, defaults to 25.
+//!   * `sendemail.smtpDomain`: name used in the SMTP greeting, defaults to
+//!     `localhost`.
+//!   * `sendemail.smtpUser` and `sendemail.smtpPass`: credentials used to
+//!     authenticate with `AUTH PLAIN`. As the connection isn't encrypted, they
+//!     are only sent to servers on the loopback interface, e.g. a local relay.
+//!   * `sendemail.smtpEncryption`: only unencrypted connections are supported, use
+//!     a local relay or `sendmail` to reach servers requiring TLS.
+//!   * `sendemail.sendmailCmd`: a shell command used instead of the SMTP server.
+//!   * `sendemail.thread`: whether to add `In-Reply-To` and `References` headers,
+//!     defaults to `true`.
+//!   * `sendemail.chainReplyTo`: whether each message replies to the previous one,
+//!     instead of all replying to the first, defaults to `false`.
+//!
+//! Like Git, if neither a server nor a command is configured, `/usr/sbin/sendmail`
+//! or `/usr/lib/sendmail` are used if they exist, otherwise the SMTP server at
+//! `localhost`.
+
+use std::convert::TryFrom;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+use crate::export::FormattedPatch;
+use crate::queue::{log::SentSeries, Queue};
+use crate::rfc2822::{encode_base64, format_date, mailbox, parse_mailbox};
+
+/// How messages are delivered.
+pub enum Transport {
+    /// Send through a SMTP server.
+    Smtp {
+        host: String,
+        port: u16,
+        domain: String,
+        auth: Option<(String, String)>,
+    },
+    /// Pipe each message to a `sendmail`-like program.
+    Sendmail(String),
+    /// Pipe each message to a shell command, as `sendemail.sendmailCmd`.
+    SendmailCmd(String),
+}
+
+impl Transport {
+    /// Deliver a message to the given recipient addresses.
+    pub fn send(&self, from: &str, recipients: &[String], message: &[u8]) -> Result<(), Error> {
+        match self {
+            Self::Smtp {
+                host,
+                port,
+                domain,
+                auth,
+            } => smtp_send(
+                host,
+                *port,
+                domain,
+                auth.as_ref(),
+                from,
+                recipients,
+                message,
+            ),
+            Self::Sendmail(program) => {
+                let mut cmd = Command::new(program);
+                cmd.args(["-i", "-f", from]).args(recipients);
+                run_sendmail(cmd, program, message)
+            }
+            Self::SendmailCmd(command) => {
+                // Like Git, the recipients are appended to the command.
+                let mut cmd = Command::new("sh");
+                cmd.args(["-c", &format!("{} \"$@\"", command), command])
+                    .args(["-i", "-f", from])
+                    .args(recipients);
+                run_sendmail(cmd, command, message)
+            }
+        }
+    }
+}
+
+/// The `sendemail.*` configuration.
+pub struct SendEmailConfig {
+    from: Option<String>,
+    to: Vec<String>,
+    cc: Vec<String>,
+    thread: bool,
+    chain_reply_to: bool,
+    transport: Transport,
+}
+
+impl SendEmailConfig {
+    pub fn from_config(config: &git2::Config) -> Result<Self, Error> {
+        let get = |name: &str| config.get_string(&format!("sendemail.{}", name)).ok();
+
+        if let Some(encryption) = get("smtpEncryption") {
+            if !encryption.is_empty() && encryption != "none" {
+                return Err(Error::Mail(format!(
+                    "`sendemail.smtpEncryption = {}` is not supported, use a local relay \
+                     or `sendemail.sendmailCmd`",
+                    encryption
+                )));
+            }
+        }
+
+        let transport = if let Some(command) = get("sendmailCmd") {
+            Transport::SendmailCmd(command)
+        } else {
+            let server = get("smtpServer").or_else(|| {
+                ["/usr/sbin/sendmail", "/usr/lib/sendmail"]
+                    .iter()
+                    .find(|p| Path::new(p).exists())
+                    .map(|p| p.to_string())
+            });
+
+            match server {
+                Some(program) if program.starts_with('/') => Transport::Sendmail(program),
+                server => Transport::Smtp {
+                    host: server.unwrap_or_else(|| "localhost".to_string()),
+                    port: config
+                        .get_i32("sendemail.smtpServerPort")
+                        .ok()
+                        .and_then(|p| u16::try_from(p).ok())
+                        .unwrap_or(25),
+                    domain: get("smtpDomain").unwrap_or_else(|| "localhost".to_string()),
+                    auth: get("smtpUser").map(|user| (user, get("smtpPass").unwrap_or_default())),
+                },
+            }
+        };
+
+        Ok(Self {
+            from: get("from"),
+            to: multivar(config, "sendemail.to")?,
+            cc: multivar(config, "sendemail.cc")?,
+            thread: config.get_bool("sendemail.thread").unwrap_or(true),
+            chain_reply_to: config.get_bool("sendemail.chainReplyTo").unwrap_or(false),
+            transport,
+        })
+    }
+
+    /// The configured recipients.
+    pub fn to(&self) -> &[String] {
+        &self.to
+    }
+
+    /// The configured carbon copy recipients.
+    pub fn cc(&self) -> &[String] {
+        &self.cc
+    }
+
+    pub fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    /// The sender mailbox, `sendemail.from` or the given user.
+    pub fn sender(&self, user: &git2::Signature<'_>) -> String {
+        match &self.from {
+            Some(from) => from.clone(),
+            None => mailbox(
+                user.name().unwrap_or_default(),
+                user.email().unwrap_or_default(),
+            ),
+        }
+    }
+
+    /// Prepare the messages of a patch series to be sent.
+    ///
+    /// Each message gets a Message-ID, a `Date` one second after the previous
+    /// message, so they are sorted correctly by mail clients, and the sender and
+    /// recipient headers. If threading is enabled, the messages reply to the cover
+    /// letter, or the first patch if there is none.
+    pub fn prepare(
+        &self,
+        sender: &str,
+        to: &[String],
+        cc: &[String],
+        cover_letter: Option<FormattedPatch>,
+        patches: Vec<FormattedPatch>,
+    ) -> MailSeries {
+        let has_cover_letter = cover_letter.is_some();
+        let mut messages: Vec<_> = cover_letter.into_iter().chain(patches).collect();
+
+        let (_, sender_email) = parse_mailbox(sender);
+        let domain = sender_email
+            .rsplit_once('@')
+            .map_or("localhost", |(_, domain)| domain);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+
+        let mut ids = Vec::with_capacity(messages.len());
+        let mut references: Vec<String> = Vec::new();
+        for (idx, message) in messages.iter_mut().enumerate() {
+            let id = format!(
+                "<{}.{}-{}-git-queue@{}>",
+                now,
+                std::process::id(),
+                idx + 1,
+                domain
+            );
+
+            message.set_sender(sender);
+            message.set_header("Date", format_date(git2::Time::new(now + idx as i64, 0)));
+            if !to.is_empty() {
+                message.set_header("To", to.join(", "));
+            }
+            if !cc.is_empty() {
+                message.set_header("Cc", cc.join(", "));
+            }
+            message.set_header("Message-ID", id.clone());
+
+            if self.thread {
+                if let Some(parent) = references.last() {
+                    message.set_header("In-Reply-To", parent.clone());
+                    message.set_header("References", references.join(" "));
+                }
+                if self.chain_reply_to || references.is_empty() {
+                    references.push(id.clone());
+                }
+            }
+            ids.push(id);
+        }
+
+        let mut recipients = Vec::with_capacity(to.len() + cc.len());
+        for (_, address) in to.iter().chain(cc).map(|r| parse_mailbox(r)) {
+            if !recipients.contains(&address) {
+                recipients.push(address);
+            }
+        }
+
+        MailSeries {
+            envelope_from: sender_email,
+            recipients,
+            has_cover_letter,
+            messages,
+            ids,
+        }
+    }
+}
+
+/// A patch series ready to be sent.
+pub struct MailSeries {
+    envelope_from: String,
+    recipients: Vec<String>,
+    has_cover_letter: bool,
+    messages: Vec<FormattedPatch>,
+    ids: Vec<String>,
+}
+
+impl MailSeries {
+    /// The messages to send, starting with the cover letter, if any.
+    pub fn messages(&self) -> &[FormattedPatch] {
+        &self.messages
+    }
+
+    /// Send the messages, recording their Message-IDs in the queue log.
+    ///
+    /// `on_sent` is called after each message is sent. If a message can't be
+    /// sent, the ones sent before it are still recorded.
+    pub fn send(
+        self,
+        transport: &Transport,
+        queue: &mut Queue<'_>,
+        mut on_sent: impl FnMut(&FormattedPatch),
+    ) -> Result<SentSeries, Error> {
+        let mut sent = SentSeries::default();
+        let mut result = Ok(());
+        for (idx, (message, id)) in self.messages.iter().zip(&self.ids).enumerate() {
+            result = transport.send(&self.envelope_from, &self.recipients, &message.to_message());
+            if result.is_err() {
+                break;
+            }
+
+            if idx == 0 && self.has_cover_letter {
+                sent.cover_letter = Some(id.clone());
+            } else {
+                sent.patches.push((message.name().to_string(), id.clone()));
+            }
+            on_sent(message);
+        }
+
+        let num_sent = sent.patches.len();
+        if sent.cover_letter.is_some() || num_sent > 0 {
+            let plural = if num_sent == 1 { "" } else { "es" };
+            let message = format!("mail {} patch{}", num_sent, plural);
+            queue.record_mail(message, sent.clone())?;
+        }
+
+        result.map(|()| sent)
+    }
+}
+
+fn multivar(config: &git2::Config, name: &str) -> Result<Vec<String>, Error> {
+    let entries = match config.multivar(name, None) {
+        Ok(entries) => entries,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut values = Vec::new();
+    for entry in &entries {
+        if let Some(value) = entry?.value() {
+            values.push(value.to_string());
+        }
+    }
+    Ok(values)
+}
+
+fn run_sendmail(mut cmd: Command, program: &str, message: &[u8]) -> Result<(), Error> {
+    let spawn_error = |err| Error::Mail(format!("failed to run `{}`: {}", program, err));
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+
+    child
+        .stdin
+        .take()
+        .expect("child stdin is piped")
+        .write_all(message)
+        .map_err(spawn_error)?;
+    let output = child.wait_with_output().map_err(spawn_error)?;
+
+    if !output.status.success() {
+        return Err(Error::Mail(format!(
+            "`{}` failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// How long to wait for the SMTP server before giving up.
+const SMTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn smtp_send(
+    host: &str,
+    port: u16,
+    domain: &str,
+    auth: Option<&(String, String)>,
+    from: &str,
+    recipients: &[String],
+    message: &[u8],
+) -> Result<(), Error> {
+    let io_error = |err: std::io::Error| Error::Mail(format!("{}:{}: {}", host, port, err));
+
+    let stream = TcpStream::connect((host, port)).map_err(io_error)?;
+    stream
+        .set_read_timeout(Some(SMTP_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(SMTP_TIMEOUT)))
+        .map_err(io_error)?;
+    // The password would be sent in clear text.
+    if auth.is_some() && !stream.peer_addr().map_err(io_error)?.ip().is_loopback() {
+        return Err(Error::Mail(format!(
+            "refusing to authenticate to {} over an unencrypted connection, \
+             use a local relay or `sendmail` instead",
+            host
+        )));
+    }
+    let mut smtp = Smtp {
+        reader: BufReader::new(stream.try_clone().map_err(io_error)?),
+        writer: stream,
+    };
+
+    smtp.expect(220)?;
+    smtp.command(&format!("EHLO {}", domain), 250)?;
+    if let Some((user, pass)) = auth {
+        let credentials = encode_base64(format!("\0{}\0{}", user, pass).as_bytes());
+        smtp.command(&format!("AUTH PLAIN {}", credentials), 235)?;
+    }
+    smtp.command(&format!("MAIL FROM:<{}>", from), 250)?;
+    for recipient in recipients {
+        smtp.command(&format!("RCPT TO:<{}>", recipient), 250)?;
+    }
+    smtp.command("DATA", 354)?;
+    smtp.writer
+        .write_all(&smtp_data(message))
+        .map_err(io_error)?;
+    smtp.expect(250)?;
+
+    // The message was accepted, a failure to quit doesn't matter.
+    let _ = smtp.command("QUIT", 221);
+
+    Ok(())
+}
+
+/// Encode a message as the data of the SMTP `DATA` command, ending with the
+/// terminating `.` line.
+///
+/// Lines must end with CRLF, and lines starting with a dot have it doubled so
+/// they aren't taken as the end of the data.
+fn smtp_data(message: &[u8]) -> Vec<u8> {
+    // The last line break ends the last line, it doesn't start a new one.
+    let message = message.strip_suffix(b"\n").unwrap_or(message);
+
+    let mut data = Vec::with_capacity(message.len() + message.len() / 32 + 5);
+    for line in message.split(|&b| b == b'\n') {
+        if line.starts_with(b".") {
+            data.push(b'.');
+        }
+        data.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+        data.extend_from_slice(b"\r\n");
+    }
+    data.extend_from_slice(b".\r\n");
+
+    data
+}
+
+struct Smtp {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Smtp {
+    fn command(&mut self, command: &str, code: u16) -> Result<(), Error> {
+        write!(self.writer, "{}\r\n", command).map_err(|err| Error::Mail(err.to_string()))?;
+        self.expect(code)
+    }
+
+    /// Read a reply, checking it is in the same class of the given code.
+    fn expect(&mut self, code: u16) -> Result<(), Error> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return Err(Error::Mail("connection closed by the server".to_string())),
+                Ok(_) => {}
+                Err(err) => return Err(Error::Mail(err.to_string())),
+            }
+            reply.push_str(&line);
+            // The last line of a multiline reply has a space after the code.
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+
+        let received: u16 = reply.get(..3).and_then(|c| c.parse().ok()).unwrap_or(0);
+        if received / 100 == code / 100 {
+            Ok(())
+        } else {
+            Err(Error::Mail(format!("server replied: {}", reply.trim_end())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        export::{format_patches, queue_cover_letter},
+        testing::{add_patch, base_branch, clone_of, setup},
+    };
+    use std::net::TcpListener;
+    use tempfile::TempDir;
+
+    fn config(dir: &TempDir, values: &[(&str, &str)]) -> git2::Config {
+        let mut config = git2::Config::open(&dir.path().join("config")).unwrap();
+        for (key, value) in values {
+            config.set_multivar(key, "^$", value).unwrap();
+        }
+        config
+    }
+
+    #[test]
+    fn sendemail_config() {
+        let dir = TempDir::new().unwrap();
+        let smtp = config(
+            &dir,
+            &[
+                ("sendemail.smtpServer", "mail.example.com"),
+                ("sendemail.smtpServerPort", "2525"),
+                ("sendemail.smtpUser", "me"),
+                ("sendemail.to", "a@example.com"),
+                ("sendemail.to", "b@example.com"),
+                ("sendemail.from", "Me <me@example.com>"),
+            ],
+        );
+        let smtp = SendEmailConfig::from_config(&smtp).unwrap();
+        assert!(matches!(
+            smtp.transport(),
+            Transport::Smtp { host, port: 2525, domain, auth: Some((user, pass)) }
+                if host == "mail.example.com"
+                    && domain == "localhost"
+                    && user == "me"
+                    && pass.is_empty()
+        ));
+        assert_eq!(smtp.to(), ["a@example.com", "b@example.com"]);
+        assert!(smtp.cc().is_empty());
+        let user = git2::Signature::now("A", "a@example.com").unwrap();
+        assert_eq!(smtp.sender(&user), "Me <me@example.com>");
+
+        let dir = TempDir::new().unwrap();
+        let sendmail = config(&dir, &[("sendemail.smtpServer", "/bin/sendmail")]);
+        let sendmail = SendEmailConfig::from_config(&sendmail).unwrap();
+        assert!(matches!(sendmail.transport(), Transport::Sendmail(p) if p == "/bin/sendmail"));
+        assert_eq!(sendmail.sender(&user), "A <a@example.com>");
+
+        let dir = TempDir::new().unwrap();
+        let tls = config(&dir, &[("sendemail.smtpEncryption", "tls")]);
+        assert!(matches!(
+            SendEmailConfig::from_config(&tls),
+            Err(Error::Mail(_))
+        ));
+    }
+
+    #[test]
+    fn send_threaded_series() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "a");
+        add_patch(&mut queue, "b");
+
+        // By default every patch replies to the first one.
+        let cfg = config(&dir, &[]);
+        let series = SendEmailConfig::from_config(&cfg).unwrap().prepare(
+            "Me <me@example.com>",
+            &["a@example.com".to_string()],
+            &[
+                "Jane <a@example.com>".to_string(),
+                "c@example.com".to_string(),
+            ],
+            None,
+            format_patches(ctx.repo(), queue.state().applied()).unwrap(),
+        );
+        let messages = series.messages();
+        let ids: Vec<_> = messages
+            .iter()
+            .map(|m| m.header("Message-ID").unwrap().to_string())
+            .collect();
+        assert!(ids[0].ends_with("-1-git-queue@example.com>"), "{}", ids[0]);
+        assert_eq!(messages[0].header("In-Reply-To"), None);
+        assert_eq!(messages[1].header("In-Reply-To"), Some(&*ids[0]));
+        assert_eq!(messages[1].header("References"), Some(&*ids[0]));
+        assert_eq!(messages[1].header("To"), Some("a@example.com"));
+        assert_eq!(
+            messages[1].header("Cc"),
+            Some("Jane <a@example.com>, c@example.com")
+        );
+        // The author isn't the sender, so it's kept in the body.
+        assert_eq!(messages[1].header("From"), Some("Me <me@example.com>"));
+        assert!(messages[1]
+            .body()
+            .starts_with(b"From: A <a@example.com>\n\n"));
+        assert_eq!(series.recipients, ["a@example.com", "c@example.com"]);
+        assert_eq!(series.envelope_from, "me@example.com");
+
+        // Each message is piped to the command, which gets the envelope.
+        let out = dir.path().join("out");
+        let script = dir.path().join("sendmail");
+        let body = format!("echo \"$@\" >> {0}\ncat >> {0}\n", out.display());
+        std::fs::write(&script, body).unwrap();
+        let command = format!("sh {}", script.display());
+        let transport = Transport::SendmailCmd(command);
+        let mut sent = Vec::new();
+        let recorded = series
+            .send(&transport, &mut queue, |m| sent.push(m.name().to_string()))
+            .unwrap();
+        assert_eq!(sent, ["a", "b"]);
+        let out = std::fs::read_to_string(out).unwrap();
+        assert!(out.starts_with("-i -f me@example.com a@example.com c@example.com\n"));
+        assert_eq!(out.matches("\nMessage-ID: ").count(), 2);
+
+        let mail = queue.state().mail().unwrap();
+        assert_eq!(mail.cover_letter, None);
+        assert_eq!(mail.patches, recorded.patches);
+        assert_eq!(mail.patches[1], ("b".to_string(), ids[1].clone()));
+
+        // With a cover letter and chained replies, each message replies to the
+        // previous one.
+        let cfg = config(&dir, &[("sendemail.chainReplyTo", "true")]);
+        let patches = format_patches(ctx.repo(), queue.state().applied()).unwrap();
+        let cover = queue_cover_letter(&queue, &patches).unwrap();
+        let series = SendEmailConfig::from_config(&cfg).unwrap().prepare(
+            "A <a@example.com>",
+            &[],
+            &[],
+            Some(cover),
+            patches,
+        );
+        let messages = series.messages();
+        let ids: Vec<_> = messages
+            .iter()
+            .map(|m| m.header("Message-ID").unwrap().to_string())
+            .collect();
+        assert_eq!(messages[2].header("In-Reply-To"), Some(&*ids[1]));
+        assert_eq!(messages[2].header("References"), Some(&*ids[..2].join(" ")));
+        assert_eq!(messages[2].header("To"), None);
+        assert_eq!(messages[2].header("From"), Some("A <a@example.com>"));
+
+        let failing = Transport::SendmailCmd("false".to_string());
+        assert!(series.send(&failing, &mut queue, |_| {}).is_err());
+    }
+
+    #[test]
+    fn smtp_data_ends_lines_and_stuffs_dots() {
+        assert_eq!(smtp_data(b"a\n.b\n\nc\n"), b"a\r\n..b\r\n\r\nc\r\n.\r\n");
+        assert_eq!(smtp_data(b"a\r\nb"), b"a\r\nb\r\n.\r\n");
+    }
+
+    /// A fake SMTP server accepting a single message, returning what it received.
+    fn fake_server() -> (u16, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let mut received = Vec::new();
+            writer.write_all(b"220 fake ESMTP\r\n").unwrap();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.strip_suffix("\r\n").expect("lines end with CRLF");
+                received.push(line.to_string());
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if line.starts_with("EHLO") {
+                    b"250-fake\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 ok\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go on\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+            received
+        });
+
+        (port, handle)
+    }
+
+    #[test]
+    fn smtp_send_command_sequence() {
+        let (port, server) = fake_server();
+        let auth = ("user".to_string(), "pass".to_string());
+        let recipients = vec!["a@example.com".to_string(), "b@example.com".to_string()];
+        smtp_send(
+            "127.0.0.1",
+            port,
+            "client.example.com",
+            Some(&auth),
+            "me@example.com",
+            &recipients,
+            b"Subject: test\n\n.hidden\nbody\n",
+        )
+        .unwrap();
+
+        let credentials = encode_base64(b"\0user\0pass");
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                "EHLO client.example.com".to_string(),
+                format!("AUTH PLAIN {}", credentials),
+                "MAIL FROM:<me@example.com>".to_string(),
+                "RCPT TO:<a@example.com>".to_string(),
+                "RCPT TO:<b@example.com>".to_string(),
+                "DATA".to_string(),
+                "Subject: test".to_string(),
+                "".to_string(),
+                "..hidden".to_string(),
+                "body".to_string(),
+                ".".to_string(),
+                "QUIT".to_string(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! # Naming
+//!
+//! Validation of queue and patch names, and derivation of patch names from
+//! commit subjects.
+//!
+//! Queues and patches are stored as Git references, so their names follow the
+//! rules of `git check-ref-format`. Patch names are a single component, i.e. they
+//! can't contain `/`. Queue names may have several, where the first ones are the
+//! namespace of the queue, e.g. `team/feature` is in the `team` namespace. Like
+//! Git branches, a queue can't be named like the namespace of another one. Each
+//! component is a file name when the reference is written, so it can't be longer
+//! than 250 bytes, leaving room for the `.lock` suffix.
+//!
+//! Names derived from subjects are lowercase slugs of ASCII letters, digits, `_`
+//! and `-`, up to `qg.patchNameLength` characters (40 by default). When the slug
+//! is taken, a numeric suffix is added, e.g. `fix-the-build-2`.
+
+use crate::error::Error;
+
+/// Default maximum length of a patch name derived from a subject.
+const DEFAULT_MAX_LENGTH: usize = 40;
+
+/// Maximum length of a component of a name, a file name of at most 255 bytes
+/// once `.lock` is added.
+const MAX_COMPONENT_LENGTH: usize = 250;
+
+/// Name used when nothing is left of the subject.
+const FALLBACK_NAME: &str = "patch";
+
+/// The rule of reference names that a name breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameRule {
+    Empty,
+    Slash,
+    EmptyComponent,
+    LeadingDot,
+    TrailingDot,
+    LockSuffix,
+    DoubleDot,
+    AtBrace,
+    LoneAt,
+    TooLong,
+    Character(char),
+}
+
+impl std::fmt::Display for NameRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => f.write_str("it is empty"),
+            Self::Slash => f.write_str("patch names can't contain `/`"),
+            Self::EmptyComponent => f.write_str("it can't start or end with `/`, nor contain `//`"),
+            Self::LeadingDot => f.write_str("it can't start with `.`, nor contain `/.`"),
+            Self::TrailingDot => f.write_str("it can't end with `.`"),
+            Self::LockSuffix => f.write_str("it can't end with `.lock`, nor contain `.lock/`"),
+            Self::DoubleDot => f.write_str("it can't contain `..`"),
+            Self::AtBrace => f.write_str("it can't contain `@{`"),
+            Self::LoneAt => f.write_str("it can't be `@`"),
+            Self::TooLong => write!(
+                f,
+                "it can't have components longer than {} bytes",
+                MAX_COMPONENT_LENGTH
+            ),
+            Self::Character(c) if c.is_ascii_control() => {
+                write!(f, "it can't contain control characters, like {:?}", c)
+            }
+            Self::Character(' ') => f.write_str("it can't contain spaces"),
+            Self::Character(c) => write!(f, "it can't contain `{}`", c),
+        }
+    }
+}
+
+/// A name that can't be used for a queue or patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameError {
+    name: String,
+    rule: NameRule,
+}
+
+impl NameError {
+    /// The invalid name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The rule broken by the name.
+    pub fn rule(&self) -> NameRule {
+        self.rule
+    }
+}
+
+impl std::fmt::Display for NameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid name `{}`: {}", self.name, self.rule)
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// Check that a name can be used as a queue name.
+///
+/// Queue names can have several components separated by `/`, like branches.
+pub fn validate_queue_name(name: &str) -> Result<(), NameError> {
+    check(name, true).map_err(|rule| NameError {
+        name: name.to_string(),
+        rule,
+    })
+}
+
+/// Check that a name can be used as a patch name.
+pub fn validate_patch_name(name: &str) -> Result<(), NameError> {
+    check(name, false).map_err(|rule| NameError {
+        name: name.to_string(),
+        rule,
+    })
+}
+
+fn check(name: &str, allow_slash: bool) -> Result<(), NameRule> {
+    if name.is_empty() {
+        return Err(NameRule::Empty);
+    }
+    if name == "@" {
+        return Err(NameRule::LoneAt);
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|&c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+    {
+        return Err(NameRule::Character(c));
+    }
+    if name.contains("..") {
+        return Err(NameRule::DoubleDot);
+    }
+    if name.contains("@{") {
+        return Err(NameRule::AtBrace);
+    }
+    if name.ends_with('.') {
+        return Err(NameRule::TrailingDot);
+    }
+    if !allow_slash && name.contains('/') {
+        return Err(NameRule::Slash);
+    }
+
+    for component in name.split('/') {
+        if component.is_empty() {
+            return Err(NameRule::EmptyComponent);
+        }
+        if component.starts_with('.') {
+            return Err(NameRule::LeadingDot);
+        }
+        if component.ends_with(".lock") {
+            return Err(NameRule::LockSuffix);
+        }
+        if component.len() > MAX_COMPONENT_LENGTH {
+            return Err(NameRule::TooLong);
+        }
+    }
+
+    Ok(())
+}
+
+/// Is the queue in the namespace, e.g. `team/feature` in `team`?
+///
+/// A queue is not in a namespace with its own name.
+pub fn in_namespace(queue: &str, namespace: &str) -> bool {
+    let namespace = namespace.trim_end_matches('/');
+    queue
+        .strip_prefix(namespace)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Maximum length of names derived from subjects, from `qg.patchNameLength`.
+///
+/// Longer values than the length allowed for patch names are clamped to it.
+pub fn max_length(config: &git2::Config) -> Result<usize, Error> {
+    match config.get_i64("qg.patchNameLength") {
+        // Leave room for a numeric suffix.
+        Ok(len) if len >= 8 => Ok((len as u64).min(MAX_COMPONENT_LENGTH as u64) as usize),
+        Ok(_) => Err(Error::Config(
+            "qg.patchNameLength must be at least 8".to_string(),
+        )),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(DEFAULT_MAX_LENGTH),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Derive a name from a commit subject, at most `max_length` bytes long.
+///
+/// Runs of other characters than ASCII letters, digits and `_` are replaced by
+/// a single `-`.
+pub fn slug(subject: &str, max_length: usize) -> String {
+    let mut slug = String::with_capacity(max_length);
+    for c in subject.chars() {
+        if slug.len() >= max_length {
+            break;
+        }
+
+        if c.is_ascii_alphanumeric() || c == '_' {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    match slug.trim_end_matches('-') {
+        "" => FALLBACK_NAME.to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+/// Derive a name from a commit subject that isn't taken.
+///
+/// If the slug of the subject is taken, a numeric suffix starting from 2 is
+/// added, shortening the slug if needed to stay within `max_length`.
+pub fn unique_name(subject: &str, max_length: usize, is_taken: impl Fn(&str) -> bool) -> String {
+    let base = slug(subject, max_length);
+    let mut name = base.clone();
+    let mut suffix = 1;
+    while is_taken(&name) {
+        suffix += 1;
+        let suffix = format!("-{}", suffix);
+        let base =
+            base[..base.len().min(max_length.saturating_sub(suffix.len()))].trim_end_matches('-');
+        name = format!("{}{}", base, suffix);
+    }
+
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(result: Result<(), NameError>) -> NameRule {
+        result.unwrap_err().rule()
+    }
+
+    #[test]
+    fn valid_names() {
+        for name in &["fix", "fix-the_build.2", "v1.0", "a@b", "\u{e9}t\u{e9}"] {
+            assert_eq!(validate_patch_name(name), Ok(()), "{}", name);
+            assert_eq!(validate_queue_name(name), Ok(()), "{}", name);
+        }
+        assert_eq!(validate_queue_name("team/feature/part-1"), Ok(()));
+        assert_eq!(validate_patch_name(&"a".repeat(250)), Ok(()));
+        assert_eq!(
+            validate_queue_name(&format!("{0}/{0}", "a".repeat(250))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn invalid_names() {
+        for (name, expected) in &[
+            ("", NameRule::Empty),
+            ("@", NameRule::LoneAt),
+            ("a b", NameRule::Character(' ')),
+            ("a~1", NameRule::Character('~')),
+            ("a^", NameRule::Character('^')),
+            ("q:a", NameRule::Character(':')),
+            ("a?", NameRule::Character('?')),
+            ("a*", NameRule::Character('*')),
+            ("a[0]", NameRule::Character('[')),
+            ("a\\b", NameRule::Character('\\')),
+            ("a\tb", NameRule::Character('\t')),
+            ("a\u{7f}", NameRule::Character('\u{7f}')),
+            ("a..b", NameRule::DoubleDot),
+            ("a@{1}", NameRule::AtBrace),
+            ("a.", NameRule::TrailingDot),
+            (".a", NameRule::LeadingDot),
+            ("a.lock", NameRule::LockSuffix),
+        ] {
+            assert_eq!(rule(validate_patch_name(name)), *expected, "{:?}", name);
+            assert_eq!(rule(validate_queue_name(name)), *expected, "{:?}", name);
+        }
+
+        let long = "a".repeat(251);
+        assert_eq!(rule(validate_patch_name(&long)), NameRule::TooLong);
+        assert_eq!(
+            rule(validate_queue_name(&format!("team/{}", long))),
+            NameRule::TooLong
+        );
+
+        assert_eq!(rule(validate_patch_name("team/a")), NameRule::Slash);
+        for (name, expected) in &[
+            ("/a", NameRule::EmptyComponent),
+            ("a/", NameRule::EmptyComponent),
+            ("a//b", NameRule::EmptyComponent),
+            ("a/.b", NameRule::LeadingDot),
+            ("a.lock/b", NameRule::LockSuffix),
+        ] {
+            assert_eq!(rule(validate_queue_name(name)), *expected, "{:?}", name);
+        }
+
+        let err = validate_queue_name("a b").unwrap_err();
+        assert_eq!(err.name(), "a b");
+        assert_eq!(
+            err.to_string(),
+            "invalid name `a b`: it can't contain spaces"
+        );
+    }
+
+    #[test]
+    fn namespaces() {
+        assert!(in_namespace("team/feature", "team"));
+        assert!(in_namespace("team/feature", "team/"));
+        assert!(in_namespace("team/sub/feature", "team"));
+        assert!(in_namespace("team/sub/feature", "team/sub"));
+        assert!(!in_namespace("team", "team"));
+        assert!(!in_namespace("teams/feature", "team"));
+        assert!(!in_namespace("team", "team/feature"));
+    }
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slug("Fix the build", 40), "fix-the-build");
+        assert_eq!(slug("  [ci] Don't run twice!  ", 40), "ci-don-t-run-twice");
+        assert_eq!(slug("snake_case_name", 40), "snake_case_name");
+        assert_eq!(slug("caf\u{e9} au lait", 40), "caf-au-lait");
+        assert_eq!(slug("!!!", 40), "patch");
+        assert_eq!(slug("", 40), "patch");
+        assert_eq!(slug("a very long subject line", 8), "a-very-l");
+        // No trailing `-` when cut at a separator.
+        assert_eq!(slug("a very long subject line", 7), "a-very");
+    }
+
+    #[test]
+    fn unique_names() {
+        let taken = ["fix-the-build", "fix-the-build-2"];
+        let is_taken = |name: &str| taken.contains(&name);
+        assert_eq!(unique_name("Update docs", 40, is_taken), "update-docs");
+        assert_eq!(
+            unique_name("Fix the build", 40, is_taken),
+            "fix-the-build-3"
+        );
+
+        // The slug is shortened to make room for the suffix.
+        let taken = ["fix-the-buil", "fix-the-bu-2", "fix-the-bu-3"];
+        let is_taken = |name: &str| taken.contains(&name);
+        assert_eq!(unique_name("Fix the build", 12, is_taken), "fix-the-bu-4");
+        // Without a `-` before the suffix when cut after one.
+        assert_eq!(unique_name("ab cd", 5, |name| name == "ab-cd"), "ab-2");
+    }
+
+    #[test]
+    fn max_length_from_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = git2::Config::open(&dir.path().join("config")).unwrap();
+        assert_eq!(max_length(&config).unwrap(), DEFAULT_MAX_LENGTH);
+
+        config.set_i64("qg.patchNameLength", 8).unwrap();
+        assert_eq!(max_length(&config).unwrap(), 8);
+        config.set_i64("qg.patchNameLength", 100).unwrap();
+        assert_eq!(max_length(&config).unwrap(), 100);
+        config.set_i64("qg.patchNameLength", 1000).unwrap();
+        assert_eq!(max_length(&config).unwrap(), MAX_COMPONENT_LENGTH);
+
+        for invalid in &[7, 0, -1] {
+            config.set_i64("qg.patchNameLength", *invalid).unwrap();
+            assert!(matches!(max_length(&config), Err(Error::Config(_))));
+        }
+        config.set_str("qg.patchNameLength", "long").unwrap();
+        assert!(matches!(max_length(&config), Err(Error::Git(_))));
+    }
+}
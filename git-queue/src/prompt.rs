@@ -0,0 +1,232 @@
+//! # Shell Prompt
+//!
+//! The current queue and its patches, for shell prompts, which run it on every
+//! command, so it must be fast even in large repositories. Only HEAD, the
+//! repository configuration and the log of the current queue are read, straight
+//! from the git directory: initializing libgit2 alone would take too long. For
+//! this reason queues in a custom [`RefLayout`] set in the global configuration
+//! are not found, unless the caller gives the prefixes.
+
+mod gitdir;
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    error::Error,
+    refs::{RefLayout, DEFAULT_BRANCH_PREFIX, DEFAULT_LOG_REF, DEFAULT_PATCH_REF},
+};
+use gitdir::{GitDir, Kind, ObjectId};
+
+/// The current queue and its patches.
+#[derive(Debug, Clone)]
+pub struct PromptInfo {
+    /// Name of the queue.
+    pub queue: String,
+    /// The top patch, if any patch is applied.
+    pub top: Option<String>,
+    /// Number of applied patches.
+    pub applied: usize,
+    /// Number of patches, applied or not.
+    pub total: usize,
+}
+
+/// The fields of a log entry needed by the prompt, see [`crate::queue::log`].
+#[derive(serde::Deserialize)]
+struct Meta {
+    applied: Vec<String>,
+    patches: HashMap<String, serde::de::IgnoredAny>,
+}
+
+impl PromptInfo {
+    /// The information of the current queue of the repository containing `path`,
+    /// `None` outside of a repository or if HEAD isn't a queue branch.
+    ///
+    /// The reference layout is read from `qg.branchPrefix` and `qg.logRef` in
+    /// the repository configuration, `branch_prefix` and `log_ref` override them.
+    pub fn current(
+        path: &Path,
+        branch_prefix: Option<&str>,
+        log_ref: Option<&str>,
+    ) -> Result<Option<Self>, Error> {
+        let dir = match GitDir::discover(path)? {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        let branch = match dir.head_branch()? {
+            Some(branch) => branch,
+            None => return Ok(None),
+        };
+
+        let config = dir.config_section("qg")?;
+        let get = |key, default| config.get(key).map_or(default, String::as_str);
+        let refs = RefLayout::new(
+            branch_prefix.unwrap_or_else(|| get("branchprefix", DEFAULT_BRANCH_PREFIX)),
+            log_ref.unwrap_or_else(|| get("logref", DEFAULT_LOG_REF)),
+            get("patchref", DEFAULT_PATCH_REF),
+        )?;
+        let queue = match refs.queue_of_branch(&branch) {
+            Some(queue) => queue,
+            None => return Ok(None),
+        };
+        let entry = match dir.resolve_ref(&refs.log_ref(queue))? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let meta = read_meta(&dir, &entry).map_err(|err| match err.kind() {
+            std::io::ErrorKind::InvalidData | std::io::ErrorKind::NotFound => {
+                Error::Inconsistency("queuelog reference")
+            }
+            _ => err.into(),
+        })?;
+
+        Ok(Some(Self {
+            queue: queue.to_string(),
+            top: meta.applied.last().cloned(),
+            applied: meta.applied.len(),
+            total: meta.patches.len(),
+        }))
+    }
+
+    /// Format the information with a format string, where `%q` is replaced by
+    /// the queue, `%t` by the top patch, or `-` if there is none, `%a` by the
+    /// number of applied patches, `%n` by the total number of patches and `%%`
+    /// by `%`. Other characters are copied as they are.
+    pub fn format(&self, format: &str) -> String {
+        let mut out = String::with_capacity(format.len() + self.queue.len());
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('q') => out.push_str(&self.queue),
+                Some('t') => out.push_str(self.top.as_deref().unwrap_or("-")),
+                Some('a') => out.push_str(&self.applied.to_string()),
+                Some('n') => out.push_str(&self.total.to_string()),
+                Some('%') => out.push('%'),
+                Some(other) => {
+                    out.push('%');
+                    out.push(other);
+                }
+                None => out.push('%'),
+            }
+        }
+
+        out
+    }
+}
+
+/// Read the `meta` blob of a log entry.
+fn read_meta(dir: &GitDir, entry: &ObjectId) -> std::io::Result<Meta> {
+    let invalid = |message| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let tree = match dir.read_object(entry)? {
+        (Kind::Commit, commit) => gitdir::commit_tree(&commit),
+        _ => None,
+    };
+    let tree = tree.ok_or_else(|| invalid("expected a log entry commit"))?;
+    let meta = match dir.read_object(&tree)? {
+        (Kind::Tree, tree) => gitdir::tree_entry(&tree, "meta"),
+        _ => None,
+    };
+    let meta = meta.ok_or_else(|| invalid("expected a meta blob"))?;
+    match dir.read_object(&meta)? {
+        (Kind::Blob, meta) => {
+            serde_json::from_slice(&meta).map_err(|_| invalid("expected meta content to be a JSON"))
+        }
+        _ => Err(invalid("expected a meta blob")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ctx::Ctx,
+        queue::Queue,
+        testing::{add_patch, base_branch, clone_of, setup},
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn current_queue_loose_and_packed() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let workdir = ctx.repo().workdir().unwrap().to_path_buf();
+        assert!(PromptInfo::current(&workdir, None, None).unwrap().is_none());
+
+        let queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        queue.switch_to().unwrap();
+        let mut queue = Queue::for_queue(&ctx, "q").unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        add_patch(&mut queue, "two");
+        let state = queue.state();
+        let applied = vec![("one".to_string(), state.patch("one").unwrap())];
+        let unapplied = vec![("two".to_string(), state.patch("two").unwrap())];
+        let base = state.base();
+        queue
+            .replace_stack("pop".to_string(), base, applied, unapplied, |_| {})
+            .unwrap();
+
+        let check = |path: &Path| {
+            let info = PromptInfo::current(path, None, None).unwrap().unwrap();
+            assert_eq!(info.format("%q %a/%n %t"), "q 1/2 one");
+        };
+        check(&workdir);
+        std::fs::create_dir(workdir.join("sub")).unwrap();
+        check(&workdir.join("sub"));
+
+        // Everything packed, the meta blobs likely as deltas of each other.
+        let status = std::process::Command::new("git")
+            .args(["gc", "--quiet", "--aggressive", "--prune=now"])
+            .current_dir(&workdir)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(!workdir.join(".git/refs/queuelogs/q").exists());
+        check(&workdir);
+
+        // Shell prompts run it on every command, it must be well under 10ms.
+        let fastest = (0..10)
+            .map(|_| {
+                let start = Instant::now();
+                PromptInfo::current(&workdir, None, None).unwrap();
+                start.elapsed()
+            })
+            .min()
+            .unwrap();
+        assert!(fastest < Duration::from_millis(10), "took {:?}", fastest);
+    }
+
+    #[test]
+    fn layout_from_repository_config() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let workdir = ctx.repo().workdir().unwrap().to_path_buf();
+        let mut config = ctx.repo().config().unwrap();
+        config.set_str("qg.branchPrefix", "stacks").unwrap();
+        config.set_str("qg.logRef", "refs/stacklogs").unwrap();
+        let ctx = Ctx::from_repo(git2::Repository::open(&workdir).unwrap()).unwrap();
+
+        let queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        queue.switch_to().unwrap();
+        let mut queue = Queue::for_queue(&ctx, "q").unwrap().unwrap();
+        add_patch(&mut queue, "one");
+
+        let info = PromptInfo::current(&workdir, None, None).unwrap().unwrap();
+        assert_eq!(info.format("%q %a/%n %t"), "q 1/1 one");
+        let info = PromptInfo::current(&workdir, Some("stacks"), Some("refs/stacklogs"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.queue, "q");
+        assert!(PromptInfo::current(&workdir, Some("queues"), None)
+            .unwrap()
+            .is_none());
+    }
+}
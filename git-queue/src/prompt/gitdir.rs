@@ -0,0 +1,584 @@
+//! # Git Directory Reader
+//!
+//! Just enough of git to read the log of a queue: HEAD, the repository
+//! configuration, loose and packed references and objects, loose or in packs.
+//! Initializing libgit2 takes several times longer than all of this, mostly to
+//! load TLS certificates the prompt never uses.
+//!
+//! Alternates and SHA-256 repositories are not supported.
+
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use miniz_oxide::{
+    inflate::{
+        decompress_to_vec_zlib,
+        stream::{inflate, InflateState},
+    },
+    DataFormat, MZError, MZFlush, MZStatus,
+};
+
+pub(super) type ObjectId = [u8; 20];
+
+/// How many symbolic references are followed, like git.
+const MAX_SYMBOLIC_DEPTH: usize = 5;
+
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Kind {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+}
+
+impl Kind {
+    fn from_name(name: &[u8]) -> io::Result<Self> {
+        match name {
+            b"commit" => Ok(Self::Commit),
+            b"tree" => Ok(Self::Tree),
+            b"blob" => Ok(Self::Blob),
+            b"tag" => Ok(Self::Tag),
+            _ => Err(invalid("unknown object type")),
+        }
+    }
+
+    fn from_code(code: u8) -> io::Result<Self> {
+        match code {
+            1 => Ok(Self::Commit),
+            2 => Ok(Self::Tree),
+            3 => Ok(Self::Blob),
+            4 => Ok(Self::Tag),
+            _ => Err(invalid("unknown object type")),
+        }
+    }
+}
+
+pub(super) struct GitDir {
+    /// The directory of HEAD, a subdirectory of `common` in linked worktrees.
+    dir: PathBuf,
+    /// The directory of the configuration, references and objects.
+    common: PathBuf,
+}
+
+impl GitDir {
+    /// Find the git directory of `path`, or of its closest parent, like git:
+    /// `$GIT_DIR`, a `.git` directory or file, or a bare repository.
+    pub(super) fn discover(path: &Path) -> io::Result<Option<Self>> {
+        if let Some(dir) = std::env::var_os("GIT_DIR") {
+            return Self::open(path.join(dir)).map(Some);
+        }
+
+        for dir in path.ancestors() {
+            let dotgit = dir.join(".git");
+            if dotgit.is_dir() {
+                return Self::open(dotgit).map(Some);
+            }
+            if dotgit.is_file() {
+                let content = fs::read_to_string(&dotgit)?;
+                return match content.trim_end().strip_prefix("gitdir: ") {
+                    Some(target) => Self::open(dir.join(target)).map(Some),
+                    None => Err(invalid("invalid .git file")),
+                };
+            }
+            if dir.join("HEAD").is_file() && dir.join("objects").is_dir() {
+                return Self::open(dir.to_path_buf()).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn open(dir: PathBuf) -> io::Result<Self> {
+        let common = match fs::read_to_string(dir.join("commondir")) {
+            Ok(common) => dir.join(common.trim_end()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => dir.clone(),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Self { dir, common })
+    }
+
+    /// The branch checked out, without `refs/heads/`, `None` if HEAD is detached.
+    pub(super) fn head_branch(&self) -> io::Result<Option<String>> {
+        let head = fs::read_to_string(self.dir.join("HEAD"))?;
+
+        Ok(head
+            .trim_end()
+            .strip_prefix("ref: refs/heads/")
+            .map(str::to_string))
+    }
+
+    /// The values of a section of the repository configuration, by lowercase key.
+    ///
+    /// Only the repository configuration is read, not the global one, nor the
+    /// files it includes.
+    pub(super) fn config_section(&self, section: &str) -> io::Result<HashMap<String, String>> {
+        match fs::read_to_string(self.common.join("config")) {
+            Ok(config) => Ok(parse_config_section(&config, section)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The object a reference points to, following symbolic references, `None`
+    /// if it doesn't exist.
+    pub(super) fn resolve_ref(&self, name: &str) -> io::Result<Option<ObjectId>> {
+        let mut name = name.to_string();
+        for _ in 0..MAX_SYMBOLIC_DEPTH {
+            let content = match fs::read_to_string(self.common.join(&name)) {
+                Ok(content) => content,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return self.packed_ref(&name),
+                Err(err) => return Err(err),
+            };
+
+            let content = content.trim_end();
+            match content.strip_prefix("ref: ") {
+                Some(target) => name = target.to_string(),
+                None => {
+                    return parse_hex(content)
+                        .map(Some)
+                        .ok_or_else(|| invalid("invalid reference"))
+                }
+            }
+        }
+
+        Err(invalid("too many symbolic references"))
+    }
+
+    fn packed_ref(&self, name: &str) -> io::Result<Option<ObjectId>> {
+        let packed = match fs::read_to_string(self.common.join("packed-refs")) {
+            Ok(packed) => packed,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        for line in packed.lines() {
+            // Comments and the objects peeled from the tag in the previous line.
+            if line.starts_with('#') || line.starts_with('^') {
+                continue;
+            }
+            if let Some((id, refname)) = line.split_once(' ') {
+                if refname == name {
+                    return parse_hex(id)
+                        .map(Some)
+                        .ok_or_else(|| invalid("invalid packed reference"));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Read an object, loose or packed.
+    pub(super) fn read_object(&self, id: &ObjectId) -> io::Result<(Kind, Vec<u8>)> {
+        let hex = to_hex(id);
+        let loose = self.common.join("objects").join(&hex[..2]).join(&hex[2..]);
+        match fs::read(loose) {
+            Ok(compressed) => return read_loose(&compressed),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        let packs = match fs::read_dir(self.common.join("objects").join("pack")) {
+            Ok(packs) => packs,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(missing(&hex)),
+            Err(err) => return Err(err),
+        };
+        for entry in packs {
+            let index = entry?.path();
+            if index.extension() != Some("idx".as_ref()) {
+                continue;
+            }
+            if let Some(offset) = find_in_index(&index, id)? {
+                let mut pack = BufReader::new(File::open(index.with_extension("pack"))?);
+                return self.read_packed(&mut pack, offset);
+            }
+        }
+
+        Err(missing(&hex))
+    }
+
+    fn read_packed(&self, pack: &mut BufReader<File>, offset: u64) -> io::Result<(Kind, Vec<u8>)> {
+        pack.seek(SeekFrom::Start(offset))?;
+
+        let mut byte = read_byte(pack)?;
+        let code = (byte >> 4) & 0x7;
+        let mut size = u64::from(byte & 0xf);
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = read_byte(pack)?;
+            size |= u64::from(byte & 0x7f)
+                .checked_shl(shift)
+                .ok_or_else(|| invalid("invalid object size"))?;
+            shift += 7;
+        }
+        let size = size
+            .try_into()
+            .map_err(|_| invalid("invalid object size"))?;
+
+        match code {
+            OFS_DELTA => {
+                let mut byte = read_byte(pack)?;
+                let mut distance = u64::from(byte & 0x7f);
+                while byte & 0x80 != 0 {
+                    byte = read_byte(pack)?;
+                    distance = ((distance + 1) << 7) | u64::from(byte & 0x7f);
+                }
+                let delta = inflate_from(pack, size)?;
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| invalid("invalid delta base"))?;
+                let (kind, base) = self.read_packed(pack, base_offset)?;
+
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            REF_DELTA => {
+                let mut base_id = [0; 20];
+                pack.read_exact(&mut base_id)?;
+                let delta = inflate_from(pack, size)?;
+                let (kind, base) = self.read_object(&base_id)?;
+
+                Ok((kind, apply_delta(&base, &delta)?))
+            }
+            code => Ok((Kind::from_code(code)?, inflate_from(pack, size)?)),
+        }
+    }
+}
+
+/// The tree of a commit.
+pub(super) fn commit_tree(commit: &[u8]) -> Option<ObjectId> {
+    let line = commit.split(|&b| b == b'\n').next()?;
+    let hex = std::str::from_utf8(line.strip_prefix(b"tree ")?).ok()?;
+
+    parse_hex(hex)
+}
+
+/// The object of an entry of a tree.
+pub(super) fn tree_entry(mut tree: &[u8], name: &str) -> Option<ObjectId> {
+    // Each entry is `<mode> <name>\0<id>`.
+    while !tree.is_empty() {
+        let nul = tree.iter().position(|&b| b == 0)?;
+        let (header, rest) = tree.split_at(nul);
+        let id = rest.get(1..21)?;
+        if header.splitn(2, |&b| b == b' ').nth(1)? == name.as_bytes() {
+            return id.try_into().ok();
+        }
+        tree = &rest[21..];
+    }
+
+    None
+}
+
+fn read_loose(compressed: &[u8]) -> io::Result<(Kind, Vec<u8>)> {
+    let mut object =
+        decompress_to_vec_zlib(compressed).map_err(|_| invalid("corrupt loose object"))?;
+    // The content follows a `<type> <size>\0` header.
+    let nul = object
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| invalid("corrupt loose object"))?;
+    let kind = object[..nul]
+        .split(|&b| b == b' ')
+        .next()
+        .ok_or_else(|| invalid("corrupt loose object"))?;
+    let kind = Kind::from_name(kind)?;
+    object.drain(..=nul);
+
+    Ok((kind, object))
+}
+
+/// The offset of an object in the pack of a version 2 index.
+fn find_in_index(index: &Path, id: &ObjectId) -> io::Result<Option<u64>> {
+    const HEADER: [u8; 8] = [0xff, b't', b'O', b'c', 0, 0, 0, 2];
+
+    let mut file = File::open(index)?;
+    let mut header = [0; 8 + 256 * 4];
+    file.read_exact(&mut header)?;
+    if header[..8] != HEADER {
+        return Err(invalid("unsupported pack index"));
+    }
+
+    // The number of objects with a first byte up to each value.
+    let fanout = |byte: usize| {
+        let start = 8 + byte * 4;
+        u64::from(u32::from_be_bytes(
+            header[start..start + 4].try_into().unwrap(),
+        ))
+    };
+    let total = fanout(255);
+    let ids = header.len() as u64;
+    let offsets = ids + total * (20 + 4);
+    let large_offsets = offsets + total * 4;
+
+    let mut low = match id[0] {
+        0 => 0,
+        first => fanout(usize::from(first) - 1),
+    };
+    let mut high = fanout(usize::from(id[0]));
+    let mut entry = [0; 20];
+    while low < high {
+        let mid = low + (high - low) / 2;
+        file.seek(SeekFrom::Start(ids + mid * 20))?;
+        file.read_exact(&mut entry)?;
+        match entry.cmp(id) {
+            std::cmp::Ordering::Less => low = mid + 1,
+            std::cmp::Ordering::Greater => high = mid,
+            std::cmp::Ordering::Equal => {
+                let mut offset = [0; 4];
+                file.seek(SeekFrom::Start(offsets + mid * 4))?;
+                file.read_exact(&mut offset)?;
+                let offset = u32::from_be_bytes(offset);
+                if offset & 0x8000_0000 == 0 {
+                    return Ok(Some(u64::from(offset)));
+                }
+
+                let mut offset64 = [0; 8];
+                let large = u64::from(offset & 0x7fff_ffff);
+                file.seek(SeekFrom::Start(large_offsets + large * 8))?;
+                file.read_exact(&mut offset64)?;
+                return Ok(Some(u64::from_be_bytes(offset64)));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Inflate a zlib stream of a known size, reading no more than needed.
+fn inflate_from(reader: &mut impl BufRead, size: usize) -> io::Result<Vec<u8>> {
+    let mut state = InflateState::new_boxed(DataFormat::Zlib);
+    // One byte more than expected, so a longer content is noticed.
+    let mut out = vec![0; size + 1];
+    let mut written = 0;
+    loop {
+        let input = reader.fill_buf()?;
+        let result = inflate(&mut state, input, &mut out[written..], MZFlush::None);
+        reader.consume(result.bytes_consumed);
+        written += result.bytes_written;
+
+        match result.status {
+            Ok(MZStatus::StreamEnd) if written == size => {
+                out.truncate(size);
+                return Ok(out);
+            }
+            Ok(MZStatus::Ok) | Err(MZError::Buf)
+                if result.bytes_consumed > 0 || result.bytes_written > 0 => {}
+            _ => return Err(invalid("corrupt packed object")),
+        }
+    }
+}
+
+/// Apply a delta of a pack to its base object.
+fn apply_delta(base: &[u8], mut delta: &[u8]) -> io::Result<Vec<u8>> {
+    let corrupt = || invalid("corrupt delta");
+
+    let base_size = delta_size(&mut delta)?;
+    let size = delta_size(&mut delta)?;
+    if base_size != base.len() {
+        return Err(corrupt());
+    }
+
+    let mut out = Vec::with_capacity(size);
+    while !delta.is_empty() {
+        let command = take_byte(&mut delta)?;
+        if command & 0x80 != 0 {
+            // Copy from the base, the bits tell which bytes of the offset and
+            // the length follow.
+            let mut offset = 0;
+            for i in 0..4 {
+                if command & (1 << i) != 0 {
+                    offset |= usize::from(take_byte(&mut delta)?) << (8 * i);
+                }
+            }
+            let mut length = 0;
+            for i in 0..3 {
+                if command & (0x10 << i) != 0 {
+                    length |= usize::from(take_byte(&mut delta)?) << (8 * i);
+                }
+            }
+            if length == 0 {
+                length = 0x10000;
+            }
+            let copied = base.get(offset..offset + length).ok_or_else(corrupt)?;
+            out.extend_from_slice(copied);
+        } else if command != 0 {
+            // Insert the next bytes.
+            let length = usize::from(command);
+            if delta.len() < length {
+                return Err(corrupt());
+            }
+            let (inserted, rest) = delta.split_at(length);
+            out.extend_from_slice(inserted);
+            delta = rest;
+        } else {
+            return Err(corrupt());
+        }
+    }
+
+    if out.len() != size {
+        return Err(corrupt());
+    }
+
+    Ok(out)
+}
+
+fn delta_size(delta: &mut &[u8]) -> io::Result<usize> {
+    let mut size = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = take_byte(delta)?;
+        size |= usize::from(byte & 0x7f)
+            .checked_shl(shift)
+            .ok_or_else(|| invalid("corrupt delta"))?;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+fn take_byte(delta: &mut &[u8]) -> io::Result<u8> {
+    let (&byte, rest) = delta
+        .split_first()
+        .ok_or_else(|| invalid("corrupt delta"))?;
+    *delta = rest;
+
+    Ok(byte)
+}
+
+fn read_byte(reader: &mut impl Read) -> io::Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+
+    Ok(byte[0])
+}
+
+fn parse_config_section(config: &str, section: &str) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    let mut in_section = false;
+    for line in config.lines() {
+        let mut line = line.trim_start();
+        if let Some(header) = line.strip_prefix('[') {
+            // `[section]`, `[section "subsection"]` or `[section.subsection]`,
+            // possibly followed by a variable.
+            let end = match header.find(']') {
+                Some(end) => end,
+                None => {
+                    in_section = false;
+                    continue;
+                }
+            };
+            in_section = header[..end].trim().eq_ignore_ascii_case(section);
+            line = header[end + 1..].trim_start();
+        }
+        if !in_section || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        // Variables without a value are booleans, not needed here.
+        if let Some((key, value)) = line.split_once('=') {
+            values.insert(key.trim().to_ascii_lowercase(), config_value(value));
+        }
+    }
+
+    values
+}
+
+/// The value of a configuration variable, without quotes, escapes and comments.
+fn config_value(raw: &str) -> String {
+    let mut value = String::with_capacity(raw.len());
+    let mut quoted = false;
+    let mut chars = raw.trim().chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' | ';' if !quoted => break,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some('b') => {
+                    value.pop();
+                }
+                Some(c) => value.push(c),
+                None => {}
+            },
+            c => value.push(c),
+        }
+    }
+
+    value.trim_end().to_string()
+}
+
+fn parse_hex(hex: &str) -> Option<ObjectId> {
+    if hex.len() != 40 {
+        return None;
+    }
+
+    let mut id = [0; 20];
+    for (byte, pair) in id.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+
+    Some(id)
+}
+
+fn to_hex(id: &ObjectId) -> String {
+    id.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn missing(hex: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("object {} not found", hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_values() {
+        let config = "\
+[core]
+\tbare = false
+[qg \"sub\"]
+\tlogRef = refs/sub
+[QG]
+\tbranchPrefix = \"my queues\" # comment
+\tlogref = refs/first
+\tlogRef = refs/last ; comment
+\tsignoff
+[qg] patchRef = \"refs/with\\\"quote\"
+[other]
+\tlogRef = refs/other
+";
+        let values = parse_config_section(config, "qg");
+
+        assert_eq!(values["branchprefix"], "my queues");
+        assert_eq!(values["logref"], "refs/last");
+        assert_eq!(values["patchref"], "refs/with\"quote");
+        assert_eq!(values.len(), 3);
+    }
+
+    #[test]
+    fn delta_copies_and_inserts() {
+        let base = b"hello world";
+        // The sizes, then copy 6 bytes at 0, insert "there" and copy 2 bytes at 9.
+        let mut delta = vec![11, 13, 0x80 | 0x10, 6, 5];
+        delta.extend_from_slice(b"there");
+        delta.extend_from_slice(&[0x80 | 0x01 | 0x10, 9, 2]);
+
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"hello thereld");
+        assert!(apply_delta(b"short", &delta).is_err());
+    }
+}
@@ -1,9 +1,12 @@
-use git2::{BranchType, ErrorCode};
+use git2::{build::CheckoutBuilder, BranchType, ErrorCode, Oid, Signature, Tree};
 
-use self::log::QueueState;
-use crate::{ctx::Ctx, error::Error};
+use self::{
+    log::{PatchReview, QueueState, SentSeries},
+    patch::Patch,
+};
+use crate::{ctx::Ctx, error::Error, naming};
 
-mod log;
+pub mod log;
 pub mod patch;
 
 pub struct Queue<'r> {
@@ -14,16 +17,16 @@ pub struct Queue<'r> {
 
 impl<'r> Queue<'r> {
     pub fn for_queue(ctx: &'r Ctx, queue: &str) -> Result<Option<Self>, Error> {
+        naming::validate_queue_name(queue)?;
         let branch = match ctx
             .repo()
-            .find_branch(&Self::gitref_name(queue), git2::BranchType::Local)
+            .find_branch(&ctx.refs().queue_branch(queue), git2::BranchType::Local)
         {
             Ok(branch) => branch,
             Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
-            Err(err) if err.code() == ErrorCode::InvalidSpec => return Err(Error::InvalidName),
             Err(err) => return Err(err.into()),
         };
-        let state = QueueState::current_for_queue(ctx.repo(), queue)?;
+        let state = QueueState::current_for_queue(ctx, queue)?;
 
         Ok(Some(Self { branch, state, ctx }))
     }
@@ -36,7 +39,7 @@ impl<'r> Queue<'r> {
             return Ok(None);
         };
         let name = branch.name()?.unwrap();
-        if let Some((_, queue)) = name.split_once("queues/") {
+        if let Some(queue) = ctx.refs().queue_of_branch(name) {
             Self::for_queue(ctx, queue)
         } else {
             Ok(None)
@@ -48,13 +51,28 @@ impl<'r> Queue<'r> {
         name: &str,
         branch: git2::Branch<'r>,
     ) -> Result<Option<Self>, Error> {
+        Self::check_new_name(ctx, name)?;
+
         let base = branch.get().peel_to_commit()?;
-        let queue_branch = match ctx.repo().branch(&Self::gitref_name(name), &base, false) {
+        let queue_branch = match ctx
+            .repo()
+            .branch(&ctx.refs().queue_branch(name), &base, false)
+        {
             Ok(b) => b,
             Err(err) if err.code() == ErrorCode::Exists => return Ok(None),
             Err(err) => return Err(err.into()),
         };
-        let state = QueueState::new(ctx.repo(), name, &branch)?;
+        let state = match QueueState::new(ctx, name, &branch) {
+            Ok(state) => state,
+            Err(err) => {
+                // Don't leave a queue branch without a log behind.
+                let mut queue_branch = queue_branch;
+                if let Err(del_err) = queue_branch.delete() {
+                    tracing::warn!("failed to delete queue branch: {}", del_err);
+                }
+                return Err(err);
+            }
+        };
 
         Ok(Some(Self {
             branch: queue_branch,
@@ -63,6 +81,51 @@ impl<'r> Queue<'r> {
         }))
     }
 
+    /// Create a queue from an existing log, e.g. one fetched from a remote.
+    ///
+    /// The queue branch and the patches are created from the state of the given
+    /// log entry. Returns `None` if the queue already exists.
+    pub fn from_log(ctx: &'r Ctx, name: &str, log: Oid) -> Result<Option<Self>, Error> {
+        Self::check_new_name(ctx, name)?;
+        let repo = ctx.repo();
+        let log_ref = ctx.refs().log_ref(name);
+        if repo.find_reference(&log_ref).is_ok() {
+            return Ok(None);
+        }
+
+        let state = QueueState::at_log_entry(ctx, name, log)?;
+        let head = repo.find_commit(state.head())?;
+        let branch = match repo.branch(&ctx.refs().queue_branch(name), &head, false) {
+            Ok(branch) => branch,
+            Err(err) if err.code() == ErrorCode::Exists => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        for (patch, oid) in state.applied().chain(state.unapplied()) {
+            Patch::adopt(ctx, name, patch, oid)?;
+        }
+        repo.reference(&log_ref, log, false, &format!("create {} from log", name))?;
+
+        Ok(Some(Self { branch, state, ctx }))
+    }
+
+    /// Check that a queue can be created with the given name.
+    fn check_new_name(ctx: &Ctx, name: &str) -> Result<(), Error> {
+        naming::validate_queue_name(name)?;
+        // Queues are branches, so a queue can't be in the namespace of another.
+        for queue in ctx.repo().branches(Some(BranchType::Local))? {
+            let (queue, _) = queue?;
+            let queue = match queue.name()?.and_then(|n| ctx.refs().queue_of_branch(n)) {
+                Some(queue) => queue,
+                None => continue,
+            };
+            if naming::in_namespace(queue, name) || naming::in_namespace(name, queue) {
+                return Err(Error::NestedQueue(name.to_string(), queue.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn list(ctx: &'r Ctx) -> Result<impl Iterator<Item = Result<Queue<'r>, Error>>, Error> {
         let branches = ctx.repo().branches(Some(BranchType::Local))?;
 
@@ -70,8 +133,7 @@ impl<'r> Queue<'r> {
             r.map_err(Error::Git)
                 .and_then(|(b, _)| {
                     let name = b.name()?.ok_or(Error::NonUtf8)?;
-                    if name.starts_with("queues/") {
-                        let name = name.split('/').nth(1).unwrap();
+                    if let Some(name) = ctx.refs().queue_of_branch(name) {
                         Self::for_queue(ctx, name)?
                             .ok_or_else(|| Error::Inconsistency("queuelog"))
                             .map(Some)
@@ -87,10 +149,29 @@ impl<'r> Queue<'r> {
         self.state.name()
     }
 
+    /// The context this queue was loaded from.
+    pub fn ctx(&self) -> &'r Ctx {
+        self.ctx
+    }
+
     pub fn base_name(&self) -> &str {
         self.state.base_name()
     }
 
+    /// The description of this queue, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.state.description()
+    }
+
+    /// The current state of this queue.
+    pub fn state(&self) -> &QueueState {
+        &self.state
+    }
+
+    pub fn patches_num(&self) -> usize {
+        self.state.patches_num()
+    }
+
     pub fn can_close(&self) -> bool {
         self.state.patches_num() == 0
     }
@@ -103,6 +184,389 @@ impl<'r> Queue<'r> {
         self.ctx.checkout_branch(&self.branch, true)
     }
 
+    /// Create a new patch on top of the queue.
+    ///
+    /// The queue is moved to the new patch. If this is the current queue, the
+    /// index and working tree are updated to match it.
+    pub fn new_patch(
+        &mut self,
+        name: &str,
+        author: &Signature<'_>,
+        message: &str,
+        tree: &Tree<'_>,
+    ) -> Result<Patch<'r>, Error> {
+        if self.state.has_patch(name) {
+            return Err(Error::AlreadyExists("patch"));
+        }
+
+        let parent = self.ctx.repo().find_commit(self.state.head())?;
+        let patch = Patch::create(self.ctx, self.name(), name, author, message, tree, &parent)?;
+
+        let log_message = format!("new {}", name);
+        let moved = self.move_head(patch.id(), &log_message);
+        let created = moved.and_then(|()| {
+            let next = self.state.create_next(self.ctx, log_message.clone(), |s| {
+                s.upsert_patch(name.to_string(), patch.id());
+                Ok(())
+            });
+            if next.is_err() {
+                if let Err(undo_err) = self.move_head(parent.id(), &log_message) {
+                    tracing::warn!("failed to move the queue back: {}", undo_err);
+                }
+            }
+            next
+        });
+        let (state, ()) = match created {
+            Ok(created) => created,
+            Err(err) => {
+                // Don't leave a patch that isn't in the queue behind.
+                if let Err(del_err) = self.ctx.repo().find_reference(patch.ref_name())?.delete() {
+                    tracing::warn!("failed to delete patch reference: {}", del_err);
+                }
+                return Err(err);
+            }
+        };
+        self.state = state;
+
+        Ok(patch)
+    }
+
+    /// Derive a patch name from a commit subject, unique in the queue and in
+    /// `taken`, see [`naming::unique_name`].
+    pub fn unique_patch_name(&self, subject: &str, taken: &[String]) -> Result<String, Error> {
+        let max_length = naming::max_length(self.ctx.config())?;
+        Ok(naming::unique_name(subject, max_length, |name| {
+            self.state.has_patch(name) || taken.iter().any(|t| t == name)
+        }))
+    }
+
+    /// Replace the patches of the queue with existing commits, e.g. ones created
+    /// by another tool.
+    ///
+    /// A reference is created for each patch, and the queue is moved to the top
+    /// applied patch, or `base` if there is none. See [`QueueState::set_stack`]
+    /// for the order of the patches.
+    pub fn adopt_patches(
+        &mut self,
+        message: String,
+        base: Oid,
+        applied: Vec<(String, Oid)>,
+        unapplied: Vec<(String, Oid)>,
+    ) -> Result<(), Error> {
+        for (name, oid) in applied.iter().chain(&unapplied) {
+            Patch::adopt(self.ctx, self.name(), name, *oid)?;
+        }
+
+        let (state, ()) = self.state.create_next(self.ctx, message, |s| {
+            s.set_stack(base, applied, unapplied);
+            Ok(())
+        })?;
+        if state.head() != self.state.head() {
+            self.move_head(state.head(), state.message())?;
+        }
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Set or remove the description of this queue.
+    pub fn describe(&mut self, description: Option<String>) -> Result<(), Error> {
+        let (state, ()) = self
+            .state
+            .create_next(self.ctx, "describe".to_string(), |s| {
+                s.set_description(description);
+                Ok(())
+            })?;
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Record in the queue log that the given patches were exported.
+    pub fn record_export(&mut self, patches: Vec<String>) -> Result<(), Error> {
+        let plural = if patches.len() == 1 { "" } else { "es" };
+        let message = format!("export {} patch{}", patches.len(), plural);
+        let (state, ()) = self.state.create_next(self.ctx, message, |s| {
+            s.set_exported(patches);
+            Ok(())
+        })?;
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Snapshot the current patches as the next version of the series, named
+    /// `v<n>` after the highest numbered version, returning its name.
+    pub fn bump_version(&mut self) -> Result<String, Error> {
+        let name = self.state.next_version_name();
+        let message = format!("version bump {}", name);
+        let (state, ()) = self.state.create_next(self.ctx, message, |s| {
+            s.add_version(name.clone());
+            Ok(())
+        })?;
+        self.state = state;
+
+        Ok(name)
+    }
+
+    /// Record in the queue log the Message-IDs of a patch series sent by email.
+    pub fn record_mail(&mut self, message: String, series: SentSeries) -> Result<(), Error> {
+        let (state, ()) = self.state.create_next(self.ctx, message, |s| {
+            s.set_mail(series);
+            Ok(())
+        })?;
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Record in the queue log the patches pushed as review branches, see
+    /// [`crate::remote::QueueRemote::push_branches`].
+    pub fn record_branches(
+        &mut self,
+        remote: &str,
+        prefix: &str,
+        patches: Vec<(String, Oid)>,
+    ) -> Result<(), Error> {
+        let message = format!("push-branches to {}", remote);
+        let (state, ()) = self.state.create_next(self.ctx, message, |s| {
+            s.set_pushed_branches(remote, prefix, patches);
+            Ok(())
+        })?;
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Record in the queue log the reviews of patches, see [`crate::review`].
+    ///
+    /// A `None` review forgets the review of the patch, e.g. after closing it.
+    pub fn record_reviews(
+        &mut self,
+        message: String,
+        reviews: Vec<(String, Option<PatchReview>)>,
+    ) -> Result<(), Error> {
+        let (state, ()) = self.state.create_next(self.ctx, message, |s| {
+            for (patch, review) in reviews {
+                s.set_review(&patch, review);
+            }
+            Ok(())
+        })?;
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Move the queue to a later entry of its log, e.g. one fetched from a remote.
+    ///
+    /// The patches and the queue branch are updated to the state of the entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Diverged`] if the entry doesn't descend from the current
+    /// state of the queue.
+    pub fn fast_forward(&mut self, log: Oid, message: &str) -> Result<(), Error> {
+        let repo = self.ctx.repo();
+        let current = self.state.oid().expect("queue state is committed");
+        if log == current {
+            return Ok(());
+        }
+        if !repo.graph_descendant_of(log, current)? {
+            return Err(Error::Diverged(self.name().to_string()));
+        }
+
+        let state = self.state.at_entry(repo, log)?;
+
+        // Claim the log first, so nothing is moved if the queue changed meanwhile.
+        match repo.reference_matching(self.state.gitref(), log, true, current, message) {
+            Ok(_) => {}
+            Err(err) if err.code() == ErrorCode::Modified => {
+                return Err(Error::Modified("queuelog reference"))
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        if let Err(err) = self.move_to(&state, message) {
+            self.restore_log(log, current);
+            return Err(err);
+        }
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Replace the patches of the queue with a merge of its log and another one,
+    /// see [`crate::sync`].
+    ///
+    /// Unlike [`Queue::adopt_patches`], the patch references are moved to the
+    /// given commits, and the ones of patches not in the stack are deleted.
+    /// `modify` is called with the new state, e.g. to record the merged log.
+    pub fn replace_stack(
+        &mut self,
+        message: String,
+        base: Oid,
+        applied: Vec<(String, Oid)>,
+        unapplied: Vec<(String, Oid)>,
+        modify: impl FnOnce(&mut QueueState),
+    ) -> Result<(), Error> {
+        for (name, _) in applied.iter().chain(&unapplied) {
+            naming::validate_patch_name(name)?;
+        }
+
+        let previous = self.state.oid().expect("queue state is committed");
+        let (state, ()) = self.state.create_next(self.ctx, message, |s| {
+            s.set_stack(base, applied, unapplied);
+            modify(s);
+            Ok(())
+        })?;
+        if let Err(err) = self.move_to(&state, state.message()) {
+            self.restore_log(state.oid().expect("state was committed"), previous);
+            return Err(err);
+        }
+        self.state = state;
+
+        Ok(())
+    }
+
+    /// Rewrite the messages of patches, e.g. to edit their trailers.
+    ///
+    /// `reword` is called with the name and message of each patch, returning the
+    /// new message, or `None` to keep it. The applied patches above a reworded
+    /// one are recreated on top of it. Returns the names of the reworded patches.
+    pub fn reword_patches(
+        &mut self,
+        message: String,
+        mut reword: impl FnMut(&str, &str) -> Option<String>,
+    ) -> Result<Vec<String>, Error> {
+        let repo = self.ctx.repo();
+        let mut reworded = Vec::new();
+        let mut rewrite = |name: &str, oid: Oid, parent: Option<Oid>| -> Result<Oid, Error> {
+            let commit = repo.find_commit(oid)?;
+            let current = commit.message().ok_or(Error::NonUtf8)?;
+            let new_message = reword(name, current);
+            if new_message.is_some() {
+                reworded.push(name.to_string());
+            }
+            let parent = match parent {
+                Some(parent) => repo.find_commit(parent)?,
+                None if new_message.is_some() => commit.parent(0)?,
+                None => return Ok(oid),
+            };
+            if new_message.is_none() && commit.parent_id(0).ok() == Some(parent.id()) {
+                return Ok(oid);
+            }
+
+            let new_oid = self.ctx.commit(
+                None,
+                &commit.author(),
+                new_message.as_deref().unwrap_or(current),
+                &commit.tree()?,
+                &[&parent],
+            )?;
+            Ok(new_oid)
+        };
+
+        let base = self.state.base();
+        let mut tip = base;
+        let mut applied = Vec::new();
+        for (name, oid) in self.state.applied() {
+            tip = rewrite(name, oid, Some(tip))?;
+            applied.push((name.to_string(), tip));
+        }
+        let mut unapplied = Vec::new();
+        for (name, oid) in self.state.unapplied() {
+            unapplied.push((name.to_string(), rewrite(name, oid, None)?));
+        }
+        // Unapplied patches are kept as a stack, the next one to be pushed is the last.
+        unapplied.reverse();
+
+        if !reworded.is_empty() {
+            self.replace_stack(message, base, applied, unapplied, |_| {})?;
+        }
+
+        Ok(reworded)
+    }
+
+    /// Move the queue branch and the patch references from the current state to
+    /// `state`, whose log entry is already committed.
+    ///
+    /// The branch is moved first, as checking it out fails if the working tree
+    /// has conflicting changes. If anything fails, what was moved is put back.
+    fn move_to(&mut self, state: &QueueState, message: &str) -> Result<(), Error> {
+        let head = self.state.head();
+        if state.head() != head {
+            self.move_head(state.head(), message)?;
+        }
+        if let Err(err) = self.update_patch_refs(&self.state, state, message) {
+            if let Err(undo_err) = self.update_patch_refs(state, &self.state, message) {
+                tracing::warn!("failed to restore patch references: {}", undo_err);
+            }
+            if state.head() != head {
+                if let Err(undo_err) = self.move_head(head, message) {
+                    tracing::warn!("failed to move the queue back: {}", undo_err);
+                }
+            }
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    /// Point the log back from `from` to `to`, after failing to move the queue to
+    /// the state committed in `from`, so the operation can be retried.
+    fn restore_log(&self, from: Oid, to: Oid) {
+        let repo = self.ctx.repo();
+        let message = "undo failed operation";
+        // If another process moved the log meanwhile, its entry is kept.
+        if let Err(err) = repo.reference_matching(self.state.gitref(), to, true, from, message) {
+            tracing::warn!("failed to restore the queue log: {}", err);
+        }
+    }
+
+    /// Point the patch references to the patches of `to`, deleting the ones of
+    /// `from` that aren't in it.
+    fn update_patch_refs(
+        &self,
+        from: &QueueState,
+        to: &QueueState,
+        message: &str,
+    ) -> Result<(), Error> {
+        let repo = self.ctx.repo();
+        for (patch, oid) in to.applied().chain(to.unapplied()) {
+            naming::validate_patch_name(patch)?;
+            let patch_ref = self.ctx.refs().patch_ref(self.name(), patch);
+            repo.reference(&patch_ref, oid, true, message)?;
+        }
+        for (patch, _) in from.applied().chain(from.unapplied()) {
+            if !to.has_patch(patch) {
+                let patch_ref = self.ctx.refs().patch_ref(self.name(), patch);
+                repo.find_reference(&patch_ref)?.delete()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move the queue branch to the given commit, checking it out if this is the
+    /// current queue.
+    fn move_head(&mut self, oid: Oid, message: &str) -> Result<(), Error> {
+        let repo = self.ctx.repo();
+        if self.is_current() {
+            let commit = repo.find_commit(oid)?;
+            // The commit may have been created from the index, in which case the
+            // working tree is already up to date.
+            let index_tree = repo.index()?.write_tree().ok();
+            if index_tree != Some(commit.tree_id()) {
+                repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))?;
+            }
+        }
+
+        let reference = self.branch.get_mut().set_target(oid, message)?;
+        self.branch = git2::Branch::wrap(reference);
+
+        Ok(())
+    }
+
     pub fn close(mut self) -> Result<(), Error> {
         assert!(!self.is_current(), "tried to close current queue");
         assert_eq!(
@@ -124,8 +588,193 @@ impl<'r> Queue<'r> {
             Err(err) => Err(err.into()),
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{FetchOutcome, QueueRemote};
+    use crate::testing::{add_patch, base_branch, clone_of, setup};
+
+    fn ref_target(ctx: &Ctx, name: &str) -> Option<Oid> {
+        ctx.repo().refname_to_id(name).ok()
+    }
+
+    #[test]
+    fn new_patch_on_stale_state() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+        let mut stale = Queue::for_queue(&ctx, "q").unwrap().unwrap();
+        // Only the log changes, so the branch can still be moved.
+        queue.describe(Some("A queue.".to_string())).unwrap();
+        let head = queue.state().head();
+
+        let repo = ctx.repo();
+        let tree = repo.find_commit(head).unwrap().tree().unwrap();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        let result = stale.new_patch("two", &author, "two", &tree);
+        assert!(matches!(result, Err(Error::Modified(_))));
+
+        // The queue is left as the other handle made it.
+        let branch = format!("refs/heads/{}", ctx.refs().queue_branch("q"));
+        assert_eq!(ref_target(&ctx, &branch), Some(head));
+        assert_eq!(ref_target(&ctx, &ctx.refs().patch_ref("q", "two")), None);
+    }
+
+    #[test]
+    fn failed_fast_forward_can_be_retried() {
+        let (dir, remote) = setup();
+        let ours = clone_of(&dir, "ours", &remote);
+        let queue = Queue::initialize(&ours, "q", base_branch(&ours))
+            .unwrap()
+            .unwrap();
+        queue.switch_to().unwrap();
+        let mut queue = Queue::for_queue(&ours, "q").unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        QueueRemote::connect(&ours, None)
+            .unwrap()
+            .publish(&queue)
+            .unwrap();
+
+        let theirs = clone_of(&dir, "theirs", &remote);
+        let origin = QueueRemote::connect(&theirs, None).unwrap();
+        origin.fetch("q").unwrap();
+        let mut their_queue = Queue::for_queue(&theirs, "q").unwrap().unwrap();
+        add_patch(&mut their_queue, "two");
+        origin.publish(&their_queue).unwrap();
+
+        // An untracked file in the way of the checkout.
+        let file = ours.repo().workdir().unwrap().join("two");
+        std::fs::write(&file, "mine").unwrap();
+        let log = ref_target(&ours, &ours.refs().log_ref("q"));
+        let origin = QueueRemote::connect(&ours, None).unwrap();
+        assert!(origin.fetch("q").is_err());
+        assert_eq!(ref_target(&ours, &ours.refs().log_ref("q")), log);
+        assert_eq!(ref_target(&ours, &ours.refs().patch_ref("q", "two")), None);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "mine");
+
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(origin.fetch("q").unwrap(), FetchOutcome::Updated);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "two");
+        let queue = Queue::for_queue(&ours, "q").unwrap().unwrap();
+        assert_eq!(queue.state().oid(), their_queue.state().oid());
+    }
+
+    #[test]
+    fn failed_replace_stack_can_be_retried() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        queue.switch_to().unwrap();
+        let mut queue = Queue::for_queue(&ctx, "q").unwrap().unwrap();
+        add_patch(&mut queue, "one");
+
+        // A commit adding a file on top of the queue, outside of it.
+        let repo = ctx.repo();
+        let head = repo.find_commit(queue.state().head()).unwrap();
+        let mut builder = repo.treebuilder(Some(&head.tree().unwrap())).unwrap();
+        builder
+            .insert("two", repo.blob(b"two").unwrap(), 0o100644)
+            .unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        let two = repo
+            .commit(None, &author, &author, "two", &tree, &[&head])
+            .unwrap();
+        let stack = || {
+            let one = ("one".to_string(), head.id());
+            vec![one, ("two".to_string(), two)]
+        };
+
+        // An untracked file in the way of the checkout.
+        let file = repo.workdir().unwrap().join("two");
+        std::fs::write(&file, "mine").unwrap();
+        let log = ref_target(&ctx, &ctx.refs().log_ref("q"));
+        let base = queue.state().base();
+        let result = queue.replace_stack("add two".to_string(), base, stack(), vec![], |_| {});
+        assert!(result.is_err());
+        assert_eq!(ref_target(&ctx, &ctx.refs().log_ref("q")), log);
+        assert_eq!(ref_target(&ctx, &ctx.refs().patch_ref("q", "two")), None);
+        assert_eq!(queue.state().head(), head.id());
+
+        std::fs::remove_file(&file).unwrap();
+        queue
+            .replace_stack("add two".to_string(), base, stack(), vec![], |_| {})
+            .unwrap();
+        assert_eq!(queue.state().head(), two);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "two");
+    }
+
+    #[test]
+    fn nested_queue_names() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        base_branch(&ctx);
+        let main = || ctx.repo().find_branch("main", BranchType::Local).unwrap();
+        Queue::initialize(&ctx, "team/feature", main())
+            .unwrap()
+            .unwrap();
+
+        for name in &["team", "team/feature/part"] {
+            match Queue::initialize(&ctx, name, main()) {
+                Err(Error::NestedQueue(queue, other)) => {
+                    assert_eq!((queue.as_str(), other.as_str()), (*name, "team/feature"))
+                }
+                other => panic!("created `{}`: {:?}", name, other.map(|q| q.is_some())),
+            }
+        }
+        assert!(matches!(
+            Queue::initialize(&ctx, "team/a b", main()),
+            Err(Error::InvalidName(_))
+        ));
+        assert!(Queue::initialize(&ctx, "team/other", main())
+            .unwrap()
+            .is_some());
+        assert!(Queue::initialize(&ctx, "teams", main()).unwrap().is_some());
+    }
+
+    #[test]
+    fn nested_queues_are_listed_switched_and_closed() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        base_branch(&ctx);
+        let main = || ctx.repo().find_branch("main", BranchType::Local).unwrap();
+        for name in ["team/feature", "team/other", "solo"] {
+            Queue::initialize(&ctx, name, main()).unwrap().unwrap();
+        }
+
+        let mut names: Vec<_> = Queue::list(&ctx)
+            .unwrap()
+            .map(|q| q.unwrap().name().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["solo", "team/feature", "team/other"]);
+
+        Queue::for_queue(&ctx, "team/feature")
+            .unwrap()
+            .unwrap()
+            .switch_to()
+            .unwrap();
+        let current = Queue::current(&ctx).unwrap().unwrap();
+        assert_eq!(current.name(), "team/feature");
+        assert!(current.is_current());
 
-    fn gitref_name(queue: &str) -> String {
-        format!("queues/{}", queue)
+        let other = Queue::for_queue(&ctx, "team/other").unwrap().unwrap();
+        let log = other.state().gitref().to_string();
+        other.close().unwrap();
+        assert!(Queue::for_queue(&ctx, "team/other").unwrap().is_none());
+        assert_eq!(ref_target(&ctx, &log), None);
+        assert!(Queue::for_queue(&ctx, "team/feature").unwrap().is_some());
+        // The namespace is still taken by the remaining queue.
+        assert!(matches!(
+            Queue::initialize(&ctx, "team", main()),
+            Err(Error::NestedQueue(..))
+        ));
     }
 }
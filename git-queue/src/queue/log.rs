@@ -1,6 +1,7 @@
 //! # Queue State Log
 //!
-//! Each queue have a log attached to it (in the ref `refs/queuelogs/<queue>`). The
+//! Each queue have a log attached to it (in the ref `refs/queuelogs/<queue>` by
+//! default, see [`crate::refs`]). The
 //! purpose of this log is to track each operation executed in the queue.
 //!
 //! Each entry in the log ensures it have the proper references to objects it needs
@@ -32,23 +33,49 @@
 //!   * `applied`: a list of the applied patches.
 //!   * `unapplied`: same of `applied`, but for unapplied patches.
 //!   * `patches`: a map of each patch name to its commit OID when the entry was created.
+//!   * `description: <string or missing>`: the description of the queue, used as
+//!     its cover letter. Carried to the next entries, so the history of its edits
+//!     is kept in the log.
+//!   * `versions: <list or missing>`: the versions of the patch series, sent for
+//!     review, in the order they were created. Each version has a `name` and the
+//!     `entry`, the OID of the log entry with the patches of the version. Carried
+//!     to the next entries.
+//!   * `mail: <object or missing>`: the Message-IDs of the patch series sent by the
+//!     operation, only present in entries created by `qg mail`. It has the fields
+//!     `cover_letter`, the Message-ID of the cover letter or `null`, and `patches`,
+//!     a list of `[<patch name>, <Message-ID>]` pairs in the order they were sent.
+//!   * `exported: <list or missing>`: the names of the patches exported by the
+//!     operation, only present in entries created by `qg export`.
+//!   * `merged: <sha1 or missing>`: the OID of the other predecessor of an entry
+//!     merging two diverged logs, only present in entries created by `qg sync`.
+//!   * `branches: <list or missing>`: the review branches pushed by `qg
+//!     push-branches`, one object for each `remote` and `prefix` they were pushed
+//!     with, where `patches` maps each patch name to the commit last pushed.
+//!     Carried to the next entries.
+//!   * `reviews: <map or missing>`: the reviews of the patches created by `qg
+//!     review`, mapping each patch name to an object with the `id` of the review
+//!     in the forge, its `url` and `status`, if known, and the `commit` of the
+//!     patch last sent for review. Carried to the next entries.
 //!
 //! ### Parents
 //!
 //! Each entry commit have the following parents, in order:
 //!
 //! * The previous entry commit.
+//! * The merged entry commit, if any.
 //! * The branch head commit when the entry was created.
 //! * All applied or unapplied patches commits when the entry was created.
 
-use crate::error::Error;
+use crate::{ctx::Ctx, error::Error};
 use git2::{Oid, Repository, Tree};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 /// The queue state at a specific point in time.
+#[derive(Clone)]
 pub struct QueueState {
     oid: Option<Oid>,
     gitref_name: String,
+    name: String,
     entry: LogEntryV1,
 }
 
@@ -59,14 +86,56 @@ impl QueueState {
     ///
     /// If the queue does not exist, this function will return a [`git2::Error`]
     /// instance with code [`git2::ErrorCode::NotFound`].
-    pub fn current_for_queue(repo: &Repository, queue: &str) -> Result<Self, Error> {
-        let gitref_name = Self::gitref_name(queue);
-        let gitref = repo.find_reference(&gitref_name)?;
-        let mut commit = None;
-        let mut maybe_inconsistent = || {
-            let c = gitref.peel_to_commit()?;
-            let tree = c.tree()?;
-            commit = Some(c);
+    pub fn current_for_queue(ctx: &Ctx, queue: &str) -> Result<Self, Error> {
+        let gitref_name = ctx.refs().log_ref(queue);
+        let gitref = ctx.repo().find_reference(&gitref_name)?;
+        let commit = gitref
+            .peel_to_commit()
+            .map_err(|_| Error::Inconsistency("queuelog reference"))?;
+
+        Self::from_commit(ctx.repo(), gitref_name, queue.to_string(), &commit)
+    }
+
+    /// Get the state of the given entry of the log of a queue.
+    pub fn at_log_entry(ctx: &Ctx, queue: &str, oid: Oid) -> Result<Self, Error> {
+        let commit = ctx.repo().find_commit(oid)?;
+
+        Self::from_commit(
+            ctx.repo(),
+            ctx.refs().log_ref(queue),
+            queue.to_string(),
+            &commit,
+        )
+    }
+
+    /// Get the state before this one in the log, if any.
+    pub fn previous(&self, repo: &Repository) -> Result<Option<Self>, Error> {
+        match self.entry.previous {
+            Some(LogOid(oid)) => {
+                let commit = repo.find_commit(oid)?;
+                self.in_same_log(repo, &commit).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The entry before this one in the log, if any.
+    pub fn previous_oid(&self) -> Option<Oid> {
+        self.entry.previous.map(|oid| oid.0)
+    }
+
+    fn in_same_log(&self, repo: &Repository, commit: &git2::Commit<'_>) -> Result<Self, Error> {
+        Self::from_commit(repo, self.gitref_name.clone(), self.name.clone(), commit)
+    }
+
+    fn from_commit(
+        repo: &Repository,
+        gitref_name: String,
+        name: String,
+        commit: &git2::Commit<'_>,
+    ) -> Result<Self, Error> {
+        let maybe_inconsistent = || {
+            let tree = commit.tree()?;
 
             let meta_obj = tree.get_path("meta".as_ref())?.to_object(repo)?;
             let meta_blob = meta_obj
@@ -83,15 +152,17 @@ impl QueueState {
             .map_err(|_: git2::Error| Error::Inconsistency("queuelog reference"))?;
 
         Ok(Self {
-            oid: commit.map(|c| c.id()),
+            oid: Some(commit.id()),
             gitref_name,
+            name,
             entry,
         })
     }
 
     /// Create a new stack state in the given branch.
-    pub fn new(repo: &Repository, queue: &str, base: &git2::Branch<'_>) -> Result<Self, Error> {
-        let gitref_name = Self::gitref_name(queue);
+    pub fn new(ctx: &Ctx, queue: &str, base: &git2::Branch<'_>) -> Result<Self, Error> {
+        let repo = ctx.repo();
+        let gitref_name = ctx.refs().log_ref(queue);
         if repo.find_reference(&gitref_name).is_ok() {
             return Err(Error::AlreadyExists("queuelog"));
         }
@@ -110,15 +181,20 @@ impl QueueState {
             applied: vec![],
             unapplied: vec![],
             patches: HashMap::new(),
+            description: None,
+            versions: vec![],
+            mail: None,
+            exported: vec![],
+            merged: None,
+            branches: vec![],
+            reviews: HashMap::new(),
         };
 
         let tree = entry.build_tree(repo, &base_commit.tree()?)?;
 
-        let user = repo.signature()?;
-        let commit = repo.commit(
+        let commit = ctx.commit(
             Some(&gitref_name),
-            &user,
-            &user,
+            ctx.user(),
             &entry.message,
             &tree,
             // There is no previous entry, nor patches to add.
@@ -128,21 +204,37 @@ impl QueueState {
         Ok(Self {
             oid: Some(commit),
             gitref_name,
+            name: queue.to_string(),
             entry,
         })
     }
 
+    /// The OID of the log entry of this state, if it was already committed.
+    pub fn oid(&self) -> Option<Oid> {
+        self.oid
+    }
+
+    /// The message describing the operation that created this state.
+    pub fn message(&self) -> &str {
+        &self.entry.message
+    }
+
     pub fn base_name(&self) -> &str {
         &self.entry.base_name
     }
 
+    /// The last commit before the applied patches.
+    pub fn base(&self) -> Oid {
+        self.entry.base.0
+    }
+
     /// The HEAD commit of thi state.
     pub fn head(&self) -> Oid {
         self.entry.head.0
     }
 
     pub fn name(&self) -> &str {
-        self.gitref().split_at("refs/queuelogs/".len()).1
+        &self.name
     }
 
     pub fn gitref(&self) -> &str {
@@ -169,6 +261,11 @@ impl QueueState {
         })
     }
 
+    /// The commit of the given patch in this state, if it exists.
+    pub fn patch(&self, name: &str) -> Option<Oid> {
+        self.entry.patches.get(name).map(|oid| oid.0)
+    }
+
     /// Does this state have the given patch?
     pub fn has_patch(&self, name: &str) -> bool {
         self.entry.patches.contains_key(name)
@@ -218,6 +315,9 @@ impl QueueState {
 
         let patch_oid = self.entry.patches.remove(old_name).unwrap();
         self.entry.patches.insert(new_name.clone(), patch_oid);
+        if let Some(review) = self.entry.reviews.remove(old_name) {
+            self.entry.reviews.insert(new_name.clone(), review);
+        }
 
         if let Some(idx) = self.entry.applied.iter().position(|pn| pn == old_name) {
             self.entry.applied[idx] = new_name;
@@ -227,22 +327,285 @@ impl QueueState {
     }
 
     /// Update or create a patch OID in the state.
+    ///
+    /// New patches are pushed to the top of the stack.
     pub fn upsert_patch(&mut self, patch: String, commit: Oid) {
         if !self.has_patch(&patch) {
             self.entry.applied.push(patch.clone());
         }
 
+        if self.entry.applied.last() == Some(&patch) {
+            self.entry.head = LogOid(commit);
+        }
+
         self.entry.patches.insert(patch, LogOid(commit));
     }
 
-    /// Creates a new state in the log entry after modifying with the given
-    /// function.
-    pub fn create_next<T, F>(
+    /// The description of the queue, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.entry.description.as_deref()
+    }
+
+    /// Set or remove the description of the queue.
+    pub fn set_description(&mut self, description: Option<String>) {
+        self.entry.description = description;
+    }
+
+    /// The versions of the patch series and the OID of the log entry with their
+    /// patches, from the oldest to the newest.
+    pub fn versions(&self) -> impl Iterator<Item = (&str, Oid)> + '_ {
+        self.entry
+            .versions
+            .iter()
+            .map(|v| (v.name.as_str(), v.entry.0))
+    }
+
+    /// Get the state with the patches of the given version.
+    pub fn version(&self, repo: &Repository, name: &str) -> Result<Option<Self>, Error> {
+        match self.entry.versions.iter().find(|v| v.name == name) {
+            Some(version) => {
+                let commit = repo.find_commit(version.entry.0)?;
+                self.in_same_log(repo, &commit).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Add a version with the patches of the previous state.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if there is no previous state, i.e. this is the root state.
+    pub fn add_version(&mut self, name: String) {
+        let entry = self
+            .entry
+            .previous
+            .expect("tried to add version to root state");
+        self.entry.versions.push(LogVersion { name, entry });
+    }
+
+    /// The other predecessor of this state, if it merges two diverged logs.
+    pub fn merged(&self) -> Option<Oid> {
+        self.entry.merged.map(|oid| oid.0)
+    }
+
+    /// The name of the next version, `v<n>` after the highest numbered one.
+    pub fn next_version_name(&self) -> String {
+        let highest = self
+            .entry
+            .versions
+            .iter()
+            .filter_map(|v| v.name.strip_prefix('v')?.parse::<usize>().ok())
+            .max()
+            .unwrap_or(0);
+
+        format!("v{}", highest.max(self.entry.versions.len()) + 1)
+    }
+
+    /// Record that this state merges the log of `other`.
+    ///
+    /// The versions of `other` missing in this state are added after its own. A
+    /// version of `other` named like a different one of this state, e.g. when
+    /// both logs bumped `v2`, is added with the next free version name. The
+    /// review branches pushed only from `other` are recorded too, the commits
+    /// recorded in this state are kept for the others. Likewise, the reviews of
+    /// `other` are recorded for the patches of this state without one, or when
+    /// only the one of `other` is for the current commit of the patch.
+    ///
+    /// Must be called once the stack of this state is the merged one.
+    pub fn set_merged(&mut self, other: &QueueState) {
+        self.entry.merged = other.oid.map(LogOid);
+        for theirs in &other.entry.branches {
+            let ours = self
+                .entry
+                .branches
+                .iter_mut()
+                .find(|b| b.remote == theirs.remote && b.prefix == theirs.prefix);
+            match ours {
+                Some(ours) => {
+                    for (patch, oid) in &theirs.patches {
+                        ours.patches.entry(patch.clone()).or_insert(*oid);
+                    }
+                }
+                None => self.entry.branches.push(theirs.clone()),
+            }
+        }
+        for (patch, theirs) in &other.entry.reviews {
+            let oid = match self.patch(patch) {
+                Some(oid) => oid,
+                None => continue,
+            };
+            let replace = match self.entry.reviews.get(patch) {
+                Some(ours) => ours.commit() != oid && theirs.commit() == oid,
+                None => true,
+            };
+            if replace {
+                self.entry.reviews.insert(patch.clone(), theirs.clone());
+            }
+        }
+        for version in &other.entry.versions {
+            let same_name = self.entry.versions.iter().find(|v| v.name == version.name);
+            match same_name {
+                Some(v) if v.entry.0 == version.entry.0 => {}
+                Some(_) => {
+                    let name = self.next_version_name();
+                    self.entry.versions.push(LogVersion {
+                        name,
+                        entry: version.entry,
+                    });
+                }
+                None => self.entry.versions.push(version.clone()),
+            }
+        }
+    }
+
+    /// The patches pushed as review branches to a remote with a prefix, and the
+    /// commit last pushed for each one.
+    pub fn pushed_branches(&self, remote: &str, prefix: &str) -> Vec<(&str, Oid)> {
+        self.entry
+            .branches
+            .iter()
+            .filter(|b| b.remote == remote && b.prefix == prefix)
+            .flat_map(|b| b.patches.iter().map(|(name, oid)| (name.as_str(), oid.0)))
+            .collect()
+    }
+
+    /// Record the patches pushed as review branches to a remote with a prefix,
+    /// replacing the ones recorded before.
+    pub fn set_pushed_branches(&mut self, remote: &str, prefix: &str, patches: Vec<(String, Oid)>) {
+        self.entry
+            .branches
+            .retain(|b| b.remote != remote || b.prefix != prefix);
+        if !patches.is_empty() {
+            self.entry.branches.push(PushedBranches {
+                remote: remote.to_string(),
+                prefix: prefix.to_string(),
+                patches: patches
+                    .into_iter()
+                    .map(|(name, oid)| (name, LogOid(oid)))
+                    .collect(),
+            });
+        }
+    }
+
+    /// The review of a patch, if it was sent for review.
+    pub fn review(&self, patch: &str) -> Option<&PatchReview> {
+        self.entry.reviews.get(patch)
+    }
+
+    /// The reviews of all patches, including the ones removed from the queue
+    /// whose reviews weren't closed yet.
+    pub fn reviews(&self) -> impl Iterator<Item = (&str, &PatchReview)> + '_ {
+        self.entry
+            .reviews
+            .iter()
+            .map(|(name, r)| (name.as_str(), r))
+    }
+
+    /// Record the review of a patch, or forget it if `review` is `None`.
+    pub fn set_review(&mut self, patch: &str, review: Option<PatchReview>) {
+        match review {
+            Some(review) => self.entry.reviews.insert(patch.to_string(), review),
+            None => self.entry.reviews.remove(patch),
+        };
+    }
+
+    /// The patch series sent by email in this state's operation, if any.
+    pub fn mail(&self) -> Option<&SentSeries> {
+        self.entry.mail.as_ref()
+    }
+
+    /// Record the patch series sent by email in this state's operation.
+    pub fn set_mail(&mut self, series: SentSeries) {
+        self.entry.mail = Some(series);
+    }
+
+    /// The patches exported in this state's operation.
+    pub fn exported(&self) -> &[String] {
+        &self.entry.exported
+    }
+
+    /// Record the patches exported in this state's operation.
+    pub fn set_exported(&mut self, patches: Vec<String>) {
+        self.entry.exported = patches;
+    }
+
+    /// Find the most recent state, starting from this one, matching the predicate.
+    pub fn find(
         &self,
         repo: &Repository,
-        message: String,
-        func: F,
-    ) -> Result<(Self, T), Error>
+        mut predicate: impl FnMut(&Self) -> bool,
+    ) -> Result<Option<Self>, Error> {
+        if predicate(self) {
+            return Ok(Some(self.clone()));
+        }
+
+        let mut state = self.previous(repo)?;
+        while let Some(current) = state {
+            if predicate(&current) {
+                return Ok(Some(current));
+            }
+            state = current.previous(repo)?;
+        }
+        Ok(None)
+    }
+
+    /// Like [`QueueState::find`], but following the entries merged by syncs too,
+    /// nearest first.
+    pub fn find_merged(
+        &self,
+        repo: &Repository,
+        mut predicate: impl FnMut(&Self) -> bool,
+    ) -> Result<Option<Self>, Error> {
+        let mut queue = VecDeque::from(vec![self.clone()]);
+        let mut seen: HashSet<Oid> = self.oid.into_iter().collect();
+        while let Some(state) = queue.pop_front() {
+            if predicate(&state) {
+                return Ok(Some(state));
+            }
+            let parents = state.previous_oid().into_iter().chain(state.merged());
+            for oid in parents {
+                if seen.insert(oid) {
+                    queue.push_back(state.at_entry(repo, oid)?);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Load the state of the given log entry commit.
+    ///
+    /// The entry must belong to the same log of this state.
+    pub fn at_entry(&self, repo: &Repository, oid: Oid) -> Result<Self, Error> {
+        let commit = repo.find_commit(oid)?;
+        self.in_same_log(repo, &commit)
+    }
+
+    /// Replace all patches of the stack, e.g. when adopting patches created by
+    /// another tool.
+    ///
+    /// `applied` is ordered from the bottom to the top of the stack, and
+    /// `unapplied` in the order the patches would be pushed.
+    pub fn set_stack(
+        &mut self,
+        base: Oid,
+        applied: Vec<(String, Oid)>,
+        unapplied: Vec<(String, Oid)>,
+    ) {
+        self.entry.base = LogOid(base);
+        self.entry.head = LogOid(applied.last().map_or(base, |&(_, oid)| oid));
+        self.entry.patches = applied
+            .iter()
+            .chain(&unapplied)
+            .map(|(name, oid)| (name.clone(), LogOid(*oid)))
+            .collect();
+        self.entry.applied = applied.into_iter().map(|(name, _)| name).collect();
+        self.entry.unapplied = unapplied.into_iter().rev().map(|(name, _)| name).collect();
+    }
+
+    /// Creates a new state in the log entry after modifying with the given
+    /// function.
+    pub fn create_next<T, F>(&self, ctx: &Ctx, message: String, func: F) -> Result<(Self, T), Error>
     where
         F: FnOnce(&mut Self) -> Result<T, Error>,
     {
@@ -250,7 +613,7 @@ impl QueueState {
 
         let res = func(&mut next)?;
 
-        next.commit(repo)?;
+        next.commit(ctx)?;
 
         Ok((next, res))
     }
@@ -263,6 +626,7 @@ impl QueueState {
         Self {
             oid: None,
             gitref_name: self.gitref_name.clone(),
+            name: self.name.clone(),
             entry: LogEntryV1 {
                 message,
                 head: LogOid(self.head()),
@@ -272,19 +636,37 @@ impl QueueState {
                 applied: self.entry.applied.clone(),
                 unapplied: self.entry.unapplied.clone(),
                 patches: self.entry.patches.clone(),
+                description: self.entry.description.clone(),
+                versions: self.entry.versions.clone(),
+                mail: None,
+                exported: vec![],
+                merged: None,
+                branches: self.entry.branches.clone(),
+                reviews: self.entry.reviews.clone(),
             },
         }
     }
 
-    fn commit(&mut self, repo: &Repository) -> Result<(), Error> {
+    /// Commit this state to the log.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Modified`] if the log reference doesn't point to the
+    /// previous entry anymore, i.e. another process committed a new state since
+    /// this one was created. The operation can be retried from a fresh state.
+    fn commit(&mut self, ctx: &Ctx) -> Result<(), Error> {
         assert!(self.oid.is_none(), "tried to commit already commited entry");
+        let repo = ctx.repo();
         let prev_oid = self.entry.previous.expect("tried to commit root state").0;
         let prev = repo.find_commit(prev_oid)?;
-        let user = repo.signature()?;
 
         let tree = self.entry.build_tree(repo, &prev.tree()?)?;
 
-        let mut parents = vec![prev, repo.head()?.peel_to_commit()?];
+        let mut parents = vec![prev];
+        if let Some(LogOid(merged)) = self.entry.merged {
+            parents.push(repo.find_commit(merged)?);
+        }
+        parents.push(repo.find_commit(self.head())?);
 
         for &LogOid(patch) in self.entry.patches.values() {
             parents.push(repo.find_commit(patch)?);
@@ -292,22 +674,20 @@ impl QueueState {
 
         let parent_refs: Vec<_> = parents.iter().collect();
 
-        let oid = repo.commit(
-            Some(&self.gitref_name),
-            &user,
-            &user,
-            &self.entry.message,
-            &tree,
-            &parent_refs,
-        )?;
+        let oid = ctx.commit(None, ctx.user(), &self.entry.message, &tree, &parent_refs)?;
+
+        // Only move the log if no one else did it since we read the previous entry.
+        match repo.reference_matching(&self.gitref_name, oid, true, prev_oid, &self.entry.message) {
+            Ok(_) => {}
+            Err(err) if err.code() == git2::ErrorCode::Modified => {
+                return Err(Error::Modified("queuelog reference"))
+            }
+            Err(err) => return Err(err.into()),
+        }
         self.oid = Some(oid);
 
         Ok(())
     }
-
-    fn gitref_name(queue: &str) -> String {
-        format!("refs/queuelogs/{}", queue)
-    }
 }
 
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
@@ -320,6 +700,72 @@ struct LogEntryV1 {
     applied: Vec<String>,
     unapplied: Vec<String>,
     patches: HashMap<String, LogOid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    versions: Vec<LogVersion>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mail: Option<SentSeries>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exported: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    merged: Option<LogOid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    branches: Vec<PushedBranches>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    reviews: HashMap<String, PatchReview>,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct LogVersion {
+    name: String,
+    entry: LogOid,
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+struct PushedBranches {
+    remote: String,
+    prefix: String,
+    patches: HashMap<String, LogOid>,
+}
+
+/// The review of a patch in a forge.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct PatchReview {
+    /// Identifier of the review in the forge.
+    pub id: String,
+    /// Address of the review, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Status of the review when it was last updated, e.g. `open` or `merged`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    commit: LogOid,
+}
+
+impl PatchReview {
+    pub fn new(id: String, url: Option<String>, status: Option<String>, commit: Oid) -> Self {
+        Self {
+            id,
+            url,
+            status,
+            commit: LogOid(commit),
+        }
+    }
+
+    /// The commit of the patch last sent for review.
+    pub fn commit(&self) -> Oid {
+        self.commit.0
+    }
+}
+
+/// The Message-IDs of a patch series sent by email.
+#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct SentSeries {
+    /// Message-ID of the cover letter, if one was sent.
+    pub cover_letter: Option<String>,
+    /// Each sent patch and its Message-ID, in the order they were sent.
+    pub patches: Vec<(String, String)>,
 }
 
 impl LogEntryV1 {
@@ -352,7 +798,7 @@ impl LogEntryV1 {
 }
 
 #[derive(Clone, Copy)]
-struct LogOid(Oid);
+pub(crate) struct LogOid(pub(crate) Oid);
 
 impl serde::Serialize for LogOid {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -380,3 +826,154 @@ impl<'de> serde::Deserialize<'de> for LogOid {
 fn invalid_meta(message: &str) -> git2::Error {
     git2::Error::new(git2::ErrorCode::Modified, git2::ErrorClass::Object, message)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queue::Queue;
+    use crate::testing::{add_patch, base_branch, clone_of, setup};
+
+    #[test]
+    fn find_only_entries_of_the_log() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let base = base_branch(&ctx);
+        let mut queue = Queue::initialize(&ctx, "q", base).unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        let first = queue.state().clone();
+        add_patch(&mut queue, "two");
+        let branch = ctx.find_branch("main").unwrap().unwrap();
+        let mut other = Queue::initialize(&ctx, "r", branch).unwrap().unwrap();
+        add_patch(&mut other, "three");
+
+        let repo = ctx.repo();
+        let find = |oid: Option<Oid>| {
+            queue
+                .state()
+                .find_merged(repo, |s| s.oid() == oid)
+                .unwrap()
+                .and_then(|s| s.oid())
+        };
+        assert_eq!(find(first.oid()), first.oid());
+        assert_eq!(find(queue.state().oid()), queue.state().oid());
+        // Entries of other queues and the patches aren't entries of this log.
+        assert_eq!(find(other.state().oid()), None);
+        assert_eq!(find(queue.state().patch("one")), None);
+    }
+
+    #[test]
+    fn descriptions_are_kept_in_the_log() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let base = base_branch(&ctx);
+        let mut queue = Queue::initialize(&ctx, "q", base).unwrap().unwrap();
+        assert_eq!(queue.description(), None);
+
+        queue
+            .describe(Some("First\n\nSome text.".to_string()))
+            .unwrap();
+        assert_eq!(queue.state().message(), "describe");
+        add_patch(&mut queue, "one");
+        queue.describe(Some("Second".to_string())).unwrap();
+        add_patch(&mut queue, "two");
+
+        // Carried to the next entries and read back from the log.
+        let state = QueueState::current_for_queue(&ctx, "q").unwrap();
+        assert_eq!(state.description(), Some("Second"));
+
+        let repo = ctx.repo();
+        let mut history = vec![];
+        let mut entry = Some(state);
+        while let Some(state) = entry {
+            history.push(state.description().map(str::to_string));
+            entry = state.previous(repo).unwrap();
+        }
+        let first = Some("First\n\nSome text.".to_string());
+        let second = Some("Second".to_string());
+        assert_eq!(
+            history,
+            [second.clone(), second, first.clone(), first, None]
+        );
+
+        queue.describe(None).unwrap();
+        let state = QueueState::current_for_queue(&ctx, "q").unwrap();
+        assert_eq!(state.description(), None);
+    }
+
+    #[test]
+    fn versions_snapshot_the_patches() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let base = base_branch(&ctx);
+        let mut queue = Queue::initialize(&ctx, "q", base).unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        let first = queue.state().clone();
+        assert_eq!(queue.bump_version().unwrap(), "v1");
+        assert_eq!(queue.state().message(), "version bump v1");
+        add_patch(&mut queue, "two");
+        let second = queue.state().clone();
+        assert_eq!(queue.bump_version().unwrap(), "v2");
+        add_patch(&mut queue, "three");
+
+        let repo = ctx.repo();
+        let state = QueueState::current_for_queue(&ctx, "q").unwrap();
+        let versions: Vec<_> = state.versions().collect();
+        assert_eq!(
+            versions,
+            [("v1", first.oid().unwrap()), ("v2", second.oid().unwrap())]
+        );
+        let v1 = state.version(repo, "v1").unwrap().unwrap();
+        assert_eq!(v1.oid(), first.oid());
+        let names: Vec<_> = v1.applied().map(|(name, _)| name).collect();
+        assert_eq!(names, ["one"]);
+        assert!(state.version(repo, "v3").unwrap().is_none());
+        assert_eq!(state.next_version_name(), "v3");
+    }
+
+    #[test]
+    fn find_exported_patches() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let base = base_branch(&ctx);
+        let mut queue = Queue::initialize(&ctx, "q", base).unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        add_patch(&mut queue, "two");
+        queue.record_export(vec!["one".to_string()]).unwrap();
+        assert_eq!(queue.state().message(), "export 1 patch");
+        let exported = queue.state().clone();
+        queue
+            .reword_patches("reword".to_string(), |name, _| {
+                (name == "one").then(|| "one, reworded".to_string())
+            })
+            .unwrap();
+
+        let repo = ctx.repo();
+        let was_exported = |patch: &'static str| {
+            move |s: &QueueState| s.exported().iter().any(|name| name == patch)
+        };
+        let found = queue.state().find(repo, was_exported("one")).unwrap();
+        assert_eq!(found.as_ref().and_then(|s| s.oid()), exported.oid());
+        assert!(queue
+            .state()
+            .find(repo, was_exported("two"))
+            .unwrap()
+            .is_none());
+        // The state itself is the most recent one.
+        let found = exported.find(repo, was_exported("one")).unwrap();
+        assert_eq!(found.and_then(|s| s.oid()), exported.oid());
+
+        // How the patches changed since they were exported.
+        let interdiff = |patch: &str| {
+            let old = exported.patch(patch).unwrap();
+            let new = queue.state().patch(patch).unwrap();
+            crate::range_diff::interdiff(repo, old, new).unwrap()
+        };
+        let one = interdiff("one");
+        assert!(
+            one.starts_with("@@ -1,4 +1,4 @@\n-one\n+one, reworded\n"),
+            "{}",
+            one
+        );
+        assert_eq!(interdiff("two"), "");
+    }
+}
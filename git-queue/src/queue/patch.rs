@@ -1,4 +1,6 @@
-use git2::{Error, ErrorCode, Tree};
+use git2::{ErrorCode, Signature, Tree};
+
+use crate::{ctx::Ctx, error::Error, naming, trailers};
 
 pub struct Patch<'r> {
     ref_name: String,
@@ -6,15 +8,11 @@ pub struct Patch<'r> {
 }
 
 impl<'r> Patch<'r> {
-    pub fn from_name(
-        repo: &'r git2::Repository,
-        queue: &str,
-        name: &str,
-    ) -> Result<Option<Self>, Error> {
-        let ref_name = format!("refs/patches/{}/{}", queue, name);
-        match repo.find_reference(&ref_name) {
-            Err(error) if error.code() == ErrorCode::NotFound => return Ok(None),
-            Err(error) => return Err(error),
+    pub fn from_name(ctx: &'r Ctx, queue: &str, name: &str) -> Result<Option<Self>, Error> {
+        let ref_name = ctx.refs().patch_ref(queue, name);
+        match ctx.repo().find_reference(&ref_name) {
+            Err(error) if error.code() == ErrorCode::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
             Ok(_ref) => {
                 let commit = _ref.peel_to_commit()?;
 
@@ -23,6 +21,61 @@ impl<'r> Patch<'r> {
         }
     }
 
+    /// Create a new patch in the given queue.
+    ///
+    /// The patch commit is created on top of `parent`, and signed if the user
+    /// configured Git to sign commits. The message is signed off if
+    /// `qg.autoSignoff` is set, and a Change-Id is added if `qg.changeId` is set
+    /// and it has none, see [`crate::trailers`].
+    pub fn create(
+        ctx: &'r Ctx,
+        queue: &str,
+        name: &str,
+        author: &Signature<'_>,
+        message: &str,
+        tree: &Tree<'_>,
+        parent: &git2::Commit<'_>,
+    ) -> Result<Self, Error> {
+        naming::validate_patch_name(name)?;
+        let ref_name = ctx.refs().patch_ref(queue, name);
+        if ctx.repo().find_reference(&ref_name).is_ok() {
+            return Err(Error::AlreadyExists("patch"));
+        }
+
+        let mut message = signoff(ctx, message)?;
+        if trailers::change_id_enabled(ctx.config())? && trailers::change_id(&message).is_none() {
+            let change_id = trailers::new_change_id(tree.id(), parent.id(), author, &message);
+            message = trailers::add(&message, trailers::CHANGE_ID, &change_id);
+        }
+
+        let oid = ctx.commit(Some(&ref_name), author, &message, tree, &[parent])?;
+        let commit = ctx.repo().find_commit(oid)?;
+
+        Ok(Self { ref_name, commit })
+    }
+
+    /// Create the reference of a patch pointing to an existing commit.
+    ///
+    /// Does nothing if the reference already points to the commit.
+    pub fn adopt(ctx: &'r Ctx, queue: &str, name: &str, oid: git2::Oid) -> Result<Self, Error> {
+        naming::validate_patch_name(name)?;
+        let repo = ctx.repo();
+        let ref_name = ctx.refs().patch_ref(queue, name);
+        match repo.find_reference(&ref_name) {
+            Ok(existing) if existing.target() != Some(oid) => {
+                return Err(Error::AlreadyExists("patch"))
+            }
+            Ok(_) => {}
+            Err(err) if err.code() == ErrorCode::NotFound => {
+                repo.reference(&ref_name, oid, false, &format!("adopt {}", name))?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+        let commit = repo.find_commit(oid)?;
+
+        Ok(Self { ref_name, commit })
+    }
+
     /// Full reference name of this patch.
     pub fn ref_name(&self) -> &str {
         &self.ref_name
@@ -39,26 +92,65 @@ impl<'r> Patch<'r> {
     }
 
     /// Amend this patch.
-    pub fn amend(
-        &mut self,
-        amend: PatchAmend<'r, '_>,
-        repo: &'r git2::Repository,
-    ) -> Result<git2::Oid, Error> {
-        let new_oid = self.commit.amend(
+    ///
+    /// The new commit keeps the author, parents and Change-Id of the current one,
+    /// and is signed if the user configured Git to sign commits. The message is
+    /// signed off if `qg.autoSignoff` is set.
+    pub fn amend(&mut self, amend: PatchAmend<'r, '_>, ctx: &'r Ctx) -> Result<git2::Oid, Error> {
+        let current = self.commit.message().ok_or(Error::NonUtf8)?;
+        let message = match (amend.message, trailers::change_id(current)) {
+            (Some(message), Some(change_id)) if trailers::change_id(message).is_none() => {
+                trailers::add(message, trailers::CHANGE_ID, change_id)
+            }
+            (Some(message), _) => message.to_string(),
+            (None, _) => current.to_string(),
+        };
+        let message = signoff(ctx, &message)?;
+        let tree = match amend.tree {
+            Some(tree) => tree.clone(),
+            None => self.commit.tree()?,
+        };
+        let parents: Vec<_> = self.commit.parents().collect();
+        let parent_refs: Vec<_> = parents.iter().collect();
+
+        let new_oid = ctx.commit(
             Some(&self.ref_name),
-            None,
-            Some(&repo.signature()?),
-            None,
-            amend.message,
-            amend.tree,
+            &self.commit.author(),
+            &message,
+            &tree,
+            &parent_refs,
         )?;
 
-        self.commit = repo.find_commit(new_oid)?;
+        self.commit = ctx.repo().find_commit(new_oid)?;
 
         Ok(self.id())
     }
 }
 
+/// Sign off a message with the user identity if `qg.autoSignoff` is set.
+fn signoff(ctx: &Ctx, message: &str) -> Result<String, Error> {
+    if !trailers::auto_signoff_enabled(ctx.config())? {
+        return Ok(message.to_string());
+    }
+
+    let signoff = trailers::signoff(ctx.user());
+    let signed = trailers::parse(message)
+        .iter()
+        .any(|(k, v)| k.eq_ignore_ascii_case(trailers::SIGNED_OFF_BY) && *v == signoff);
+    if signed {
+        return Ok(message.to_string());
+    }
+    // Keep the Change-Id as the last trailer, where Gerrit expects it.
+    match trailers::change_id(message) {
+        Some(change_id) => {
+            let message = trailers::remove(message, trailers::CHANGE_ID, None);
+            let message = trailers::add(&message, trailers::SIGNED_OFF_BY, &signoff);
+            Ok(trailers::add(&message, trailers::CHANGE_ID, change_id))
+        }
+        None => Ok(trailers::add(message, trailers::SIGNED_OFF_BY, &signoff)),
+    }
+}
+
 #[derive(Default)]
 pub struct PatchAmend<'r, 's> {
     message: Option<&'s str>,
@@ -74,3 +166,57 @@ impl<'r, 's> PatchAmend<'r, 's> {
         self.tree = Some(tree);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{base_branch, clone_of, setup};
+
+    #[test]
+    fn auto_signoff() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut config = ctx.repo().config().unwrap();
+        config.set_bool("qg.autoSignoff", true).unwrap();
+        let workdir = ctx.repo().workdir().unwrap().to_path_buf();
+        let ctx = Ctx::from_repo(git2::Repository::open(&workdir).unwrap()).unwrap();
+
+        let base = base_branch(&ctx).get().peel_to_commit().unwrap();
+        let tree = base.tree().unwrap();
+        let author = Signature::now("B", "b@example.com").unwrap();
+        let message = |patch: &Patch<'_>| patch.commit.message().unwrap().to_string();
+
+        // Signed off by the user, not the author.
+        let mut patch = Patch::create(&ctx, "q", "one", &author, "One", &tree, &base).unwrap();
+        assert_eq!(message(&patch), "One\n\nSigned-off-by: A <a@example.com>\n");
+        let mut amend = PatchAmend::default();
+        amend.set_message("One\n\nCc: C\n");
+        patch.amend(amend, &ctx).unwrap();
+        assert_eq!(
+            message(&patch),
+            "One\n\nCc: C\nSigned-off-by: A <a@example.com>\n"
+        );
+        let signed = message(&patch);
+        patch.amend(PatchAmend::default(), &ctx).unwrap();
+        assert_eq!(message(&patch), signed);
+
+        // The Change-Id stays the last trailer.
+        let change_id = "Change-Id: I0123456789abcdef0123456789abcdef01234567";
+        let mut patch = Patch::create(
+            &ctx,
+            "q",
+            "two",
+            &author,
+            &format!("Two\n\n{}\n", change_id),
+            &tree,
+            &base,
+        )
+        .unwrap();
+        let expected = format!("Two\n\nSigned-off-by: A <a@example.com>\n{}\n", change_id);
+        assert_eq!(message(&patch), expected);
+        let mut amend = PatchAmend::default();
+        amend.set_message("Two, amended");
+        patch.amend(amend, &ctx).unwrap();
+        assert_eq!(message(&patch), expected.replace("Two", "Two, amended"));
+    }
+}
@@ -0,0 +1,261 @@
+//! # Range Diff
+//!
+//! Compare two versions of a patch series patch-by-patch, like `git range-diff`.
+//!
+//! Patches are matched by name. Each pair is compared by diffing the text of the
+//! patches, i.e. the commit message followed by the diff. The `index` lines and
+//! the line numbers of the hunks are left out, as they change whenever an earlier
+//! patch in the series changes.
+
+use git2::{DiffFormat, DiffOptions, Oid, Repository};
+
+use crate::error::Error;
+use crate::export::commit_diff;
+use crate::queue::log::QueueState;
+use crate::spec::series;
+
+/// A patch in two versions of a series.
+pub struct PatchComparison {
+    name: String,
+    old: Option<(usize, Oid)>,
+    new: Option<(usize, Oid)>,
+    interdiff: String,
+}
+
+impl PatchComparison {
+    /// Name of the patch.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Position and commit of the patch in the old version, if it exists there.
+    pub fn before(&self) -> Option<(usize, Oid)> {
+        self.old
+    }
+
+    /// Position and commit of the patch in the new version, if it exists there.
+    pub fn after(&self) -> Option<(usize, Oid)> {
+        self.new
+    }
+
+    /// Diff between the old and new text of the patch, empty if they're equal
+    /// or the patch exists in only one version.
+    pub fn interdiff(&self) -> &str {
+        &self.interdiff
+    }
+
+    /// Is the patch in both versions, with the same text?
+    pub fn is_unchanged(&self) -> bool {
+        self.old.is_some() && self.new.is_some() && self.interdiff.is_empty()
+    }
+}
+
+/// Compare the patches of two queue states.
+///
+/// The comparisons follow the order of the new patches, with the applied ones
+/// first, followed by the patches removed since the old version.
+pub fn range_diff(
+    repo: &Repository,
+    old: &QueueState,
+    new: &QueueState,
+) -> Result<Vec<PatchComparison>, Error> {
+    let old_patches = series(old);
+    let new_patches = series(new);
+
+    let mut comparisons = Vec::with_capacity(new_patches.len());
+    for (idx, &(name, new_oid)) in new_patches.iter().enumerate() {
+        let old_patch = old_patches.iter().position(|&(n, _)| n == name);
+        let old = old_patch.map(|i| (i + 1, old_patches[i].1));
+
+        let interdiff = match old {
+            Some((_, old_oid)) => interdiff(repo, old_oid, new_oid)?,
+            None => String::new(),
+        };
+
+        comparisons.push(PatchComparison {
+            name: name.to_string(),
+            old,
+            new: Some((idx + 1, new_oid)),
+            interdiff,
+        });
+    }
+
+    for (idx, &(name, old_oid)) in old_patches.iter().enumerate() {
+        if !new_patches.iter().any(|&(n, _)| n == name) {
+            comparisons.push(PatchComparison {
+                name: name.to_string(),
+                old: Some((idx + 1, old_oid)),
+                new: None,
+                interdiff: String::new(),
+            });
+        }
+    }
+
+    Ok(comparisons)
+}
+
+/// Diff the text of two commits of a patch, empty if they're equal.
+pub fn interdiff(repo: &Repository, old: Oid, new: Oid) -> Result<String, Error> {
+    if old == new {
+        return Ok(String::new());
+    }
+
+    diff_text(&patch_text(repo, old)?, &patch_text(repo, new)?)
+}
+
+/// The text of a patch: its message followed by its diff.
+fn patch_text(repo: &Repository, oid: Oid) -> Result<String, Error> {
+    let commit = repo.find_commit(oid)?;
+    let diff = commit_diff(repo, &commit)?;
+
+    let mut text = String::from_utf8_lossy(commit.message_bytes()).into_owned();
+    text.push_str("\n---\n");
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        let content = String::from_utf8_lossy(line.content());
+        match line.origin() {
+            'F' => {
+                for header in content.lines().filter(|l| !l.starts_with("index ")) {
+                    text.push_str(header);
+                    text.push('\n');
+                }
+            }
+            'H' => {
+                // Keep only the function context of the hunk header.
+                let context = content.splitn(3, "@@").nth(2).unwrap_or_default();
+                text.push_str("@@");
+                text.push_str(context.trim_end());
+                text.push('\n');
+            }
+            origin @ ('+' | '-' | ' ') => {
+                text.push(origin);
+                text.push_str(&content);
+            }
+            _ => text.push_str(&content),
+        }
+        true
+    })?;
+
+    Ok(text)
+}
+
+/// Diff two texts, returning only the hunks.
+fn diff_text(old: &str, new: &str) -> Result<String, Error> {
+    if old == new {
+        return Ok(String::new());
+    }
+
+    let mut patch = git2::Patch::from_buffers(
+        old.as_bytes(),
+        None,
+        new.as_bytes(),
+        None,
+        Some(DiffOptions::new().context_lines(3)),
+    )?;
+
+    let mut diff = String::new();
+    patch.print(&mut |_, _, line| {
+        let content = String::from_utf8_lossy(line.content());
+        match line.origin() {
+            'H' => diff.push_str(&content),
+            origin @ ('+' | '-' | ' ') => {
+                diff.push(origin);
+                diff.push_str(&content);
+            }
+            _ => return true,
+        }
+        // The last line of a text may have no newline.
+        if !diff.ends_with('\n') {
+            diff.push('\n');
+        }
+        true
+    })?;
+
+    Ok(diff)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        queue::Queue,
+        testing::{add_patch, base_branch, clone_of, setup},
+    };
+    use git2::Signature;
+
+    /// A commit on top of `parent` setting the content of `file`.
+    fn commit(repo: &Repository, parent: Oid, file: &str, content: &str, message: &str) -> Oid {
+        let parent = repo.find_commit(parent).unwrap();
+        let mut builder = repo.treebuilder(Some(&parent.tree().unwrap())).unwrap();
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        builder.insert(file, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        repo.commit(None, &author, &author, message, &tree, &[&parent])
+            .unwrap()
+    }
+
+    #[test]
+    fn compare_versions() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        for name in ["one", "two", "three"] {
+            add_patch(&mut queue, name);
+        }
+        let old = queue.state().clone();
+
+        // Change `one`, so `two` is rebased without changes, drop `three` and add
+        // `four`.
+        let repo = ctx.repo();
+        let base = queue.state().base();
+        let one = commit(repo, base, "one", "changed", "one");
+        let two = commit(repo, one, "two", "two", "two");
+        let four = commit(repo, two, "four", "four", "four");
+        let stack = vec![
+            ("one".to_string(), one),
+            ("two".to_string(), two),
+            ("four".to_string(), four),
+        ];
+        queue
+            .replace_stack("change".to_string(), base, stack, vec![], |_| {})
+            .unwrap();
+
+        let comparisons = range_diff(repo, &old, queue.state()).unwrap();
+        let summary: Vec<_> = comparisons
+            .iter()
+            .map(|c| {
+                let position = |p: Option<(usize, Oid)>| p.map(|(idx, _)| idx);
+                (c.name(), position(c.before()), position(c.after()))
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("one", Some(1), Some(1)),
+                ("two", Some(2), Some(2)),
+                ("four", None, Some(3)),
+                ("three", Some(3), None),
+            ]
+        );
+
+        assert!(!comparisons[0].is_unchanged());
+        assert_eq!(
+            comparisons[0].interdiff(),
+            "@@ -5,5 +5,5 @@ new file mode 100644\n \
+             --- /dev/null\n \
+             +++ b/one\n \
+             @@\n\
+             -+one\n\
+             ++changed\n \
+             \\ No newline at end of file\n"
+        );
+        // Only the patch text is compared, not the commit.
+        assert_ne!(comparisons[1].before(), comparisons[1].after());
+        assert!(comparisons[1].is_unchanged());
+        assert!(!comparisons[2].is_unchanged());
+        assert_eq!(comparisons[2].interdiff(), "");
+        assert_eq!(interdiff(repo, one, one).unwrap(), "");
+    }
+}
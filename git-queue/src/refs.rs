@@ -0,0 +1,332 @@
+//! # Reference Layout
+//!
+//! Where the queues are stored in the repository. Each queue is made of:
+//!
+//!   * a branch, named `<qg.branchPrefix>/<queue>` (`queues/<queue>` by default);
+//!   * a log, in the reference `<qg.logRef>/<queue>` (`refs/queuelogs/<queue>`
+//!     by default), see [`crate::queue::log`];
+//!   * a reference for each patch, named `<qg.patchRef>/<queue>/<patch>`
+//!     (`refs/patches/<queue>/<patch>` by default).
+//!
+//! When the configuration changes, existing queues can be moved to the new
+//! layout with [`migrate`].
+
+use git2::{BranchType, Repository};
+
+use crate::{error::Error, naming};
+
+pub const DEFAULT_BRANCH_PREFIX: &str = "queues";
+pub const DEFAULT_LOG_REF: &str = "refs/queuelogs";
+pub const DEFAULT_PATCH_REF: &str = "refs/patches";
+
+/// The prefixes of the references of queues.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefLayout {
+    branch_prefix: String,
+    log_ref: String,
+    patch_ref: String,
+}
+
+impl Default for RefLayout {
+    fn default() -> Self {
+        Self::new(DEFAULT_BRANCH_PREFIX, DEFAULT_LOG_REF, DEFAULT_PATCH_REF)
+            .expect("default reference layout is valid")
+    }
+}
+
+impl RefLayout {
+    /// Create a layout from the given prefixes, with or without a trailing `/`.
+    ///
+    /// The branch prefix is relative to `refs/heads/`, and the log and patch
+    /// prefixes are full reference names, outside of `refs/heads/`.
+    pub fn new(branch_prefix: &str, log_ref: &str, patch_ref: &str) -> Result<Self, Error> {
+        let branch_prefix = branch_prefix.trim_end_matches('/');
+        let log_ref = log_ref.trim_end_matches('/');
+        let patch_ref = patch_ref.trim_end_matches('/');
+
+        let prefixes = [
+            ("qg.branchPrefix", branch_prefix),
+            ("qg.logRef", log_ref),
+            ("qg.patchRef", patch_ref),
+        ];
+        for (key, prefix) in &prefixes {
+            naming::validate_queue_name(prefix)
+                .map_err(|err| Error::Config(format!("{}: {}", key, err)))?;
+        }
+        for (key, prefix) in &prefixes[1..] {
+            if !prefix.starts_with("refs/") || naming::in_namespace(prefix, "refs/heads") {
+                return Err(Error::Config(format!(
+                    "{} must be under `refs/`, but not under `refs/heads/`",
+                    key
+                )));
+            }
+        }
+        if log_ref == patch_ref
+            || naming::in_namespace(log_ref, patch_ref)
+            || naming::in_namespace(patch_ref, log_ref)
+        {
+            return Err(Error::Config(
+                "qg.logRef and qg.patchRef can't be in the same namespace".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            branch_prefix: format!("{}/", branch_prefix),
+            log_ref: format!("{}/", log_ref),
+            patch_ref: format!("{}/", patch_ref),
+        })
+    }
+
+    /// Read the layout from `qg.branchPrefix`, `qg.logRef` and `qg.patchRef`.
+    pub fn from_config(config: &git2::Config) -> Result<Self, Error> {
+        let get = |key, default: &str| match config.get_string(key) {
+            Ok(value) => Ok(value),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(default.to_string()),
+            Err(err) => Err(Error::from(err)),
+        };
+
+        Self::new(
+            &get("qg.branchPrefix", DEFAULT_BRANCH_PREFIX)?,
+            &get("qg.logRef", DEFAULT_LOG_REF)?,
+            &get("qg.patchRef", DEFAULT_PATCH_REF)?,
+        )
+    }
+
+    /// Name of the branch of a queue, without `refs/heads/`.
+    pub fn queue_branch(&self, queue: &str) -> String {
+        format!("{}{}", self.branch_prefix, queue)
+    }
+
+    /// Name of the queue of a branch, if it is a queue branch.
+    pub fn queue_of_branch<'b>(&self, branch: &'b str) -> Option<&'b str> {
+        branch
+            .strip_prefix(&self.branch_prefix)
+            .filter(|q| !q.is_empty())
+    }
+
+    /// Reference of the log of a queue.
+    pub fn log_ref(&self, queue: &str) -> String {
+        format!("{}{}", self.log_ref, queue)
+    }
+
+    /// Reference of a patch of a queue.
+    pub fn patch_ref(&self, queue: &str, patch: &str) -> String {
+        format!("{}{}/{}", self.patch_ref, queue, patch)
+    }
+}
+
+/// Move the queues stored with the `old` layout to the `new` one.
+///
+/// The branch, log and patch references of each queue are renamed, and HEAD is
+/// updated if it was in one of the queues. Returns the names of the moved queues.
+///
+/// # Errors
+///
+/// Returns [`Error::AlreadyExists`] if a reference already exists in the new
+/// layout, in which case the queues moved before it are kept in the new layout.
+pub fn migrate(repo: &Repository, old: &RefLayout, new: &RefLayout) -> Result<Vec<String>, Error> {
+    let mut queues = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        let (branch, _) = branch?;
+        let name = branch.name()?.ok_or(Error::NonUtf8)?;
+        if let Some(queue) = old.queue_of_branch(name) {
+            // Only branches with a log are queues.
+            if repo.find_reference(&old.log_ref(queue)).is_ok() {
+                queues.push(queue.to_string());
+            }
+        }
+    }
+
+    for queue in &queues {
+        let message = format!("migrate queue {}", queue);
+
+        let patch_prefix = format!("{}{}/", old.patch_ref, queue);
+        let mut patches = Vec::new();
+        for reference in repo.references_glob(&format!("{}*", patch_prefix))? {
+            let reference = reference?;
+            let name = reference.name().ok_or(Error::NonUtf8)?;
+            match name.strip_prefix(&patch_prefix) {
+                // Skip the patches of queues in the namespace of this one.
+                Some(patch) if !patch.contains('/') => patches.push(patch.to_string()),
+                _ => {}
+            }
+        }
+        for patch in &patches {
+            rename(
+                repo,
+                &old.patch_ref(queue, patch),
+                &new.patch_ref(queue, patch),
+                &message,
+            )?;
+        }
+
+        rename(repo, &old.log_ref(queue), &new.log_ref(queue), &message)?;
+
+        if old.branch_prefix == new.branch_prefix {
+            continue;
+        }
+        // Renaming the branch also updates HEAD if it points to it.
+        let mut branch = repo.find_branch(&old.queue_branch(queue), BranchType::Local)?;
+        match branch.rename(&new.queue_branch(queue), false) {
+            Ok(_) => {}
+            Err(err) if err.code() == git2::ErrorCode::Exists => {
+                return Err(Error::AlreadyExists("queue branch"))
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(queues)
+}
+
+fn rename(repo: &Repository, old: &str, new: &str, message: &str) -> Result<(), Error> {
+    if old == new {
+        return Ok(());
+    }
+
+    match repo.find_reference(old)?.rename(new, false, message) {
+        Ok(_) => Ok(()),
+        Err(err) if err.code() == git2::ErrorCode::Exists => Err(Error::AlreadyExists("reference")),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ctx::Ctx,
+        queue::Queue,
+        testing::{add_patch, base_branch, clone_of, setup},
+    };
+
+    #[test]
+    fn layout_names() {
+        let layout = RefLayout::default();
+        assert_eq!(layout.queue_branch("team/q"), "queues/team/q");
+        assert_eq!(layout.log_ref("team/q"), "refs/queuelogs/team/q");
+        assert_eq!(layout.patch_ref("team/q", "p"), "refs/patches/team/q/p");
+        assert_eq!(layout.queue_of_branch("queues/team/q"), Some("team/q"));
+        assert_eq!(layout.queue_of_branch("queues/"), None);
+        assert_eq!(layout.queue_of_branch("queuesq"), None);
+        assert_eq!(layout.queue_of_branch("main"), None);
+
+        let layout = RefLayout::new("qg/queues/", "refs/qg/logs/", "refs/qg/patches").unwrap();
+        assert_eq!(layout.queue_branch("q"), "qg/queues/q");
+        assert_eq!(layout.log_ref("q"), "refs/qg/logs/q");
+        assert_eq!(layout.patch_ref("q", "p"), "refs/qg/patches/q/p");
+    }
+
+    #[test]
+    fn invalid_layouts() {
+        let invalid = |branch, log, patch| match RefLayout::new(branch, log, patch) {
+            Err(Error::Config(message)) => message,
+            other => panic!("accepted {:?}", other),
+        };
+
+        assert!(invalid("a..b", DEFAULT_LOG_REF, DEFAULT_PATCH_REF).starts_with("qg.branchPrefix"));
+        assert!(invalid("", DEFAULT_LOG_REF, DEFAULT_PATCH_REF).starts_with("qg.branchPrefix"));
+        assert!(invalid(DEFAULT_BRANCH_PREFIX, "logs", DEFAULT_PATCH_REF).starts_with("qg.logRef"));
+        assert!(
+            invalid(DEFAULT_BRANCH_PREFIX, "refs/heads/logs", DEFAULT_PATCH_REF)
+                .starts_with("qg.logRef")
+        );
+        assert!(
+            invalid(DEFAULT_BRANCH_PREFIX, DEFAULT_LOG_REF, "refs/heads/p")
+                .starts_with("qg.patchRef")
+        );
+        for (log, patch) in &[
+            ("refs/qg", "refs/qg/"),
+            ("refs/qg", "refs/qg/patches"),
+            ("refs/qg/logs", "refs/qg"),
+        ] {
+            let message = invalid(DEFAULT_BRANCH_PREFIX, log, patch);
+            assert!(message.contains("same namespace"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn layout_from_config() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut config = ctx.repo().config().unwrap();
+        assert_eq!(
+            RefLayout::from_config(&config).unwrap(),
+            RefLayout::default()
+        );
+
+        config.set_str("qg.branchPrefix", "stacks").unwrap();
+        let layout = RefLayout::from_config(&config).unwrap();
+        assert_eq!(layout.queue_branch("q"), "stacks/q");
+        assert_eq!(layout.log_ref("q"), "refs/queuelogs/q");
+
+        config.set_str("qg.logRef", "queuelogs").unwrap();
+        assert!(matches!(
+            RefLayout::from_config(&config),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn migrate_queues() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        base_branch(&ctx);
+        let repo = ctx.repo();
+        let main = || repo.find_branch("main", BranchType::Local).unwrap();
+        let mut queue = Queue::initialize(&ctx, "q", main()).unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        queue.switch_to().unwrap();
+        let mut nested = Queue::initialize(&ctx, "team/q", main()).unwrap().unwrap();
+        add_patch(&mut nested, "two");
+        let (head, log) = (queue.state().head(), queue.state().oid().unwrap());
+        // A branch looking like a queue, but without a log.
+        let commit = repo.find_commit(head).unwrap();
+        repo.branch("queues/stray", &commit, false).unwrap();
+
+        let old = RefLayout::default();
+        let new = RefLayout::new("stacks", "refs/qg/logs", "refs/qg/patches").unwrap();
+        let mut moved = migrate(repo, &old, &new).unwrap();
+        moved.sort();
+        assert_eq!(moved, ["q", "team/q"]);
+
+        assert_eq!(repo.refname_to_id("refs/heads/stacks/q").unwrap(), head);
+        assert_eq!(repo.refname_to_id("refs/qg/logs/q").unwrap(), log);
+        assert_eq!(repo.refname_to_id("refs/qg/patches/q/one").unwrap(), head);
+        assert!(repo.find_reference("refs/qg/patches/team/q/two").is_ok());
+        assert_eq!(
+            repo.head().unwrap().name(),
+            Some("refs/heads/stacks/q"),
+            "HEAD follows the current queue"
+        );
+        for old_ref in &[
+            "refs/heads/queues/q",
+            "refs/queuelogs/q",
+            "refs/patches/q/one",
+            "refs/patches/team/q/two",
+        ] {
+            assert!(repo.find_reference(old_ref).is_err(), "{} is left", old_ref);
+        }
+        assert!(repo.find_reference("refs/heads/queues/stray").is_ok());
+
+        // The queues are found with the new configuration.
+        let mut config = repo.config().unwrap();
+        config.set_str("qg.branchPrefix", "stacks").unwrap();
+        config.set_str("qg.logRef", "refs/qg/logs").unwrap();
+        config.set_str("qg.patchRef", "refs/qg/patches").unwrap();
+        let workdir = repo.workdir().unwrap();
+        let ctx = Ctx::from_repo(Repository::open(workdir).unwrap()).unwrap();
+        let queue = Queue::current(&ctx).unwrap().unwrap();
+        assert_eq!(queue.name(), "q");
+        assert_eq!(queue.state().patch("one"), Some(head));
+        assert!(Queue::for_queue(&ctx, "team/q").unwrap().is_some());
+
+        // Moving back onto existing references fails.
+        let repo = ctx.repo();
+        repo.reference("refs/queuelogs/q", log, false, "").unwrap();
+        assert!(matches!(
+            migrate(repo, &new, &old),
+            Err(Error::AlreadyExists(_))
+        ));
+    }
+}
@@ -0,0 +1,655 @@
+//! # Remote Queues
+//!
+//! Share queues through a remote repository. Publishing a queue pushes its
+//! branch, log and patches to the remote, under the namespace given in
+//! `qg.remoteNamespace` (`refs/queues` by default):
+//!
+//!   * `<namespace>/heads/<queue>`: the queue branch;
+//!   * `<namespace>/logs/<queue>`: the queue log;
+//!   * `<namespace>/patches/<queue>/<patch>`: each patch.
+//!
+//! Fetching a queue only needs its log, as each log entry has the queue head and
+//! the patches as parents. The fetched log must descend from the local one,
+//! i.e. the local queue can't have changes that weren't published. Likewise, a
+//! queue can only be published if the remote log is an ancestor of the local one.
+//!
+//! For code review in forges, each applied patch can also be pushed as its own
+//! branch, `<prefix>/<queue>/<patch>`, based on the branch of the previous patch.
+//! The prefix is `qg.reviewPrefix`, `review` by default. The commits pushed are
+//! recorded in the queue log, and a branch is only overwritten if it still has
+//! the commit last pushed. As libgit2 can't push with a lease, the branches are
+//! pushed by running `git push --force-with-lease`, so the remote refuses to
+//! update a branch someone else pushed to in the meantime.
+
+use std::process::Command;
+
+use git2::{Cred, CredentialType, Direction, FetchOptions, Oid, PushOptions, RemoteCallbacks};
+
+use crate::{ctx::Ctx, error::Error, naming, queue::Queue};
+
+const DEFAULT_NAMESPACE: &str = "refs/queues";
+const DEFAULT_REMOTE: &str = "origin";
+const DEFAULT_REVIEW_PREFIX: &str = "review";
+
+/// A remote repository where queues are published.
+pub struct QueueRemote<'c> {
+    ctx: &'c Ctx,
+    name: String,
+    namespace: String,
+    refs: Vec<(String, Oid)>,
+}
+
+/// The result of fetching a queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchOutcome {
+    /// The queue didn't exist locally and was created.
+    Created,
+    /// The local queue was moved to the remote state.
+    Updated,
+    /// The local queue has the remote state already.
+    UpToDate,
+    /// The local queue has changes that weren't published.
+    Ahead,
+}
+
+/// The result of pushing the review branch of a patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchOutcome {
+    /// The branch didn't exist in the remote and was created.
+    Created,
+    /// The branch was moved to the current commit of the patch.
+    Updated,
+    /// The branch has the current commit of the patch already.
+    UpToDate,
+    /// The patch was removed from the queue, and so was its branch.
+    Deleted,
+}
+
+/// The result of publishing a queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishOutcome {
+    /// The remote was updated with the local state.
+    Published,
+    /// The remote has the local state already.
+    UpToDate,
+}
+
+impl<'c> QueueRemote<'c> {
+    /// Connect to a remote, by default `qg.remote` or `origin`, and list its
+    /// references.
+    pub fn connect(ctx: &'c Ctx, name: Option<&str>) -> Result<Self, Error> {
+        let config = ctx.config();
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => config
+                .get_string("qg.remote")
+                .unwrap_or_else(|_| DEFAULT_REMOTE.to_string()),
+        };
+        let namespace = config
+            .get_string("qg.remoteNamespace")
+            .unwrap_or_else(|_| DEFAULT_NAMESPACE.to_string());
+        let namespace = namespace.trim_end_matches('/');
+        naming::validate_queue_name(namespace)
+            .map_err(|err| Error::Config(format!("qg.remoteNamespace: {}", err)))?;
+        if !namespace.starts_with("refs/") {
+            return Err(Error::Config(
+                "qg.remoteNamespace must be under `refs/`".to_string(),
+            ));
+        }
+
+        let mut remote = match ctx.repo().find_remote(&name) {
+            Ok(remote) => remote,
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                return Err(Error::Config(format!("remote `{}` does not exist", name)))
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let connection = remote.connect_auth(Direction::Fetch, Some(callbacks(ctx)), None)?;
+        let refs = connection
+            .list()?
+            .iter()
+            .map(|head| (head.name().to_string(), head.oid()))
+            .collect();
+
+        Ok(Self {
+            ctx,
+            name,
+            namespace: namespace.to_string(),
+            refs,
+        })
+    }
+
+    /// Name of the remote.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Names of the queues published in the remote.
+    pub fn queues(&self) -> impl Iterator<Item = &str> {
+        let prefix = format!("{}/logs/", self.namespace);
+        self.refs
+            .iter()
+            .filter_map(move |(name, _)| name.strip_prefix(&prefix))
+    }
+
+    /// Publish a queue, updating its references in the remote.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Diverged`] if the remote log isn't an ancestor of the
+    /// local one, i.e. it has changes that must be fetched first.
+    pub fn publish(&self, queue: &Queue<'_>) -> Result<PublishOutcome, Error> {
+        let repo = self.ctx.repo();
+        let name = queue.name();
+        let state = queue.state();
+        let local_log = state.oid().expect("queue state is committed");
+
+        let log_ref = self.log_ref(name);
+        match self.remote_oid(&log_ref) {
+            Some(remote_log) if remote_log == local_log => return Ok(PublishOutcome::UpToDate),
+            Some(remote_log) => {
+                // The remote log may not even exist locally.
+                let is_ancestor = repo.find_commit(remote_log).is_ok()
+                    && repo.graph_descendant_of(local_log, remote_log)?;
+                if !is_ancestor {
+                    return Err(Error::Diverged(name.to_string()));
+                }
+            }
+            None => {}
+        }
+
+        // The log is the only reference that can't be rewritten.
+        let mut refspecs = vec![
+            format!("{}:{}", self.ctx.refs().log_ref(name), log_ref),
+            format!(
+                "+refs/heads/{}:{}",
+                self.ctx.refs().queue_branch(name),
+                self.head_ref(name)
+            ),
+        ];
+        for (patch, _) in state.applied().chain(state.unapplied()) {
+            refspecs.push(format!(
+                "+{}:{}",
+                self.ctx.refs().patch_ref(name, patch),
+                self.patch_ref(name, patch)
+            ));
+        }
+        let patches_prefix = self.patch_ref(name, "");
+        for (remote_ref, _) in &self.refs {
+            match remote_ref.strip_prefix(&patches_prefix) {
+                Some(patch) if !patch.contains('/') && !state.has_patch(patch) => {
+                    refspecs.push(format!(":{}", remote_ref));
+                }
+                _ => {}
+            }
+        }
+
+        self.push(&refspecs)?;
+
+        Ok(PublishOutcome::Published)
+    }
+
+    /// Fetch a queue published in the remote, creating or updating the local one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Diverged`] if the local and remote logs diverged.
+    pub fn fetch(&self, name: &str) -> Result<FetchOutcome, Error> {
+        let log_ref = self.log_ref(name);
+        let remote_log = match self.remote_oid(&log_ref) {
+            Some(oid) => oid,
+            None => return Err(Error::NotPublished(name.to_string())),
+        };
+
+        let ctx = self.ctx;
+        let repo = ctx.repo();
+        let mut queue = match Queue::for_queue(ctx, name)? {
+            Some(queue) => queue,
+            None => {
+                self.download(&log_ref)?;
+                return match Queue::from_log(ctx, name, remote_log)? {
+                    Some(_) => Ok(FetchOutcome::Created),
+                    None => Err(Error::AlreadyExists("queue")),
+                };
+            }
+        };
+
+        let local_log = queue.state().oid().expect("queue state is committed");
+        if local_log == remote_log {
+            return Ok(FetchOutcome::UpToDate);
+        }
+        if repo.find_commit(remote_log).is_ok()
+            && repo.graph_descendant_of(local_log, remote_log)?
+        {
+            return Ok(FetchOutcome::Ahead);
+        }
+
+        self.download(&log_ref)?;
+        queue.fast_forward(remote_log, &format!("fetch from {}", self.name))?;
+
+        Ok(FetchOutcome::Updated)
+    }
+
+    /// Prefix of review branches, the given one or `qg.reviewPrefix`, or `review`.
+    pub fn review_prefix(&self, prefix: Option<&str>) -> Result<String, Error> {
+        let prefix = match prefix {
+            Some(prefix) => prefix.to_string(),
+            None => match self.ctx.config().get_string("qg.reviewPrefix") {
+                Ok(prefix) => prefix,
+                Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                    DEFAULT_REVIEW_PREFIX.to_string()
+                }
+                Err(err) => return Err(err.into()),
+            },
+        };
+        let prefix = prefix.trim_end_matches('/');
+        naming::validate_queue_name(prefix)?;
+
+        Ok(prefix.to_string())
+    }
+
+    /// Name of the review branch of a patch, see [`QueueRemote::push_branches`].
+    pub fn review_branch(prefix: &str, queue: &str, patch: &str) -> String {
+        format!("{}/{}/{}", prefix, queue, patch)
+    }
+
+    /// Push each applied patch of a queue as a branch named
+    /// `<prefix>/<queue>/<patch>`, see [`QueueRemote::review_prefix`].
+    ///
+    /// Only the branches of changed patches are pushed, and the ones of patches
+    /// removed from the queue are deleted. Branches of unapplied patches are left
+    /// as they are. Returns the name of each branch and what was done with it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BranchChanged`] if a branch in the remote doesn't have
+    /// the commit last pushed, e.g. someone else pushed to it, either when
+    /// connecting to the remote or when pushing. A branch deleted from the
+    /// remote, e.g. after merging it, is created again.
+    pub fn push_branches(
+        &self,
+        queue: &mut Queue<'_>,
+        prefix: &str,
+    ) -> Result<Vec<(String, BranchOutcome)>, Error> {
+        let name = queue.name().to_string();
+        let state = queue.state();
+        let recorded = state.pushed_branches(&self.name, prefix);
+        let last_pushed = |patch: &str| {
+            recorded
+                .iter()
+                .find(|&&(p, _)| p == patch)
+                .map(|&(_, oid)| oid)
+        };
+        let branch = |patch: &str| Self::review_branch(prefix, &name, patch);
+
+        let mut leases = Vec::new();
+        let mut outcomes = Vec::new();
+        let mut pushed = Vec::new();
+        for (patch, oid) in state.applied() {
+            let branch = branch(patch);
+            let remote_ref = format!("refs/heads/{}", branch);
+            let current = self.remote_oid(&remote_ref);
+            let outcome = match current {
+                Some(current) if current == oid => BranchOutcome::UpToDate,
+                None => BranchOutcome::Created,
+                _ if current != last_pushed(patch) => return Err(Error::BranchChanged(branch)),
+                Some(_) => BranchOutcome::Updated,
+            };
+            if outcome != BranchOutcome::UpToDate {
+                let source = self.ctx.refs().patch_ref(&name, patch);
+                leases.push((format!("{}:{}", source, remote_ref), remote_ref, current));
+            }
+            pushed.push((patch.to_string(), oid));
+            outcomes.push((branch, outcome));
+        }
+        for &(patch, oid) in &recorded {
+            if state.applied().any(|(p, _)| p == patch) {
+                continue;
+            }
+            if state.has_patch(patch) {
+                pushed.push((patch.to_string(), oid));
+                continue;
+            }
+
+            let branch = branch(patch);
+            let remote_ref = format!("refs/heads/{}", branch);
+            match self.remote_oid(&remote_ref) {
+                None => {}
+                Some(current) if current == oid => {
+                    leases.push((format!(":{}", remote_ref), remote_ref, Some(oid)));
+                    outcomes.push((branch, BranchOutcome::Deleted));
+                }
+                Some(_) => return Err(Error::BranchChanged(branch)),
+            }
+        }
+
+        if !leases.is_empty() {
+            self.push_with_lease(&leases)?;
+        }
+        let mut previous: Vec<_> = recorded
+            .iter()
+            .map(|&(p, oid)| (p.to_string(), oid))
+            .collect();
+        previous.sort();
+        pushed.sort();
+        if pushed != previous {
+            queue.record_branches(&self.name, prefix, pushed)?;
+        }
+
+        Ok(outcomes)
+    }
+
+    /// Download the log of a queue published in the remote, without updating
+    /// the local queue, returning the commit of its last entry.
+    ///
+    /// Returns `None` if the queue isn't published in the remote.
+    pub fn fetch_log(&self, name: &str) -> Result<Option<Oid>, Error> {
+        let log_ref = self.log_ref(name);
+        match self.remote_oid(&log_ref) {
+            Some(oid) => {
+                self.download(&log_ref)?;
+                Ok(Some(oid))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn remote_oid(&self, name: &str) -> Option<Oid> {
+        self.refs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|&(_, oid)| oid)
+    }
+
+    fn head_ref(&self, queue: &str) -> String {
+        format!("{}/heads/{}", self.namespace, queue)
+    }
+
+    fn log_ref(&self, queue: &str) -> String {
+        format!("{}/logs/{}", self.namespace, queue)
+    }
+
+    fn patch_ref(&self, queue: &str, patch: &str) -> String {
+        format!("{}/patches/{}/{}", self.namespace, queue, patch)
+    }
+
+    /// Push refspecs with `git push`, each one only if its remote reference still
+    /// has the given commit, or doesn't exist if `None`.
+    ///
+    /// Returns [`Error::BranchChanged`] if the remote refused a branch because
+    /// it changed.
+    fn push_with_lease(&self, leases: &[(String, String, Option<Oid>)]) -> Result<(), Error> {
+        let mut cmd = Command::new("git");
+        cmd.arg("--git-dir")
+            .arg(self.ctx.repo().path())
+            .args(["push", "--porcelain"]);
+        for (_, remote_ref, expected) in leases {
+            let expected = expected.map(|oid| oid.to_string()).unwrap_or_default();
+            cmd.arg(format!("--force-with-lease={}:{}", remote_ref, expected));
+        }
+        cmd.arg(&self.name)
+            .args(leases.iter().map(|(refspec, _, _)| refspec));
+
+        tracing::debug!(remote = %self.name, "pushing with lease");
+        let output = cmd.output()?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        // Rejected references are reported as `!\t<src>:<dst>\t[rejected] (<reason>)`.
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for line in stdout.lines() {
+            let mut fields = line.split('\t');
+            if fields.next() != Some("!") {
+                continue;
+            }
+            let remote_ref = fields.next().and_then(|refs| refs.split(':').nth(1));
+            let stale = fields.next().is_some_and(|s| s.contains("stale info"));
+            if let (Some(remote_ref), true) = (remote_ref, stale) {
+                let branch = remote_ref.strip_prefix("refs/heads/").unwrap_or(remote_ref);
+                return Err(Error::BranchChanged(branch.to_string()));
+            }
+        }
+
+        Err(Error::Git(git2::Error::new(
+            git2::ErrorCode::GenericError,
+            git2::ErrorClass::Net,
+            format!(
+                "git push failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        )))
+    }
+
+    /// Download the objects of a remote reference, without updating any local
+    /// reference.
+    fn download(&self, remote_ref: &str) -> Result<(), Error> {
+        let mut remote = self.ctx.repo().find_remote(&self.name)?;
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks(self.ctx));
+        remote.fetch(&[remote_ref], Some(&mut options), None)?;
+
+        Ok(())
+    }
+
+    fn push(&self, refspecs: &[String]) -> Result<(), Error> {
+        let mut rejected = Vec::new();
+        {
+            let mut callbacks = callbacks(self.ctx);
+            callbacks.push_update_reference(|name, status| {
+                if let Some(status) = status {
+                    rejected.push(format!("{}: {}", name, status));
+                }
+                Ok(())
+            });
+            let mut options = PushOptions::new();
+            options.remote_callbacks(callbacks);
+
+            let mut remote = self.ctx.repo().find_remote(&self.name)?;
+            remote.push(refspecs, Some(&mut options))?;
+        }
+
+        if rejected.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Git(git2::Error::new(
+                git2::ErrorCode::NotFastForward,
+                git2::ErrorClass::Net,
+                format!("the remote rejected {}", rejected.join(", ")),
+            )))
+        }
+    }
+}
+
+/// Callbacks authenticating with the SSH agent or the Git credential helpers.
+///
+/// libgit2 asks again for as long as the credentials are rejected, so each kind
+/// is only tried once.
+fn callbacks(ctx: &Ctx) -> RemoteCallbacks<'_> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut tried = CredentialType::empty();
+    callbacks.credentials(move |url, username, allowed| {
+        let untried = allowed - tried;
+        if untried.contains(CredentialType::SSH_KEY) {
+            tried |= CredentialType::SSH_KEY;
+            Cred::ssh_key_from_agent(username.unwrap_or("git"))
+        } else if untried.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            tried |= CredentialType::USER_PASS_PLAINTEXT;
+            Cred::credential_helper(ctx.config(), url, username)
+        } else if untried.contains(CredentialType::DEFAULT) {
+            tried |= CredentialType::DEFAULT;
+            Cred::default()
+        } else {
+            Err(git2::Error::from_str(&format!(
+                "authentication failed for {}",
+                url
+            )))
+        }
+    });
+
+    callbacks
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::Repository;
+
+    use super::*;
+    use crate::testing::{add_patch, base_branch, clone_of, setup};
+
+    #[test]
+    fn publish_and_fetch() {
+        let (dir, remote) = setup();
+        let ours = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ours, "q", base_branch(&ours))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+
+        let origin = QueueRemote::connect(&ours, None).unwrap();
+        assert_eq!(origin.publish(&queue).unwrap(), PublishOutcome::Published);
+        let origin = QueueRemote::connect(&ours, None).unwrap();
+        assert_eq!(origin.queues().collect::<Vec<_>>(), ["q"]);
+        assert_eq!(origin.publish(&queue).unwrap(), PublishOutcome::UpToDate);
+
+        let theirs = clone_of(&dir, "theirs", &remote);
+        let origin = QueueRemote::connect(&theirs, None).unwrap();
+        assert_eq!(origin.fetch("q").unwrap(), FetchOutcome::Created);
+        let fetched = Queue::for_queue(&theirs, "q").unwrap().unwrap();
+        assert_eq!(fetched.state().oid(), queue.state().oid());
+        assert_eq!(fetched.state().patch("one"), queue.state().patch("one"));
+        assert_eq!(origin.fetch("q").unwrap(), FetchOutcome::UpToDate);
+
+        add_patch(&mut queue, "two");
+        QueueRemote::connect(&ours, None)
+            .unwrap()
+            .publish(&queue)
+            .unwrap();
+
+        let origin = QueueRemote::connect(&theirs, None).unwrap();
+        assert_eq!(origin.fetch("q").unwrap(), FetchOutcome::Updated);
+        let fetched = Queue::for_queue(&theirs, "q").unwrap().unwrap();
+        assert_eq!(fetched.state().oid(), queue.state().oid());
+        assert_eq!(fetched.state().head(), queue.state().head());
+        let patch_ref = theirs.refs().patch_ref("q", "two");
+        assert_eq!(
+            theirs.repo().refname_to_id(&patch_ref).ok(),
+            queue.state().patch("two")
+        );
+    }
+
+    #[test]
+    fn diverged_queues() {
+        let (dir, remote) = setup();
+        let ours = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ours, "q", base_branch(&ours))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+        QueueRemote::connect(&ours, None)
+            .unwrap()
+            .publish(&queue)
+            .unwrap();
+
+        let theirs = clone_of(&dir, "theirs", &remote);
+        QueueRemote::connect(&theirs, None)
+            .unwrap()
+            .fetch("q")
+            .unwrap();
+        let mut their_queue = Queue::for_queue(&theirs, "q").unwrap().unwrap();
+        add_patch(&mut their_queue, "theirs");
+
+        add_patch(&mut queue, "ours");
+        QueueRemote::connect(&ours, None)
+            .unwrap()
+            .publish(&queue)
+            .unwrap();
+
+        let origin = QueueRemote::connect(&theirs, None).unwrap();
+        let before = their_queue.state().oid();
+        assert!(matches!(origin.fetch("q"), Err(Error::Diverged(_))));
+        assert!(matches!(
+            origin.publish(&their_queue),
+            Err(Error::Diverged(_))
+        ));
+        // Nothing was moved.
+        let their_queue = Queue::for_queue(&theirs, "q").unwrap().unwrap();
+        assert_eq!(their_queue.state().oid(), before);
+        assert!(their_queue.state().has_patch("theirs"));
+        assert!(!their_queue.state().has_patch("ours"));
+    }
+
+    #[test]
+    fn push_review_branches() {
+        let (dir, remote) = setup();
+        let ours = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ours, "q", base_branch(&ours))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+        add_patch(&mut queue, "two");
+
+        let push = |queue: &mut Queue<'_>| {
+            QueueRemote::connect(&ours, None)
+                .unwrap()
+                .push_branches(queue, "review")
+        };
+        let outcomes = push(&mut queue).unwrap();
+        assert_eq!(
+            outcomes,
+            [
+                ("review/q/one".to_string(), BranchOutcome::Created),
+                ("review/q/two".to_string(), BranchOutcome::Created),
+            ]
+        );
+
+        // A branch deleted in the remote, e.g. after merging it, is pushed again.
+        let bare = Repository::open_bare(remote.path()).unwrap();
+        bare.find_reference("refs/heads/review/q/one")
+            .unwrap()
+            .delete()
+            .unwrap();
+        let outcomes = push(&mut queue).unwrap();
+        assert_eq!(outcomes[0].1, BranchOutcome::Created);
+        assert_eq!(outcomes[1].1, BranchOutcome::UpToDate);
+
+        // A branch someone else pushed to isn't overwritten.
+        let main = bare.refname_to_id("refs/heads/main").unwrap();
+        bare.reference("refs/heads/review/q/two", main, true, "")
+            .unwrap();
+        assert!(matches!(
+            push(&mut queue),
+            Err(Error::BranchChanged(branch)) if branch == "review/q/two"
+        ));
+    }
+
+    #[test]
+    fn push_review_branches_with_lease() {
+        let (dir, remote) = setup();
+        let ours = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ours, "q", base_branch(&ours))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+        QueueRemote::connect(&ours, None)
+            .unwrap()
+            .push_branches(&mut queue, "review")
+            .unwrap();
+        queue
+            .reword_patches("reword".to_string(), |_, _| Some("changed".to_string()))
+            .unwrap();
+
+        // Someone pushes to the branch after it was listed.
+        let origin = QueueRemote::connect(&ours, None).unwrap();
+        let bare = Repository::open_bare(remote.path()).unwrap();
+        let main = bare.refname_to_id("refs/heads/main").unwrap();
+        bare.reference("refs/heads/review/q/one", main, true, "")
+            .unwrap();
+
+        assert!(matches!(
+            origin.push_branches(&mut queue, "review"),
+            Err(Error::BranchChanged(branch)) if branch == "review/q/one"
+        ));
+        assert_eq!(bare.refname_to_id("refs/heads/review/q/one").unwrap(), main);
+    }
+}
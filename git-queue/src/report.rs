@@ -0,0 +1,547 @@
+//! # Reports
+//!
+//! Machine-readable views of queues, log entries and patches, serialized as JSON
+//! by the `--format json` output of the commands. Object IDs are serialized as
+//! full hexadecimal strings, like in the queue log, and optional values are
+//! `null` when absent, so every object always has the same fields.
+//!
+//! The format is stable: fields may be added to the objects, but existing ones
+//! won't be renamed, removed or change their meaning.
+//!
+//! ## Queue
+//!
+//! * `name`: name of the queue.
+//! * `current`: whether it is the current queue.
+//! * `base_name`: name of the base branch.
+//! * `base`: commit the queue is based on.
+//! * `head`: commit of the queue branch, the top patch or the base.
+//! * `description`: description of the queue, or `null`.
+//! * `log`: current entry of the queue log.
+//! * `patches`: the patches, in push order, see below.
+//!
+//! ## Patch
+//!
+//! * `name`: name of the patch.
+//! * `commit`: commit of the patch.
+//! * `state`: `applied` or `unapplied`.
+//! * `top`: whether it is the top patch.
+//! * `review`: the review of the patch, with `id`, `url`, `status` and the
+//!   `commit` last sent, or `null`.
+//! * `signature`: the signature of the commit, see below, or `null` if it
+//!   wasn't verified.
+//!
+//! ## Log entry
+//!
+//! * `entry`: commit of the entry.
+//! * `message`: the operation recorded in the entry.
+//! * `previous`: the previous entry, or `null` for the first one.
+//! * `merged`: the entry merged into this one, or `null`.
+//! * `base`, `head`: commits of the base and the queue branch.
+//! * `applied`, `unapplied`: names of the patches, in push order.
+//! * `signature`: the signature of the entry commit, see below, or `null` if it
+//!   wasn't verified.
+//!
+//! ## Status
+//!
+//! * `queue`: the current queue, see above.
+//! * `behind`: how many commits the base branch has that the base of the queue
+//!   hasn't, or `null` if the base branch doesn't exist.
+//! * `applied`, `unapplied`: number of applied and unapplied patches.
+//! * `top`: name of the top patch, or `null` if no patch is applied.
+//! * `operation`: the Git operation in progress, e.g. `rebase` or
+//!   `cherry-pick`, or `null`.
+//! * `files`: the changed tracked files, with their `path` and whether they are
+//!   `conflicted`, `staged` or `modified` in the working tree.
+//!
+//! ## Signature
+//!
+//! * `status`: `good`, `bad`, `unknown` or `unsigned`.
+//! * `signer`: who made the signature, or `null` if it isn't known.
+//!
+//! ## Commit
+//!
+//! * `commit`: the commit.
+//! * `parents`: its parents.
+//! * `author`, `committer`: objects with `name`, `email` and `time`, in seconds
+//!   since the Unix epoch, and `offset`, the time zone offset in minutes.
+//! * `message`: the full message.
+//! * `files`: the changed files, with `path`, `status`, `insertions` and
+//!   `deletions`.
+//! * `diff`: the diff against the first parent as a patch, or `null` if it
+//!   wasn't requested.
+
+use git2::{Commit, Delta, DiffFormat, Repository, Signature};
+
+use crate::{
+    error::Error,
+    gpg::SignatureStatus,
+    queue::{
+        log::{LogOid, PatchReview, QueueState},
+        Queue,
+    },
+    status::{FileStatus, Operation},
+};
+
+/// A queue and its patches.
+#[derive(serde::Serialize)]
+pub struct QueueReport<'a> {
+    name: &'a str,
+    current: bool,
+    base_name: &'a str,
+    base: LogOid,
+    head: LogOid,
+    description: Option<&'a str>,
+    log: Option<LogOid>,
+    patches: Vec<PatchReport<'a>>,
+}
+
+impl<'a> QueueReport<'a> {
+    pub fn new(queue: &'a Queue<'_>) -> Self {
+        Self::from_state(queue.state(), queue.is_current())
+    }
+
+    /// The report of a queue at the given state.
+    pub fn from_state(state: &'a QueueState, current: bool) -> Self {
+        Self {
+            name: state.name(),
+            current,
+            base_name: state.base_name(),
+            base: LogOid(state.base()),
+            head: LogOid(state.head()),
+            description: state.description(),
+            log: state.oid().map(LogOid),
+            patches: PatchReport::all(state),
+        }
+    }
+
+    /// The patches of the queue, in push order.
+    pub fn patches_mut(&mut self) -> &mut [PatchReport<'a>] {
+        &mut self.patches
+    }
+}
+
+/// The status of the current queue and the working tree.
+#[derive(serde::Serialize)]
+pub struct StatusReport<'a> {
+    queue: QueueReport<'a>,
+    behind: Option<usize>,
+    applied: usize,
+    unapplied: usize,
+    top: Option<&'a str>,
+    operation: Option<&'static str>,
+    files: &'a [FileStatus],
+}
+
+impl<'a> StatusReport<'a> {
+    pub fn new(
+        queue: &'a Queue<'_>,
+        behind: Option<usize>,
+        operation: Option<Operation>,
+        files: &'a [FileStatus],
+    ) -> Self {
+        let state = queue.state();
+
+        Self {
+            queue: QueueReport::new(queue),
+            behind,
+            applied: state.applied().count(),
+            unapplied: state.unapplied().count(),
+            top: state.applied().last().map(|(name, _)| name),
+            operation: operation.map(Operation::name),
+            files,
+        }
+    }
+}
+
+/// Whether a patch is applied.
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchState {
+    Applied,
+    Unapplied,
+}
+
+/// A patch of a queue.
+#[derive(serde::Serialize)]
+pub struct PatchReport<'a> {
+    name: &'a str,
+    commit: LogOid,
+    state: PatchState,
+    top: bool,
+    review: Option<ReviewReport<'a>>,
+    signature: Option<SignatureReport>,
+}
+
+impl<'a> PatchReport<'a> {
+    /// The reports of all patches of a queue, in push order.
+    pub fn all(state: &'a QueueState) -> Vec<Self> {
+        let applied: Vec<_> = state.applied().collect();
+        // Unapplied patches are kept as a stack, the next one to be pushed is the last.
+        let mut unapplied: Vec<_> = state.unapplied().collect();
+        unapplied.reverse();
+
+        let top = applied.len().checked_sub(1);
+        let report = |name, oid, kind, is_top| PatchReport {
+            name,
+            commit: LogOid(oid),
+            state: kind,
+            top: is_top,
+            review: state.review(name).map(ReviewReport::new),
+            signature: None,
+        };
+
+        applied
+            .into_iter()
+            .enumerate()
+            .map(|(i, (name, oid))| report(name, oid, PatchState::Applied, Some(i) == top))
+            .chain(
+                unapplied
+                    .into_iter()
+                    .map(|(name, oid)| report(name, oid, PatchState::Unapplied, false)),
+            )
+            .collect()
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// The commit of the patch.
+    pub fn commit(&self) -> git2::Oid {
+        self.commit.0
+    }
+
+    pub fn state(&self) -> PatchState {
+        self.state
+    }
+
+    /// Is this the top patch of the queue?
+    pub fn is_top(&self) -> bool {
+        self.top
+    }
+
+    /// Record the result of verifying the signature of the patch.
+    pub fn set_signature(&mut self, status: &SignatureStatus) {
+        self.signature = Some(SignatureReport::new(status));
+    }
+}
+
+/// The review of a patch, unlike in the queue log with `null` for the absent
+/// values.
+#[derive(serde::Serialize)]
+struct ReviewReport<'a> {
+    id: &'a str,
+    url: Option<&'a str>,
+    status: Option<&'a str>,
+    commit: LogOid,
+}
+
+impl<'a> ReviewReport<'a> {
+    fn new(review: &'a PatchReview) -> Self {
+        Self {
+            id: &review.id,
+            url: review.url.as_deref(),
+            status: review.status.as_deref(),
+            commit: LogOid(review.commit()),
+        }
+    }
+}
+
+/// An entry of the log of a queue.
+#[derive(serde::Serialize)]
+pub struct LogEntryReport<'a> {
+    entry: LogOid,
+    message: &'a str,
+    previous: Option<LogOid>,
+    merged: Option<LogOid>,
+    base: LogOid,
+    head: LogOid,
+    applied: Vec<&'a str>,
+    unapplied: Vec<&'a str>,
+    signature: Option<SignatureReport>,
+}
+
+impl<'a> LogEntryReport<'a> {
+    /// The report of a committed log entry.
+    pub fn new(state: &'a QueueState) -> Result<Self, Error> {
+        let entry = state.oid().ok_or(Error::Inconsistency("queuelog"))?;
+        let mut unapplied: Vec<_> = state.unapplied().map(|(n, _)| n).collect();
+        unapplied.reverse();
+
+        Ok(Self {
+            entry: LogOid(entry),
+            message: state.message(),
+            previous: state.previous_oid().map(LogOid),
+            merged: state.merged().map(LogOid),
+            base: LogOid(state.base()),
+            head: LogOid(state.head()),
+            applied: state.applied().map(|(n, _)| n).collect(),
+            unapplied,
+            signature: None,
+        })
+    }
+
+    /// Record the result of verifying the signature of the entry.
+    pub fn set_signature(&mut self, status: &SignatureStatus) {
+        self.signature = Some(SignatureReport::new(status));
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SignatureReport {
+    status: &'static str,
+    signer: Option<String>,
+}
+
+impl SignatureReport {
+    fn new(status: &SignatureStatus) -> Self {
+        let (status, signer) = match status {
+            SignatureStatus::Unsigned => ("unsigned", None),
+            SignatureStatus::Good(signer) => ("good", Some(signer)),
+            SignatureStatus::Bad(signer) => ("bad", Some(signer).filter(|s| !s.is_empty())),
+            SignatureStatus::Unknown => ("unknown", None),
+        };
+
+        Self {
+            status,
+            signer: signer.cloned(),
+        }
+    }
+}
+
+/// A commit, with its changes.
+#[derive(serde::Serialize)]
+pub struct CommitReport {
+    commit: LogOid,
+    parents: Vec<LogOid>,
+    author: PersonReport,
+    committer: PersonReport,
+    message: String,
+    files: Vec<FileReport>,
+    diff: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct PersonReport {
+    name: String,
+    email: String,
+    time: i64,
+    offset: i32,
+}
+
+impl PersonReport {
+    fn new(signature: &Signature<'_>) -> Self {
+        Self {
+            name: String::from_utf8_lossy(signature.name_bytes()).into_owned(),
+            email: String::from_utf8_lossy(signature.email_bytes()).into_owned(),
+            time: signature.when().seconds(),
+            offset: signature.when().offset_minutes(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct FileReport {
+    path: String,
+    status: &'static str,
+    insertions: usize,
+    deletions: usize,
+}
+
+impl CommitReport {
+    /// The report of a commit, with the diff against its first parent if `diff`
+    /// is set.
+    pub fn new(repo: &Repository, commit: &Commit<'_>, diff: bool) -> Result<Self, Error> {
+        let changes = crate::export::commit_diff(repo, commit)?;
+
+        let mut files = Vec::with_capacity(changes.deltas().len());
+        for idx in 0..changes.deltas().len() {
+            let delta = changes.get_delta(idx).expect("index in bounds");
+            let (_, insertions, deletions) = match git2::Patch::from_diff(&changes, idx)? {
+                Some(patch) => patch.line_stats()?,
+                None => (0, 0, 0),
+            };
+            let path = delta.new_file().path().or_else(|| delta.old_file().path());
+            files.push(FileReport {
+                path: path
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                status: delta_status(delta.status()),
+                insertions,
+                deletions,
+            });
+        }
+
+        let diff = if diff {
+            let mut text = Vec::new();
+            changes.print(DiffFormat::Patch, |_, _, line| {
+                if matches!(line.origin(), '+' | '-' | ' ') {
+                    text.push(line.origin() as u8);
+                }
+                text.extend_from_slice(line.content());
+                true
+            })?;
+            Some(String::from_utf8_lossy(&text).into_owned())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            commit: LogOid(commit.id()),
+            parents: commit.parent_ids().map(LogOid).collect(),
+            author: PersonReport::new(&commit.author()),
+            committer: PersonReport::new(&commit.committer()),
+            message: String::from_utf8_lossy(commit.message_bytes()).into_owned(),
+            files,
+            diff,
+        })
+    }
+}
+
+fn delta_status(status: Delta) -> &'static str {
+    match status {
+        Delta::Added => "added",
+        Delta::Deleted => "deleted",
+        Delta::Modified => "modified",
+        Delta::Renamed => "renamed",
+        Delta::Copied => "copied",
+        Delta::Typechange => "typechange",
+        _ => "unmodified",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_patch, base_branch, clone_of, setup};
+    use serde_json::{json, Value};
+
+    #[test]
+    fn queue_and_log_reports() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        for patch in ["one", "two", "three"] {
+            add_patch(&mut queue, patch);
+        }
+        let patches: Vec<_> = queue
+            .state()
+            .applied()
+            .map(|(name, oid)| (name.to_string(), oid))
+            .collect();
+        let base = queue.state().base();
+        let (applied, unapplied) = patches.split_at(1);
+        queue
+            .replace_stack(
+                "pop".to_string(),
+                base,
+                applied.to_vec(),
+                unapplied.to_vec(),
+                |_| {},
+            )
+            .unwrap();
+        let review = PatchReview::new("1".to_string(), None, None, patches[0].1);
+        queue
+            .record_reviews(
+                "review".to_string(),
+                vec![("one".to_string(), Some(review))],
+            )
+            .unwrap();
+
+        queue.switch_to().unwrap();
+
+        let oid = |idx: usize| patches[idx].1.to_string();
+        let mut report = QueueReport::new(&queue);
+        report.patches_mut()[0].set_signature(&SignatureStatus::Bad(String::new()));
+        let report = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            report,
+            json!({
+                "name": "q",
+                "current": true,
+                "base_name": "main",
+                "base": base.to_string(),
+                "head": oid(0),
+                "description": null,
+                "log": queue.state().oid().unwrap().to_string(),
+                "patches": [
+                    {
+                        "name": "one",
+                        "commit": oid(0),
+                        "state": "applied",
+                        "top": true,
+                        "review": {"id": "1", "url": null, "status": null, "commit": oid(0)},
+                        "signature": {"status": "bad", "signer": null},
+                    },
+                    {
+                        "name": "two",
+                        "commit": oid(1),
+                        "state": "unapplied",
+                        "top": false,
+                        "review": null,
+                        "signature": null,
+                    },
+                    {
+                        "name": "three",
+                        "commit": oid(2),
+                        "state": "unapplied",
+                        "top": false,
+                        "review": null,
+                        "signature": null,
+                    },
+                ],
+            })
+        );
+
+        let state = queue.state();
+        let mut entry = LogEntryReport::new(state).unwrap();
+        entry.set_signature(&SignatureStatus::Good("a@example.com".to_string()));
+        assert_eq!(
+            serde_json::to_value(&entry).unwrap(),
+            json!({
+                "entry": state.oid().unwrap().to_string(),
+                "message": "review",
+                "previous": state.previous_oid().unwrap().to_string(),
+                "merged": null,
+                "base": base.to_string(),
+                "head": oid(0),
+                "applied": ["one"],
+                "unapplied": ["two", "three"],
+                "signature": {"status": "good", "signer": "a@example.com"},
+            })
+        );
+    }
+
+    #[test]
+    fn commit_reports() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+        let repo = ctx.repo();
+        let commit = repo.find_commit(queue.state().head()).unwrap();
+
+        let report =
+            serde_json::to_value(CommitReport::new(repo, &commit, false).unwrap()).unwrap();
+        assert_eq!(report["commit"], commit.id().to_string());
+        assert_eq!(report["parents"], json!([queue.state().base().to_string()]));
+        assert_eq!(report["author"]["name"], "A");
+        assert_eq!(report["author"]["email"], "a@example.com");
+        assert_eq!(report["author"]["time"], commit.author().when().seconds());
+        assert_eq!(report["message"], "one");
+        assert_eq!(
+            report["files"],
+            json!([{"path": "one", "status": "added", "insertions": 1, "deletions": 0}])
+        );
+        assert_eq!(report["diff"], Value::Null);
+
+        let report = serde_json::to_value(CommitReport::new(repo, &commit, true).unwrap()).unwrap();
+        let diff = report["diff"].as_str().unwrap();
+        assert!(diff.starts_with("diff --git a/one b/one\n"), "{}", diff);
+        assert!(diff.contains("\n+one\n"), "{}", diff);
+    }
+}
@@ -0,0 +1,811 @@
+//! # Code Review
+//!
+//! Send the applied patches of a queue for review in a forge, as a stack of
+//! reviews, e.g. pull requests, where each one is based on the previous. The
+//! patches are pushed as review branches first, see [`crate::remote`], and the
+//! review of each patch is recorded in the queue log, so later runs update the
+//! reviews of changed patches and close the ones of removed patches.
+//!
+//! Forges are accessed through a [`ReviewProvider`], chosen in `qg.review.provider`.
+//! The only one for now is `http`, [`HttpProvider`], a generic provider for forges
+//! with a JSON API, configured by:
+//!
+//!   * `qg.review.createUrl`: URL where reviews are created, with `POST`.
+//!   * `qg.review.updateUrl`: URL template of a review, where `{id}` is replaced
+//!     by its identifier. Reviews are updated and closed with `PATCH`, and their
+//!     status read with `GET`.
+//!   * `qg.review.linkUrl`: optional URL where the identifiers of the stack are
+//!     sent with `POST`, from the bottom to the top, after it is updated.
+//!   * `qg.review.idField`: field of the responses with the review identifier,
+//!     defaults to `id`.
+//!   * `qg.review.token`: optional token sent in the `Authorization: Bearer`
+//!     header.
+//!
+//! Reviews are created and updated with a JSON object with the `title` and `body`
+//! of the review, the `head` branch with the patch, and the `base` branch it
+//! should be merged into. They are closed with `{"state": "closed"}`, and the
+//! stack is linked with `{"reviews": [<id>...]}`. Responses are a JSON object
+//! with the review identifier, and optionally its address in `html_url`,
+//! `web_url` or `url`, and its status in `state` or `status`, or `merged` if
+//! `merged` is `true`. This is compatible with the pull requests API of GitHub,
+//! with an `idField` of `number`.
+//!
+//! Requests are made with `curl`, so HTTPS and proxies work as configured for it.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use serde_json::{json, Value};
+
+use crate::{
+    error::Error,
+    queue::{log::PatchReview, Queue},
+    remote::QueueRemote,
+};
+
+/// A patch to create or update a review for.
+pub struct ReviewRequest<'a> {
+    /// Name of the patch.
+    pub patch: &'a str,
+    /// Title of the review, the patch subject.
+    pub title: &'a str,
+    /// Body of the review, the rest of the patch message.
+    pub body: &'a str,
+    /// Branch with the patch.
+    pub head: &'a str,
+    /// Branch the patch should be merged into, the one of the previous patch or
+    /// the base branch of the queue.
+    pub base: &'a str,
+}
+
+/// A review as reported by the forge.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReviewInfo {
+    /// Identifier of the review in the forge.
+    pub id: String,
+    /// Address of the review, if known.
+    pub url: Option<String>,
+    /// Status of the review, e.g. `open` or `merged`, if known.
+    pub status: Option<String>,
+}
+
+/// Manages reviews in a forge.
+pub trait ReviewProvider {
+    /// Create a review for a patch.
+    fn create(&self, request: &ReviewRequest<'_>) -> Result<ReviewInfo, Error>;
+
+    /// Update the review of a patch after it changed.
+    fn update(&self, id: &str, request: &ReviewRequest<'_>) -> Result<ReviewInfo, Error>;
+
+    /// Close the review of a patch removed from the queue.
+    fn close(&self, id: &str) -> Result<(), Error>;
+
+    /// Get the current state of a review.
+    fn status(&self, id: &str) -> Result<ReviewInfo, Error>;
+
+    /// Link the reviews of a stack, from the bottom to the top.
+    ///
+    /// Does nothing by default, as each review is based on the branch of the
+    /// previous one already.
+    fn link(&self, ids: &[&str]) -> Result<(), Error> {
+        let _ = ids;
+        Ok(())
+    }
+}
+
+/// What was done with the review of a patch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReviewOutcome {
+    /// The patch had no review and one was created.
+    Created,
+    /// The patch changed and its review was updated.
+    Updated,
+    /// The patch didn't change since its review was last updated.
+    UpToDate,
+    /// The patch was removed from the queue and its review was closed.
+    Closed,
+}
+
+/// The provider configured in `qg.review.provider`, `http` by default.
+pub fn provider(config: &git2::Config) -> Result<Box<dyn ReviewProvider>, Error> {
+    match config.get_string("qg.review.provider") {
+        Ok(name) if name == "http" => Ok(Box::new(HttpProvider::from_config(config)?)),
+        Ok(name) => Err(Error::Config(format!(
+            "unknown review provider `{}` in qg.review.provider",
+            name
+        ))),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => {
+            Ok(Box::new(HttpProvider::from_config(config)?))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Send the applied patches of a queue for review, pushing their review branches
+/// with `prefix` to `remote`.
+///
+/// Returns the name of each patch and what was done with its review. If the
+/// provider fails, the reviews changed before are still recorded.
+pub fn send(
+    provider: &dyn ReviewProvider,
+    remote: &QueueRemote<'_>,
+    queue: &mut Queue<'_>,
+    prefix: &str,
+) -> Result<Vec<(String, ReviewOutcome)>, Error> {
+    remote.push_branches(queue, prefix)?;
+
+    let repo = queue.ctx().repo();
+    let state = queue.state().clone();
+    let mut outcomes = Vec::new();
+    let mut changes = Vec::new();
+    let mut result = Ok(());
+
+    let mut base = queue.base_name().to_string();
+    let mut ids = Vec::new();
+    for (patch, oid) in state.applied() {
+        let head = QueueRemote::review_branch(prefix, queue.name(), patch);
+        let review = state.review(patch);
+        if let Some(review) = review.filter(|r| r.commit() == oid) {
+            ids.push(review.id.clone());
+            outcomes.push((patch.to_string(), ReviewOutcome::UpToDate));
+            base = head;
+            continue;
+        }
+
+        let commit = repo.find_commit(oid)?;
+        let message = commit.message().ok_or(Error::NonUtf8)?;
+        let (title, body) = match message.split_once("\n\n") {
+            Some((title, body)) => (title.trim(), body.trim()),
+            None => (message.trim(), ""),
+        };
+        let request = ReviewRequest {
+            patch,
+            title,
+            body,
+            head: &head,
+            base: &base,
+        };
+        let (info, outcome) = match review {
+            Some(review) => match provider.update(&review.id, &request) {
+                Ok(info) => (info, ReviewOutcome::Updated),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            },
+            None => match provider.create(&request) {
+                Ok(info) => (info, ReviewOutcome::Created),
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            },
+        };
+
+        ids.push(info.id.clone());
+        changes.push((
+            patch.to_string(),
+            Some(PatchReview::new(info.id, info.url, info.status, oid)),
+        ));
+        outcomes.push((patch.to_string(), outcome));
+        base = head;
+    }
+
+    if result.is_ok() {
+        for (patch, review) in state.reviews() {
+            if state.has_patch(patch) {
+                continue;
+            }
+            if let Err(err) = provider.close(&review.id) {
+                result = Err(err);
+                break;
+            }
+            changes.push((patch.to_string(), None));
+            outcomes.push((patch.to_string(), ReviewOutcome::Closed));
+        }
+    }
+
+    if result.is_ok() && !changes.is_empty() {
+        let ids: Vec<_> = ids.iter().map(String::as_str).collect();
+        result = provider.link(&ids);
+    }
+    if !changes.is_empty() {
+        queue.record_reviews("review".to_string(), changes)?;
+    }
+
+    result.map(|()| outcomes)
+}
+
+/// Update the recorded status of the reviews of a queue.
+pub fn refresh(provider: &dyn ReviewProvider, queue: &mut Queue<'_>) -> Result<(), Error> {
+    let mut changes = Vec::new();
+    for (patch, review) in queue.state().reviews() {
+        let info = provider.status(&review.id)?;
+        if info.status != review.status || (info.url.is_some() && info.url != review.url) {
+            let url = info.url.or_else(|| review.url.clone());
+            changes.push((
+                patch.to_string(),
+                Some(PatchReview::new(
+                    review.id.clone(),
+                    url,
+                    info.status,
+                    review.commit(),
+                )),
+            ));
+        }
+    }
+
+    if !changes.is_empty() {
+        queue.record_reviews("review status".to_string(), changes)?;
+    }
+
+    Ok(())
+}
+
+/// A provider for forges with a JSON HTTP API, see the [module docs](self).
+pub struct HttpProvider {
+    create_url: String,
+    update_url: String,
+    link_url: Option<String>,
+    id_field: String,
+    token: Option<String>,
+}
+
+impl HttpProvider {
+    /// Read the provider configuration from `qg.review.*`.
+    pub fn from_config(config: &git2::Config) -> Result<Self, Error> {
+        let get = |key: &str| match config.get_string(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(Error::from(err)),
+        };
+        let required =
+            |key: &str| get(key)?.ok_or_else(|| Error::Config(format!("{} is not set", key)));
+
+        let update_url = required("qg.review.updateUrl")?;
+        if !update_url.contains("{id}") {
+            return Err(Error::Config(
+                "qg.review.updateUrl must contain `{id}`".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            create_url: required("qg.review.createUrl")?,
+            update_url,
+            link_url: get("qg.review.linkUrl")?,
+            id_field: get("qg.review.idField")?.unwrap_or_else(|| "id".to_string()),
+            token: get("qg.review.token")?,
+        })
+    }
+
+    fn review_url(&self, id: &str) -> String {
+        self.update_url.replace("{id}", id)
+    }
+
+    fn info(&self, response: &Value) -> Result<ReviewInfo, Error> {
+        let id = match &response[&self.id_field] {
+            Value::String(id) => id.clone(),
+            Value::Number(id) => id.to_string(),
+            _ => {
+                return Err(Error::Review(format!(
+                    "response without `{}` field",
+                    self.id_field
+                )))
+            }
+        };
+        let url = ["html_url", "web_url", "url"]
+            .iter()
+            .find_map(|field| response[field].as_str())
+            .map(str::to_string);
+        let status = if response["merged"] == Value::Bool(true) {
+            Some("merged".to_string())
+        } else {
+            ["state", "status"]
+                .iter()
+                .find_map(|field| response[field].as_str())
+                .map(str::to_string)
+        };
+
+        Ok(ReviewInfo { id, url, status })
+    }
+
+    /// Send a request with `curl`, returning the JSON response, or `null` if it
+    /// is empty.
+    fn request(&self, method: &str, url: &str, body: Option<&Value>) -> Result<Value, Error> {
+        // Keep the token out of the command line.
+        let mut headers = tempfile::NamedTempFile::new()?;
+        writeln!(headers, "Content-Type: application/json")?;
+        writeln!(headers, "Accept: application/json")?;
+        if let Some(token) = &self.token {
+            writeln!(headers, "Authorization: Bearer {}", token)?;
+        }
+        headers.flush()?;
+
+        let mut cmd = Command::new("curl");
+        cmd.args([
+            "--silent",
+            "--show-error",
+            "--location",
+            "--request",
+            method,
+        ])
+        .arg("--header")
+        .arg(format!("@{}", headers.path().display()))
+        .args(["--write-out", "\n%{http_code}"]);
+        if body.is_some() {
+            cmd.args(["--data-binary", "@-"]);
+        }
+        cmd.arg(url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        tracing::debug!(method, url, "sending review request");
+        let mut child = cmd
+            .spawn()
+            .map_err(|err| Error::Review(format!("failed to run curl: {}", err)))?;
+        {
+            let mut stdin = child.stdin.take().expect("stdin is piped");
+            if let Some(body) = body {
+                serde_json::to_writer(&mut stdin, body)
+                    .map_err(|err| Error::Review(err.to_string()))?;
+            }
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(Error::Review(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+
+        let stdout = String::from_utf8(output.stdout).map_err(|_| Error::NonUtf8)?;
+        let (response, code) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+        let code: u16 = code.trim().parse().unwrap_or_default();
+        if !(200..300).contains(&code) {
+            return Err(Error::Review(format!(
+                "{} {} returned HTTP {}: {}",
+                method,
+                url,
+                code,
+                response.trim()
+            )));
+        }
+
+        if response.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(response)
+            .map_err(|err| Error::Review(format!("invalid response from {}: {}", url, err)))
+    }
+}
+
+impl ReviewProvider for HttpProvider {
+    fn create(&self, request: &ReviewRequest<'_>) -> Result<ReviewInfo, Error> {
+        let response = self.request("POST", &self.create_url, Some(&body(request)))?;
+        self.info(&response)
+    }
+
+    fn update(&self, id: &str, request: &ReviewRequest<'_>) -> Result<ReviewInfo, Error> {
+        let response = self.request("PATCH", &self.review_url(id), Some(&body(request)))?;
+        self.info(&response)
+    }
+
+    fn close(&self, id: &str) -> Result<(), Error> {
+        let body = json!({ "state": "closed" });
+        self.request("PATCH", &self.review_url(id), Some(&body))?;
+        Ok(())
+    }
+
+    fn status(&self, id: &str) -> Result<ReviewInfo, Error> {
+        let response = self.request("GET", &self.review_url(id), None)?;
+        self.info(&response)
+    }
+
+    fn link(&self, ids: &[&str]) -> Result<(), Error> {
+        if let Some(url) = &self.link_url {
+            self.request("POST", url, Some(&json!({ "reviews": ids })))?;
+        }
+        Ok(())
+    }
+}
+
+fn body(request: &ReviewRequest<'_>) -> Value {
+    json!({
+        "title": request.title,
+        "body": request.body,
+        "head": request.head,
+        "base": request.base,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_patch, base_branch, clone_of, setup};
+    use std::cell::RefCell;
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+
+    /// A request received by the fake forge.
+    #[derive(Debug)]
+    struct Received {
+        method: String,
+        path: String,
+        authorization: Option<String>,
+        body: Value,
+    }
+
+    /// A fake forge answering each request with the next response, an HTTP
+    /// status and a body, returning its address and the requests it received.
+    fn fake_forge(
+        responses: Vec<(u16, &'static str)>,
+    ) -> (String, std::thread::JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            for (status, response) in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap().to_string();
+                let path = parts.next().unwrap().to_string();
+
+                let mut length = 0;
+                let mut authorization = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    let (name, value) = line.split_once(": ").unwrap();
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.parse().unwrap();
+                    } else if name.eq_ignore_ascii_case("authorization") {
+                        authorization = Some(value.to_string());
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let body = if body.is_empty() {
+                    Value::Null
+                } else {
+                    serde_json::from_slice(&body).unwrap()
+                };
+                received.push(Received {
+                    method,
+                    path,
+                    authorization,
+                    body,
+                });
+
+                let mut writer = stream;
+                write!(
+                    writer,
+                    "HTTP/1.1 {} Fake\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+            received
+        });
+
+        (address, handle)
+    }
+
+    /// A provider for the fake forge at `address`, with the given extra
+    /// configuration.
+    fn provider(address: &str, extra: &[(&str, &str)]) -> HttpProvider {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut config = git2::Config::open(file.path()).unwrap();
+        config
+            .set_str("qg.review.createUrl", &format!("{}/reviews", address))
+            .unwrap();
+        config
+            .set_str(
+                "qg.review.updateUrl",
+                &format!("{}/reviews/{{id}}", address),
+            )
+            .unwrap();
+        for (key, value) in extra {
+            config.set_str(key, value).unwrap();
+        }
+        HttpProvider::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn http_provider_requests() {
+        let (address, forge) = fake_forge(vec![
+            (
+                201,
+                r#"{"number": 7, "html_url": "https://forge/7", "state": "open"}"#,
+            ),
+            (200, r#"{"number": 7, "state": "open"}"#),
+            (200, r#"{"number": 7, "state": "closed", "merged": true}"#),
+            (200, ""),
+            (204, ""),
+            (422, r#"{"message": "invalid base"}"#),
+        ]);
+        let provider = provider(
+            &address,
+            &[
+                ("qg.review.idField", "number"),
+                ("qg.review.linkUrl", &format!("{}/stack", address)),
+                ("qg.review.token", "secret"),
+            ],
+        );
+        let request = ReviewRequest {
+            patch: "fix",
+            title: "Fix it",
+            body: "Details.",
+            head: "review/q/fix",
+            base: "main",
+        };
+
+        let created = provider.create(&request).unwrap();
+        assert_eq!(
+            created,
+            ReviewInfo {
+                id: "7".to_string(),
+                url: Some("https://forge/7".to_string()),
+                status: Some("open".to_string()),
+            }
+        );
+        assert_eq!(provider.update("7", &request).unwrap().url, None);
+        assert_eq!(
+            provider.status("7").unwrap().status.as_deref(),
+            Some("merged")
+        );
+        provider.close("7").unwrap();
+        provider.link(&["7", "8"]).unwrap();
+        match provider.create(&request) {
+            Err(Error::Review(message)) => assert!(message.contains("HTTP 422"), "{}", message),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+
+        let received = forge.join().unwrap();
+        let requests: Vec<_> = received
+            .iter()
+            .map(|r| (r.method.as_str(), r.path.as_str()))
+            .collect();
+        assert_eq!(
+            requests,
+            [
+                ("POST", "/reviews"),
+                ("PATCH", "/reviews/7"),
+                ("GET", "/reviews/7"),
+                ("PATCH", "/reviews/7"),
+                ("POST", "/stack"),
+                ("POST", "/reviews"),
+            ]
+        );
+        assert!(received
+            .iter()
+            .all(|r| r.authorization.as_deref() == Some("Bearer secret")));
+        let review = json!({
+            "title": "Fix it",
+            "body": "Details.",
+            "head": "review/q/fix",
+            "base": "main",
+        });
+        assert_eq!(received[0].body, review);
+        assert_eq!(received[1].body, review);
+        assert_eq!(received[2].body, Value::Null);
+        assert_eq!(received[3].body, json!({ "state": "closed" }));
+        assert_eq!(received[4].body, json!({ "reviews": ["7", "8"] }));
+    }
+
+    #[test]
+    fn send_stack_for_review() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+        add_patch(&mut queue, "two");
+
+        let (address, forge) = fake_forge(vec![
+            (201, r#"{"id": "a", "state": "open"}"#),
+            (201, r#"{"id": "b", "state": "open"}"#),
+        ]);
+        let provider = provider(&address, &[]);
+        let origin = QueueRemote::connect(&ctx, None).unwrap();
+        let outcomes = send(&provider, &origin, &mut queue, "review").unwrap();
+        assert_eq!(
+            outcomes,
+            [
+                ("one".to_string(), ReviewOutcome::Created),
+                ("two".to_string(), ReviewOutcome::Created),
+            ]
+        );
+
+        let received = forge.join().unwrap();
+        assert_eq!(received[0].body["head"], "review/q/one");
+        assert_eq!(received[0].body["base"], "main");
+        assert_eq!(received[1].body["head"], "review/q/two");
+        assert_eq!(received[1].body["base"], "review/q/one");
+
+        let state = queue.state();
+        assert_eq!(state.review("one").unwrap().id, "a");
+        assert_eq!(
+            state.review("two").unwrap().commit(),
+            state.patch("two").unwrap()
+        );
+
+        // Unchanged patches are neither pushed nor sent again, so no forge is
+        // needed.
+        let origin = QueueRemote::connect(&ctx, None).unwrap();
+        let outcomes = send(&provider, &origin, &mut queue, "review").unwrap();
+        assert!(outcomes.iter().all(|(_, o)| *o == ReviewOutcome::UpToDate));
+    }
+
+    /// A provider recording the calls made to it, where each review is
+    /// identified by its patch name and is merged.
+    #[derive(Default)]
+    struct FakeProvider {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl FakeProvider {
+        fn take_calls(&self) -> Vec<String> {
+            self.calls.take()
+        }
+    }
+
+    impl ReviewProvider for FakeProvider {
+        fn create(&self, request: &ReviewRequest<'_>) -> Result<ReviewInfo, Error> {
+            let call = format!("create {} on {}", request.patch, request.base);
+            self.calls.borrow_mut().push(call);
+            Ok(ReviewInfo {
+                id: request.patch.to_string(),
+                url: None,
+                status: Some("open".to_string()),
+            })
+        }
+
+        fn update(&self, id: &str, request: &ReviewRequest<'_>) -> Result<ReviewInfo, Error> {
+            let call = format!("update {} to {:?} on {}", id, request.title, request.base);
+            self.calls.borrow_mut().push(call);
+            Ok(ReviewInfo {
+                id: id.to_string(),
+                url: None,
+                status: Some("open".to_string()),
+            })
+        }
+
+        fn close(&self, id: &str) -> Result<(), Error> {
+            self.calls.borrow_mut().push(format!("close {}", id));
+            Ok(())
+        }
+
+        fn status(&self, id: &str) -> Result<ReviewInfo, Error> {
+            Ok(ReviewInfo {
+                id: id.to_string(),
+                url: Some(format!("https://forge/{}", id)),
+                status: Some("merged".to_string()),
+            })
+        }
+
+        fn link(&self, ids: &[&str]) -> Result<(), Error> {
+            self.calls
+                .borrow_mut()
+                .push(format!("link {}", ids.join(" ")));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn update_close_and_refresh_reviews() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        for patch in ["one", "two", "three"] {
+            add_patch(&mut queue, patch);
+        }
+        let provider = FakeProvider::default();
+        let origin = QueueRemote::connect(&ctx, None).unwrap();
+        send(&provider, &origin, &mut queue, "review").unwrap();
+        assert_eq!(
+            provider.take_calls(),
+            [
+                "create one on main",
+                "create two on review/q/one",
+                "create three on review/q/two",
+                "link one two three",
+            ]
+        );
+
+        // Reword `two` and drop `three`.
+        queue
+            .reword_patches("reword".to_string(), |patch, _| {
+                (patch == "two").then(|| "Two\n\nReworded.".to_string())
+            })
+            .unwrap();
+        let state = queue.state();
+        let applied: Vec<_> = state
+            .applied()
+            .take(2)
+            .map(|(name, oid)| (name.to_string(), oid))
+            .collect();
+        let base = state.base();
+        queue
+            .replace_stack("drop three".to_string(), base, applied, vec![], |_| {})
+            .unwrap();
+
+        let origin = QueueRemote::connect(&ctx, None).unwrap();
+        let outcomes = send(&provider, &origin, &mut queue, "review").unwrap();
+        assert_eq!(
+            outcomes,
+            [
+                ("one".to_string(), ReviewOutcome::UpToDate),
+                ("two".to_string(), ReviewOutcome::Updated),
+                ("three".to_string(), ReviewOutcome::Closed),
+            ]
+        );
+        assert_eq!(
+            provider.take_calls(),
+            [
+                "update two to \"Two\" on review/q/one",
+                "close three",
+                "link one two",
+            ]
+        );
+        let state = queue.state();
+        assert_eq!(state.message(), "review");
+        assert!(state.review("three").is_none());
+        assert_eq!(
+            state.review("two").unwrap().commit(),
+            state.patch("two").unwrap()
+        );
+
+        // The status of the reviews is recorded once it changes.
+        refresh(&provider, &mut queue).unwrap();
+        let state = queue.state().clone();
+        assert_eq!(state.message(), "review status");
+        let one = state.review("one").unwrap();
+        assert_eq!(one.status.as_deref(), Some("merged"));
+        assert_eq!(one.url.as_deref(), Some("https://forge/one"));
+        refresh(&provider, &mut queue).unwrap();
+        assert_eq!(queue.state().oid(), state.oid());
+    }
+
+    #[test]
+    fn provider_configuration() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut config = git2::Config::open(file.path()).unwrap();
+        let error = |config: &git2::Config| match super::provider(config) {
+            Err(Error::Config(message)) => message,
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        };
+
+        assert_eq!(error(&config), "qg.review.updateUrl is not set");
+        config
+            .set_str("qg.review.updateUrl", "https://forge/reviews")
+            .unwrap();
+        assert_eq!(error(&config), "qg.review.updateUrl must contain `{id}`");
+        config
+            .set_str("qg.review.updateUrl", "https://forge/reviews/{id}")
+            .unwrap();
+        assert_eq!(error(&config), "qg.review.createUrl is not set");
+        config
+            .set_str("qg.review.createUrl", "https://forge/reviews")
+            .unwrap();
+        assert!(super::provider(&config).is_ok());
+
+        config.set_str("qg.review.provider", "other").unwrap();
+        assert_eq!(
+            error(&config),
+            "unknown review provider `other` in qg.review.provider"
+        );
+    }
+}
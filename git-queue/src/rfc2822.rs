@@ -0,0 +1,388 @@
+//! Helpers to read and write RFC 2822 email messages, as used by patch files.
+
+use std::fmt::Write as _;
+
+/// Format a mailbox (`Name <email>`) for an address header.
+pub(crate) fn mailbox(name: &str, email: &str) -> String {
+    const SPECIALS: &[char] = &[
+        '(', ')', '<', '>', '[', ']', ':', ';', '@', '\\', ',', '.', '"',
+    ];
+
+    if name.is_empty() {
+        email.to_string()
+    } else if !name.is_ascii() {
+        format!("{} <{}>", encode_header(name), email)
+    } else if name.contains(SPECIALS) {
+        let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+        format!("\"{}\" <{}>", escaped, email)
+    } else {
+        format!("{} <{}>", name, email)
+    }
+}
+
+/// Parse a mailbox header value, returning the name and email.
+///
+/// Accepts `Name <email>`, `"Name" <email>`, `email (Name)` and a bare `email`.
+pub(crate) fn parse_mailbox(value: &str) -> (String, String) {
+    let value = decode_header(value.trim());
+
+    if let Some((name, rest)) = value.split_once('<') {
+        let email = rest.split('>').next().unwrap_or_default().trim();
+        let name = name.trim();
+        let name = match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+            Some(quoted) => unquote(quoted),
+            None => name.to_string(),
+        };
+        (name, email.to_string())
+    } else if let Some((email, rest)) = value.split_once('(') {
+        let name = rest.trim_end().trim_end_matches(')');
+        (name.to_string(), email.trim().to_string())
+    } else {
+        (String::new(), value)
+    }
+}
+
+/// Remove the backslash escapes of a quoted string.
+fn unquote(quoted: &str) -> String {
+    let mut unquoted = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+    unquoted
+}
+
+/// Encode a header value as a RFC 2047 encoded-word if it isn't ASCII.
+pub(crate) fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+
+    let mut encoded = String::from("=?UTF-8?q?");
+    for b in value.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'!' | b'*' | b'+' | b'-' | b'/' => {
+                encoded.push(b as char)
+            }
+            b' ' => encoded.push('_'),
+            _ => {
+                let _ = write!(encoded, "={:02X}", b);
+            }
+        }
+    }
+    encoded.push_str("?=");
+    encoded
+}
+
+/// Decode the RFC 2047 encoded-words in a header value.
+///
+/// Only UTF-8 and ASCII compatible charsets are supported, words in other
+/// charsets are decoded as if they were UTF-8.
+pub(crate) fn decode_header(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    let mut last_was_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let word = &rest[start + 2..];
+        let parsed = word.split_once('?').and_then(|(_charset, word)| {
+            let (encoding, word) = word.split_once('?')?;
+            let (text, after) = word.split_once("?=")?;
+            let bytes = match encoding {
+                "q" | "Q" => decode_q(text),
+                "b" | "B" => decode_base64(text)?,
+                _ => return None,
+            };
+            Some((String::from_utf8_lossy(&bytes).into_owned(), after))
+        });
+
+        match parsed {
+            Some((text, after)) => {
+                // Whitespace between adjacent encoded-words is ignored.
+                let between = &rest[..start];
+                if !(last_was_word && between.trim().is_empty()) {
+                    decoded.push_str(between);
+                }
+                decoded.push_str(&text);
+                rest = after;
+                last_was_word = true;
+            }
+            None => {
+                decoded.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                last_was_word = false;
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_q(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'_' => decoded.push(b' '),
+            b'=' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|h| u8::from_str_radix(h, 16).ok())
+                {
+                    Some(b) => {
+                        decoded.push(b);
+                        i += 2;
+                    }
+                    None => decoded.push(b'='),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    decoded
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        acc = (acc << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((acc >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Encode bytes in base64, with padding.
+pub(crate) fn encode_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let acc = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(acc >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Format a Git time as a RFC 2822 date, e.g. `Thu, 1 Jan 1970 00:00:00 +0000`.
+pub fn format_date(time: git2::Time) -> String {
+    let offset = time.offset_minutes();
+    let local = time.seconds() + i64::from(offset) * 60;
+    let days = local.div_euclid(86400);
+    let secs = local.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{}, {} {} {} {:02}:{:02}:{:02} {}{:02}{:02}",
+        // 1970-01-01 was a Thursday.
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60,
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60,
+    )
+}
+
+/// Parse a RFC 2822 date, e.g. `Thu, 1 Jan 1970 00:00:00 +0000`.
+///
+/// The day of the week is optional and ignored. Returns `None` if the date is
+/// malformed.
+pub(crate) fn parse_date(value: &str) -> Option<git2::Time> {
+    let value = value.split_once(',').map_or(value, |(_, date)| date);
+    let mut parts = value.split_whitespace();
+
+    let day: i64 = parts.next()?.parse().ok()?;
+    let month = parts.next()?;
+    let month = MONTHS.iter().position(|m| m.eq_ignore_ascii_case(month))? as i64 + 1;
+    let year: i64 = parts.next()?.parse().ok()?;
+
+    let mut time = parts.next()?.split(':');
+    let hour: i64 = time.next()?.parse().ok()?;
+    let minute: i64 = time.next()?.parse().ok()?;
+    let second: i64 = time.next().map_or(Some(0), |s| s.parse().ok())?;
+
+    let offset = match parts.next() {
+        Some(zone) if zone.starts_with(['+', '-']) && zone.len() == 5 => {
+            let value: i32 = zone[1..].parse().ok()?;
+            let minutes = value / 100 * 60 + value % 100;
+            if zone.starts_with('-') {
+                -minutes
+            } else {
+                minutes
+            }
+        }
+        // Obsolete zone names, all of them but UTC are rarely used.
+        _ => 0,
+    };
+
+    let local = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+    Some(git2::Time::new(local - i64::from(offset) * 60, offset))
+}
+
+/// Convert days since the UNIX epoch to a (year, month, day) date.
+///
+/// Source: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Convert a (year, month, day) date to days since the UNIX epoch.
+///
+/// Source: http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mailboxes() {
+        assert_eq!(
+            mailbox("Jane Doe", "jane@example.com"),
+            "Jane Doe <jane@example.com>"
+        );
+        assert_eq!(mailbox("", "jane@example.com"), "jane@example.com");
+        assert_eq!(
+            mailbox("Doe, \"J.\"", "jane@example.com"),
+            "\"Doe, \\\"J.\\\"\" <jane@example.com>"
+        );
+        assert_eq!(
+            mailbox("Jäne", "jane@example.com"),
+            "=?UTF-8?q?J=C3=A4ne?= <jane@example.com>"
+        );
+
+        let parsed = |name: &str, email: &str| (name.to_string(), email.to_string());
+        for name in ["Jane Doe", "Doe, \"J.\"", "Jäne", "back\\slash"] {
+            let value = mailbox(name, "jane@example.com");
+            assert_eq!(parse_mailbox(&value), parsed(name, "jane@example.com"));
+        }
+        assert_eq!(
+            parse_mailbox("jane@example.com (Jane Doe)"),
+            parsed("Jane Doe", "jane@example.com")
+        );
+        assert_eq!(
+            parse_mailbox(" jane@example.com "),
+            parsed("", "jane@example.com")
+        );
+    }
+
+    #[test]
+    fn encoded_headers() {
+        assert_eq!(encode_header("Fix the thing"), "Fix the thing");
+        assert_eq!(encode_header("Fix ß=1?"), "=?UTF-8?q?Fix_=C3=9F=3D1=3F?=");
+        assert_eq!(decode_header("=?UTF-8?q?Fix_=C3=9F=3D1=3F?="), "Fix ß=1?");
+        assert_eq!(decode_header("=?utf-8?B?w6TDtg==?= x"), "äö x");
+        // Whitespace between encoded-words is dropped, but not around text.
+        assert_eq!(decode_header("=?UTF-8?q?a?= =?UTF-8?q?b?= c"), "ab c");
+        assert_eq!(decode_header("a =?UTF-8?q?b?="), "a b");
+        // Malformed words are kept as they are.
+        assert_eq!(
+            decode_header("=?UTF-8?x?a?= =?oops"),
+            "=?UTF-8?x?a?= =?oops"
+        );
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"f"), "Zg==");
+        assert_eq!(encode_base64(b"fo"), "Zm8=");
+        assert_eq!(encode_base64(b"foo"), "Zm9v");
+        assert_eq!(encode_base64(b"\0user\0pass"), "AHVzZXIAcGFzcw==");
+        for data in [&b"f"[..], b"fo", b"foo", b"\xff\x00\x80"] {
+            assert_eq!(decode_base64(&encode_base64(data)).unwrap(), data);
+        }
+        assert_eq!(decode_base64("not base64!"), None);
+    }
+
+    #[test]
+    fn dates() {
+        let time = git2::Time::new(1_614_589_200, 60);
+        assert_eq!(format_date(time), "Mon, 1 Mar 2021 10:00:00 +0100");
+        assert_eq!(
+            format_date(git2::Time::new(0, -330)),
+            "Wed, 31 Dec 1969 18:30:00 -0530"
+        );
+        assert_eq!(
+            format_date(git2::Time::new(951_782_400, 0)),
+            "Tue, 29 Feb 2000 00:00:00 +0000"
+        );
+
+        for time in [
+            time,
+            git2::Time::new(0, -330),
+            git2::Time::new(951_782_400, 0),
+        ] {
+            let parsed = parse_date(&format_date(time)).unwrap();
+            assert_eq!(parsed.seconds(), time.seconds());
+            assert_eq!(parsed.offset_minutes(), time.offset_minutes());
+        }
+
+        let parsed = parse_date("1 mar 2021 09:00 GMT").unwrap();
+        assert_eq!(
+            (parsed.seconds(), parsed.offset_minutes()),
+            (1_614_589_200, 0)
+        );
+        assert!(parse_date("Mon, 1 Foo 2021 10:00:00 +0100").is_none());
+        assert!(parse_date("Mon, 1 Mar").is_none());
+    }
+}
@@ -0,0 +1,451 @@
+//! # Patch Specs
+//!
+//! The syntax used by all commands to refer to the patches of a queue:
+//!
+//!   * `<name>`: the patch with the given name.
+//!   * `top` and `bottom`: the top and bottom applied patches.
+//!   * `<n>`: the n-th patch of the series, starting from 1.
+//!   * `I<sha1>`: the patch with the given Change-Id trailer, which may be
+//!     abbreviated to 7 or more digits, see [`crate::trailers`].
+//!   * `<patch>~<n>`: the patch n positions below the given one, e.g. `top~2`.
+//!   * `<from>..<to>`: the patches from `from` to `to`, inclusive. Either side may
+//!     be omitted to mean the first or last patch of the same group, applied or
+//!     unapplied, of the other side. `..` alone means all applied patches.
+//!   * `<queue>:<spec>`: the patches of the given queue instead of the current
+//!     one.
+//!
+//! The series is ordered with the applied patches first, from the bottom to the
+//! top, followed by the unapplied patches in the order they would be pushed.
+//!
+//! Patch names take precedence over `top`, `bottom`, indices and Change-Ids, so
+//! every patch can be referred by its name. Names can't have `~`, `:` or `..`,
+//! as they're not allowed in reference names.
+
+use git2::{Oid, Repository};
+
+use crate::{queue::log::QueueState, trailers};
+
+/// Errors when parsing or resolving a patch spec.
+#[derive(Debug)]
+pub enum SpecError {
+    /// The spec is malformed.
+    Invalid(String),
+    /// There is no patch with the given name.
+    UnknownPatch(String),
+    /// `top` or `bottom` were used, but no patch is applied.
+    NoneApplied,
+    /// The position is outside of the series.
+    OutOfRange(String),
+    /// More than one patch has the given Change-Id.
+    AmbiguousChangeId(String),
+    /// The range has applied and unapplied patches.
+    MixedRange(String),
+    /// The start of the range is after its end.
+    Reversed(String),
+}
+
+impl std::fmt::Display for SpecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(s) => write!(f, "invalid patch spec `{}`", s),
+            Self::UnknownPatch(p) => write!(f, "patch `{}` does not exist", p),
+            Self::NoneApplied => f.write_str("there are no applied patches"),
+            Self::OutOfRange(s) => write!(f, "`{}` is outside of the series", s),
+            Self::AmbiguousChangeId(c) => write!(f, "more than one patch has Change-Id `{}`", c),
+            Self::MixedRange(s) => {
+                write!(f, "range `{}` has both applied and unapplied patches", s)
+            }
+            Self::Reversed(s) => write!(f, "range `{}` starts after its end", s),
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+/// A parsed patch spec.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchSpec<'s> {
+    spec: &'s str,
+    queue: Option<&'s str>,
+    from: Option<Endpoint<'s>>,
+    to: Option<Endpoint<'s>>,
+    range: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Endpoint<'s> {
+    base: &'s str,
+    offset: usize,
+}
+
+/// The patches selected by a spec.
+pub struct Selection<'q> {
+    patches: Vec<(&'q str, Oid)>,
+    applied: bool,
+}
+
+impl<'q> Selection<'q> {
+    /// The selected patches and their commits, in the series order.
+    pub fn patches(&self) -> &[(&'q str, Oid)] {
+        &self.patches
+    }
+
+    /// The selected patches and their commits, in the series order.
+    pub fn into_patches(self) -> Vec<(&'q str, Oid)> {
+        self.patches
+    }
+
+    /// Are the selected patches applied?
+    pub fn is_applied(&self) -> bool {
+        self.applied
+    }
+}
+
+impl std::fmt::Display for PatchSpec<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.spec)
+    }
+}
+
+impl<'s> PatchSpec<'s> {
+    /// Parse a patch spec.
+    pub fn parse(spec: &'s str) -> Result<Self, SpecError> {
+        let invalid = || SpecError::Invalid(spec.to_string());
+
+        let (queue, patches) = match spec.split_once(':') {
+            Some(("", _)) => return Err(invalid()),
+            Some((queue, patches)) => (Some(queue), patches),
+            None => (None, spec),
+        };
+
+        let (from, to, range) = match patches.split_once("..") {
+            Some((from, to)) => (from, to, true),
+            None if patches.is_empty() => return Err(invalid()),
+            None => (patches, patches, false),
+        };
+        let endpoint = |s: &'s str| -> Result<Option<Endpoint<'s>>, SpecError> {
+            if s.is_empty() {
+                return Ok(None);
+            }
+
+            let mut parts = s.split('~');
+            let base = parts.next().filter(|b| !b.is_empty()).ok_or_else(invalid)?;
+            let mut offset = 0;
+            for part in parts {
+                // `~` alone means `~1`, like in Git.
+                offset += if part.is_empty() {
+                    1
+                } else {
+                    part.parse::<usize>().map_err(|_| invalid())?
+                };
+            }
+            Ok(Some(Endpoint { base, offset }))
+        };
+
+        Ok(Self {
+            spec,
+            queue,
+            from: endpoint(from)?,
+            to: endpoint(to)?,
+            range,
+        })
+    }
+
+    /// The queue given in the spec, if any.
+    pub fn queue(&self) -> Option<&'s str> {
+        self.queue
+    }
+
+    /// Does the spec select a range of patches?
+    pub fn is_range(&self) -> bool {
+        self.range
+    }
+
+    /// Resolve the spec against the given queue state.
+    ///
+    /// The repository is used to read the Change-Id of patches.
+    pub fn resolve<'q>(
+        &self,
+        repo: &Repository,
+        state: &'q QueueState,
+    ) -> Result<Selection<'q>, SpecError> {
+        let series = series(state);
+        let num_applied = state.applied().count();
+        let position = |endpoint: &Endpoint<'_>| self.position(repo, state, &series, endpoint);
+
+        let (from, to) = match (&self.from, &self.to) {
+            (Some(from), Some(to)) => (position(from)?, position(to)?),
+            (Some(from), None) => {
+                let from = position(from)?;
+                let end = if from < num_applied {
+                    num_applied
+                } else {
+                    series.len()
+                };
+                (from, end - 1)
+            }
+            (None, Some(to)) => {
+                let to = position(to)?;
+                (if to < num_applied { 0 } else { num_applied }, to)
+            }
+            (None, None) => {
+                return Ok(Selection {
+                    patches: series[..num_applied].to_vec(),
+                    applied: true,
+                })
+            }
+        };
+
+        if from > to {
+            return Err(SpecError::Reversed(self.spec.to_string()));
+        }
+        if from < num_applied && to >= num_applied {
+            return Err(SpecError::MixedRange(self.spec.to_string()));
+        }
+
+        Ok(Selection {
+            patches: series[from..=to].to_vec(),
+            applied: to < num_applied,
+        })
+    }
+
+    /// Resolve a spec that must select a single patch.
+    pub fn resolve_one<'q>(
+        &self,
+        repo: &Repository,
+        state: &'q QueueState,
+    ) -> Result<(&'q str, Oid), SpecError> {
+        if self.range {
+            return Err(SpecError::Invalid(self.spec.to_string()));
+        }
+
+        let selection = self.resolve(repo, state)?;
+        Ok(selection.patches[0])
+    }
+
+    fn position(
+        &self,
+        repo: &Repository,
+        state: &QueueState,
+        series: &[(&str, Oid)],
+        endpoint: &Endpoint<'_>,
+    ) -> Result<usize, SpecError> {
+        let num_applied = state.applied().count();
+
+        let base = if let Some(idx) = series.iter().position(|(n, _)| *n == endpoint.base) {
+            idx
+        } else if endpoint.base == "top" {
+            num_applied.checked_sub(1).ok_or(SpecError::NoneApplied)?
+        } else if endpoint.base == "bottom" {
+            if num_applied == 0 {
+                return Err(SpecError::NoneApplied);
+            }
+            0
+        } else if let Ok(index) = endpoint.base.parse::<usize>() {
+            if index == 0 || index > series.len() {
+                return Err(SpecError::OutOfRange(endpoint.base.to_string()));
+            }
+            index - 1
+        } else if trailers::is_change_id(endpoint.base) {
+            find_change_id(repo, series, endpoint.base)?
+        } else {
+            return Err(SpecError::UnknownPatch(endpoint.base.to_string()));
+        };
+
+        base.checked_sub(endpoint.offset)
+            .ok_or_else(|| SpecError::OutOfRange(self.spec.to_string()))
+    }
+}
+
+/// Find the position of the patch with a Change-Id, or a prefix of it.
+fn find_change_id(
+    repo: &Repository,
+    series: &[(&str, Oid)],
+    change_id: &str,
+) -> Result<usize, SpecError> {
+    let mut found = None;
+    for (idx, &(_, oid)) in series.iter().enumerate() {
+        let matches = repo
+            .find_commit(oid)
+            .ok()
+            .and_then(|commit| {
+                let message = commit.message()?;
+                trailers::change_id(message).map(|id| id.starts_with(change_id))
+            })
+            .unwrap_or(false);
+        if matches {
+            if found.is_some() {
+                return Err(SpecError::AmbiguousChangeId(change_id.to_string()));
+            }
+            found = Some(idx);
+        }
+    }
+
+    found.ok_or_else(|| SpecError::UnknownPatch(change_id.to_string()))
+}
+
+/// The patches of a state, in the series order.
+pub(crate) fn series(state: &QueueState) -> Vec<(&str, Oid)> {
+    let mut patches: Vec<_> = state.applied().collect();
+    // Unapplied patches are kept as a stack, the next one to be pushed is the last.
+    patches.extend(state.unapplied().collect::<Vec<_>>().into_iter().rev());
+    patches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ctx::Ctx,
+        queue::Queue,
+        testing::{add_patch, add_patch_with_message, base_branch, clone_of, setup},
+    };
+
+    const CHANGE_ID_A: &str = "I1234567aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const CHANGE_ID_B: &str = "I1234567bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    /// A queue with the series `a b | c d`, where `a` and `b` are applied.
+    fn queue(ctx: &Ctx) -> Queue<'_> {
+        let mut queue = Queue::initialize(ctx, "q", base_branch(ctx))
+            .unwrap()
+            .unwrap();
+        add_patch_with_message(&mut queue, "a", &format!("a\n\nChange-Id: {}", CHANGE_ID_A));
+        add_patch_with_message(&mut queue, "b", &format!("b\n\nChange-Id: {}", CHANGE_ID_B));
+        add_patch(&mut queue, "c");
+        add_patch(&mut queue, "d");
+
+        let state = queue.state();
+        let patch = |name: &str| (name.to_string(), state.patch(name).unwrap());
+        let applied = vec![patch("a"), patch("b")];
+        let unapplied = vec![patch("c"), patch("d")];
+        let base = state.base();
+        queue
+            .replace_stack("pop".to_string(), base, applied, unapplied, |_| {})
+            .unwrap();
+        queue
+    }
+
+    fn resolve(queue: &Queue<'_>, spec: &str) -> Result<(Vec<String>, bool), SpecError> {
+        let selection = PatchSpec::parse(spec)?.resolve(queue.ctx().repo(), queue.state())?;
+        let names = selection
+            .patches()
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        Ok((names, selection.is_applied()))
+    }
+
+    fn names(spec: &str, queue: &Queue<'_>) -> Vec<String> {
+        resolve(queue, spec).unwrap().0
+    }
+
+    #[test]
+    fn parse_specs() {
+        let spec = PatchSpec::parse("top~2").unwrap();
+        assert_eq!(spec.queue(), None);
+        assert!(!spec.is_range());
+
+        let spec = PatchSpec::parse("other:a..b").unwrap();
+        assert_eq!(spec.queue(), Some("other"));
+        assert!(spec.is_range());
+        assert_eq!(spec.to_string(), "other:a..b");
+
+        assert!(PatchSpec::parse("other:..").unwrap().is_range());
+        for invalid in &["", ":a", "q:", "~1", "a~x", "a..b~-1", "a~1x"] {
+            match PatchSpec::parse(invalid) {
+                Err(SpecError::Invalid(spec)) => assert_eq!(spec, *invalid),
+                other => panic!("`{}` parsed as {:?}", invalid, other),
+            }
+        }
+    }
+
+    #[test]
+    fn resolve_single_patches() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let queue = queue(&ctx);
+
+        for (spec, expected) in &[
+            ("a", "a"),
+            ("c", "c"),
+            ("top", "b"),
+            ("bottom", "a"),
+            ("1", "a"),
+            ("3", "c"),
+            ("4", "d"),
+            ("top~", "a"),
+            ("top~1", "a"),
+            ("d~~", "b"),
+            ("d~1~2", "a"),
+            ("q:top", "b"),
+            ("I1234567a", "a"),
+            (CHANGE_ID_B, "b"),
+        ] {
+            assert_eq!(names(spec, &queue), [*expected], "spec `{}`", spec);
+            let spec = PatchSpec::parse(spec).unwrap();
+            let (name, _) = spec.resolve_one(ctx.repo(), queue.state()).unwrap();
+            assert_eq!(name, *expected);
+        }
+
+        assert!(matches!(
+            PatchSpec::parse("a..b")
+                .unwrap()
+                .resolve_one(ctx.repo(), queue.state()),
+            Err(SpecError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn resolve_ranges() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let queue = queue(&ctx);
+
+        let applied = (vec!["a".to_string(), "b".to_string()], true);
+        let unapplied = (vec!["c".to_string(), "d".to_string()], false);
+        for spec in &["..", "a..b", "..b", "a..", "bottom..top", "1..2", "q:.."] {
+            assert_eq!(resolve(&queue, spec).unwrap(), applied, "spec `{}`", spec);
+        }
+        for spec in &["c..d", "..d", "c..", "3..4", "d~..d"] {
+            assert_eq!(resolve(&queue, spec).unwrap(), unapplied, "spec `{}`", spec);
+        }
+        assert_eq!(names("b..b", &queue), ["b"]);
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let mut queue = queue(&ctx);
+
+        let error = |queue: &Queue<'_>, spec| resolve(queue, spec).unwrap_err();
+        assert!(matches!(error(&queue, "nope"), SpecError::UnknownPatch(p) if p == "nope"));
+        assert!(
+            matches!(error(&queue, "Iffffffff"), SpecError::UnknownPatch(p) if p == "Iffffffff")
+        );
+        assert!(
+            matches!(error(&queue, "I1234567"), SpecError::AmbiguousChangeId(c) if c == "I1234567")
+        );
+        assert!(matches!(error(&queue, "0"), SpecError::OutOfRange(s) if s == "0"));
+        assert!(matches!(error(&queue, "5"), SpecError::OutOfRange(s) if s == "5"));
+        assert!(matches!(error(&queue, "a~"), SpecError::OutOfRange(s) if s == "a~"));
+        assert!(matches!(error(&queue, "top~2"), SpecError::OutOfRange(_)));
+        assert!(matches!(error(&queue, "b..a"), SpecError::Reversed(s) if s == "b..a"));
+        assert!(matches!(error(&queue, "b..c"), SpecError::MixedRange(s) if s == "b..c"));
+        assert!(matches!(error(&queue, "a..d"), SpecError::MixedRange(_)));
+
+        // With no patch applied.
+        let state = queue.state();
+        let patch = |name: &str| (name.to_string(), state.patch(name).unwrap());
+        let unapplied = vec![patch("a"), patch("b"), patch("c"), patch("d")];
+        let base = state.base();
+        queue
+            .replace_stack("pop all".to_string(), base, vec![], unapplied, |_| {})
+            .unwrap();
+        assert!(matches!(error(&queue, "top"), SpecError::NoneApplied));
+        assert!(matches!(error(&queue, "bottom"), SpecError::NoneApplied));
+        assert!(matches!(error(&queue, "..top"), SpecError::NoneApplied));
+        assert_eq!(resolve(&queue, "..").unwrap(), (vec![], true));
+        assert_eq!(names("a..", &queue), ["a", "b", "c", "d"]);
+    }
+}
@@ -0,0 +1,183 @@
+//! # Status
+//!
+//! The state of the current queue together with the working tree: how far its
+//! base is behind the base branch, the Git operation in progress, if any, and the
+//! changed and conflicted files. Untracked files aren't reported.
+
+use git2::{RepositoryState, Status};
+
+use crate::{ctx::Ctx, error::Error, queue::Queue};
+
+/// A Git operation that stopped in the middle, e.g. because of conflicts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Merge,
+    Revert,
+    CherryPick,
+    Bisect,
+    Rebase,
+    ApplyMailbox,
+}
+
+impl Operation {
+    /// The operation in progress in the repository, if any.
+    pub fn in_progress(ctx: &Ctx) -> Option<Self> {
+        match ctx.repo().state() {
+            RepositoryState::Clean => None,
+            RepositoryState::Merge => Some(Self::Merge),
+            RepositoryState::Revert | RepositoryState::RevertSequence => Some(Self::Revert),
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+                Some(Self::CherryPick)
+            }
+            RepositoryState::Bisect => Some(Self::Bisect),
+            RepositoryState::Rebase
+            | RepositoryState::RebaseInteractive
+            | RepositoryState::RebaseMerge => Some(Self::Rebase),
+            RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => {
+                Some(Self::ApplyMailbox)
+            }
+        }
+    }
+
+    /// Name of the operation, as the Git command running it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Merge => "merge",
+            Self::Revert => "revert",
+            Self::CherryPick => "cherry-pick",
+            Self::Bisect => "bisect",
+            Self::Rebase => "rebase",
+            Self::ApplyMailbox => "am",
+        }
+    }
+}
+
+/// A file changed in the index or in the working tree.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FileStatus {
+    /// Path of the file, relative to the root of the working tree.
+    pub path: String,
+    /// The file has merge conflicts.
+    pub conflicted: bool,
+    /// The file has changes in the index.
+    pub staged: bool,
+    /// The file has changes in the working tree not in the index.
+    pub modified: bool,
+}
+
+/// The changed files of the working tree, sorted by path.
+pub fn changed_files(ctx: &Ctx) -> Result<Vec<FileStatus>, Error> {
+    let index = Status::INDEX_NEW
+        | Status::INDEX_MODIFIED
+        | Status::INDEX_DELETED
+        | Status::INDEX_RENAMED
+        | Status::INDEX_TYPECHANGE;
+    let workdir =
+        Status::WT_MODIFIED | Status::WT_DELETED | Status::WT_RENAMED | Status::WT_TYPECHANGE;
+
+    let statuses = ctx.workdir_status()?;
+    let mut files: Vec<_> = statuses
+        .iter()
+        .filter_map(|entry| {
+            let status = entry.status();
+            let file = FileStatus {
+                path: String::from_utf8_lossy(entry.path_bytes()).into_owned(),
+                conflicted: status.is_conflicted(),
+                staged: status.intersects(index),
+                modified: status.intersects(workdir),
+            };
+            (file.conflicted || file.staged || file.modified).then_some(file)
+        })
+        .collect();
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(files)
+}
+
+/// How many commits the base branch of a queue has that its base hasn't, `None`
+/// if the base branch doesn't exist anymore.
+pub fn behind_base(queue: &Queue<'_>) -> Result<Option<usize>, Error> {
+    let ctx = queue.ctx();
+    let branch = match ctx.find_branch(queue.base_name())? {
+        Some(branch) => branch,
+        None => return Ok(None),
+    };
+    let tip = branch.get().peel_to_commit()?.id();
+    // The queue is behind by as many commits as the base branch is ahead of it.
+    let (branch_ahead, _) = ctx.repo().graph_ahead_behind(tip, queue.state().base())?;
+
+    Ok(Some(branch_ahead))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{add_patch, base_branch, clone_of, setup};
+    use git2::Signature;
+
+    #[test]
+    fn behind_base_branch() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let main = base_branch(&ctx);
+        let mut queue = Queue::initialize(&ctx, "q", main).unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        assert_eq!(behind_base(&queue).unwrap(), Some(0));
+
+        let repo = ctx.repo();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        for message in &["first", "second"] {
+            let parent = repo.find_reference("refs/heads/main").unwrap();
+            let parent = parent.peel_to_commit().unwrap();
+            let tree = parent.tree().unwrap();
+            repo.commit(
+                Some("refs/heads/main"),
+                &author,
+                &author,
+                message,
+                &tree,
+                &[&parent],
+            )
+            .unwrap();
+        }
+        assert_eq!(behind_base(&queue).unwrap(), Some(2));
+
+        ctx.find_branch("main").unwrap().unwrap().delete().unwrap();
+        assert_eq!(behind_base(&queue).unwrap(), None);
+    }
+
+    #[test]
+    fn changed_and_staged_files() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let queue = Queue::initialize(&ctx, "q", base_branch(&ctx))
+            .unwrap()
+            .unwrap();
+        queue.switch_to().unwrap();
+        let mut queue = Queue::for_queue(&ctx, "q").unwrap().unwrap();
+        add_patch(&mut queue, "one");
+        assert!(changed_files(&ctx).unwrap().is_empty());
+        assert_eq!(Operation::in_progress(&ctx), None);
+
+        let workdir = ctx.repo().workdir().unwrap();
+        std::fs::write(workdir.join("one"), "changed").unwrap();
+        std::fs::write(workdir.join("new"), "new").unwrap();
+        std::fs::write(workdir.join("untracked"), "untracked").unwrap();
+        let mut index = ctx.repo().index().unwrap();
+        index.add_path("new".as_ref()).unwrap();
+        index.write().unwrap();
+
+        let files: Vec<_> = changed_files(&ctx)
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.path, f.conflicted, f.staged, f.modified))
+            .collect();
+        assert_eq!(
+            files,
+            [
+                ("new".to_string(), false, true, false),
+                ("one".to_string(), false, false, true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,473 @@
+//! # StGit Stacks
+//!
+//! Read the stacks of [Stacked Git](https://stacked-git.github.io), so they can be
+//! turned into queues without rewriting any commit.
+//!
+//! StGit keeps the metadata of the stack of a branch `<branch>` in the commit
+//! pointed by `refs/stacks/<branch>`, and a reference `refs/patches/<branch>/<patch>`
+//! for each patch. Two metadata formats are supported:
+//!
+//!   * Version 5 (StGit 2.x): a `stack.json` blob with the patch lists and a map of
+//!     each patch name to its commit OID.
+//!   * Version 4 (StGit 1.x): a `meta` blob with `Key: value` lines, where each
+//!     patch list is followed by `  <patch>: <oid>` lines.
+//!
+//! Queues don't have hidden patches, they are imported as the last unapplied ones.
+//!
+//! Importing leaves the StGit stack untouched: the branch, `refs/stacks/<branch>`
+//! and the StGit patch references stay as they are, and the queue gets its own
+//! references to the same commits. Changes to the queue aren't seen by StGit, nor
+//! the other way around.
+
+use std::collections::HashMap;
+
+use git2::{Branch, BranchType, ErrorCode, Oid, Repository};
+
+use crate::{ctx::Ctx, error::Error, naming, queue::Queue};
+
+/// The patches of a StGit stack.
+pub struct StgitStack {
+    branch: String,
+    head: Oid,
+    applied: Vec<(String, Oid)>,
+    unapplied: Vec<(String, Oid)>,
+    hidden: Vec<(String, Oid)>,
+}
+
+impl StgitStack {
+    /// Read the stack of the given branch.
+    ///
+    /// Returns `None` if the branch isn't managed by StGit.
+    pub fn read(repo: &Repository, branch: &str) -> Result<Option<Self>, Error> {
+        naming::validate_queue_name(branch)?;
+        let commit = match repo.find_reference(&format!("refs/stacks/{}", branch)) {
+            Ok(gitref) => gitref.peel_to_commit()?,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let tree = commit.tree()?;
+
+        let read_blob = |name: &str| -> Result<Option<Vec<u8>>, Error> {
+            match tree.get_name(name) {
+                Some(entry) => {
+                    let object = entry.to_object(repo)?;
+                    let blob = object
+                        .as_blob()
+                        .ok_or(Error::Inconsistency("StGit stack"))?;
+                    Ok(Some(blob.content().to_vec()))
+                }
+                None => Ok(None),
+            }
+        };
+
+        let mut stack = if let Some(json) = read_blob("stack.json")? {
+            Self::from_json(&json)?
+        } else if let Some(meta) = read_blob("meta")? {
+            Self::from_meta(&meta)?
+        } else {
+            return Err(Error::Inconsistency("StGit stack"));
+        };
+        stack.branch = branch.to_string();
+
+        Ok(Some(stack))
+    }
+
+    fn from_json(json: &[u8]) -> Result<Self, Error> {
+        #[derive(serde::Deserialize)]
+        struct StackJson {
+            version: u32,
+            head: String,
+            applied: Vec<String>,
+            unapplied: Vec<String>,
+            hidden: Vec<String>,
+            patches: HashMap<String, PatchJson>,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct PatchJson {
+            oid: String,
+        }
+
+        let meta: StackJson =
+            serde_json::from_slice(json).map_err(|_| Error::Inconsistency("StGit stack"))?;
+        if meta.version != 5 {
+            return Err(Error::Inconsistency("StGit stack version"));
+        }
+
+        let patches = meta.patches;
+        let with_oids = |names: Vec<String>| {
+            names
+                .into_iter()
+                .map(|name| {
+                    let oid = patches
+                        .get(&name)
+                        .and_then(|p| parse_oid(&p.oid).ok())
+                        .ok_or(Error::Inconsistency("StGit stack"))?;
+                    Ok((name, oid))
+                })
+                .collect::<Result<Vec<_>, Error>>()
+        };
+
+        Ok(Self {
+            branch: String::new(),
+            head: parse_oid(&meta.head)?,
+            applied: with_oids(meta.applied)?,
+            unapplied: with_oids(meta.unapplied)?,
+            hidden: with_oids(meta.hidden)?,
+        })
+    }
+
+    fn from_meta(meta: &[u8]) -> Result<Self, Error> {
+        let meta = std::str::from_utf8(meta).map_err(|_| Error::NonUtf8)?;
+
+        let mut stack = Self {
+            branch: String::new(),
+            head: Oid::zero(),
+            applied: vec![],
+            unapplied: vec![],
+            hidden: vec![],
+        };
+        let mut list = None;
+        for line in meta.lines() {
+            if let Some(patch) = line.strip_prefix("  ") {
+                let (name, oid) = patch
+                    .split_once(": ")
+                    .ok_or(Error::Inconsistency("StGit stack"))?;
+                let patches = match list {
+                    Some("Applied") => &mut stack.applied,
+                    Some("Unapplied") => &mut stack.unapplied,
+                    Some("Hidden") => &mut stack.hidden,
+                    _ => return Err(Error::Inconsistency("StGit stack")),
+                };
+                patches.push((name.to_string(), parse_oid(oid)?));
+                continue;
+            }
+
+            let (key, value) = line.split_once(':').unwrap_or((line, ""));
+            list = Some(key);
+            match (key, value.trim()) {
+                ("Version", "4") => {}
+                ("Version", _) => return Err(Error::Inconsistency("StGit stack version")),
+                ("Head", head) => stack.head = parse_oid(head)?,
+                _ => {}
+            }
+        }
+
+        if stack.head.is_zero() {
+            return Err(Error::Inconsistency("StGit stack"));
+        }
+
+        Ok(stack)
+    }
+
+    /// Name of the branch of this stack.
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// The applied patches, from the bottom to the top of the stack.
+    pub fn applied(&self) -> &[(String, Oid)] {
+        &self.applied
+    }
+
+    /// The unapplied patches, in the order they would be pushed.
+    pub fn unapplied(&self) -> &[(String, Oid)] {
+        &self.unapplied
+    }
+
+    /// The hidden patches.
+    pub fn hidden(&self) -> &[(String, Oid)] {
+        &self.hidden
+    }
+
+    /// The branch the stack is based on, the upstream of its branch if it is a
+    /// local branch.
+    pub fn upstream<'r>(&self, repo: &'r Repository) -> Result<Option<Branch<'r>>, Error> {
+        let branch = repo.find_branch(&self.branch, BranchType::Local)?;
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(err) if err.code() == ErrorCode::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Some(upstream).filter(|upstream| !upstream.get().is_remote()))
+    }
+}
+
+/// Name of the queue of an imported stack.
+///
+/// It is the name of the stack branch, unless the patch references of the queue
+/// would be the ones of StGit, `refs/patches/<branch>/`, as in the default
+/// layout. Then `-stgit` is added to it.
+pub fn queue_name(ctx: &Ctx, branch: &str) -> String {
+    let stgit = format!("refs/patches/{}/", branch);
+    let queue = ctx.refs().patch_ref(branch, "");
+    if queue.starts_with(&stgit) || stgit.starts_with(&queue) {
+        format!("{}-stgit", branch)
+    } else {
+        branch.to_string()
+    }
+}
+
+/// Create a queue with the patches of the given stack, based on `base`, e.g.
+/// [`StgitStack::upstream`].
+///
+/// The queue is named by [`queue_name`], and its patches point to the same
+/// commits of the StGit ones, so nothing is rewritten. The base of the queue is
+/// the parent of the bottom patch, or the branch head if no patch is applied.
+///
+/// # Errors
+///
+/// Returns [`Error::AlreadyExists`] if there is already a queue with the same
+/// name, and [`Error::Inconsistency`] if the branch head isn't the top of the stack.
+pub fn import_stack<'r>(
+    ctx: &'r Ctx,
+    stack: StgitStack,
+    base_branch: Branch<'r>,
+) -> Result<Queue<'r>, Error> {
+    let repo = ctx.repo();
+    let branch = repo.find_branch(&stack.branch, BranchType::Local)?;
+
+    let head = branch.get().peel_to_commit()?.id();
+    let top = stack.applied.last().map(|&(_, oid)| oid);
+    if head != stack.head || top.is_some_and(|top| top != head) {
+        return Err(Error::Inconsistency("StGit stack head"));
+    }
+    let base = match stack.applied.first() {
+        Some(&(_, bottom)) => repo.find_commit(bottom)?.parent_id(0)?,
+        None => head,
+    };
+
+    let name = queue_name(ctx, &stack.branch);
+    let mut queue =
+        Queue::initialize(ctx, &name, base_branch)?.ok_or(Error::AlreadyExists("queue"))?;
+
+    let mut unapplied = stack.unapplied;
+    unapplied.extend(stack.hidden);
+    let message = format!("import stgit stack {}", stack.branch);
+    if let Err(err) = queue.adopt_patches(message, base, stack.applied, unapplied) {
+        // Don't leave an empty queue behind.
+        let name = queue.name().to_string();
+        if let Err(close_err) = queue.close() {
+            tracing::warn!("failed to close queue `{}`: {}", name, close_err);
+        }
+        return Err(err);
+    }
+
+    Ok(queue)
+}
+
+fn parse_oid(oid: &str) -> Result<Oid, Error> {
+    // Unlike git2, don't accept abbreviated OIDs padded with zeros.
+    if oid.len() != 40 {
+        return Err(Error::Inconsistency("StGit stack"));
+    }
+    Oid::from_str(oid).map_err(|_| Error::Inconsistency("StGit stack"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{base_branch, clone_of, setup};
+    use git2::Signature;
+
+    /// Commit a file named like the patch on top of `parent`.
+    fn commit(ctx: &Ctx, name: &str, parent: Oid) -> Oid {
+        let repo = ctx.repo();
+        let parent = repo.find_commit(parent).unwrap();
+        let mut builder = repo.treebuilder(Some(&parent.tree().unwrap())).unwrap();
+        let blob = repo.blob(name.as_bytes()).unwrap();
+        builder.insert(name, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        repo.commit(None, &author, &author, name, &tree, &[&parent])
+            .unwrap()
+    }
+
+    /// A StGit 2.x stack on `feature`, based on `main`, with `p1` and `p2`
+    /// applied, `p3` unapplied and `h` hidden.
+    fn stgit_stack(ctx: &Ctx) -> HashMap<&'static str, Oid> {
+        let repo = ctx.repo();
+        let main = base_branch(ctx).get().target().unwrap();
+        let mut patches = HashMap::new();
+        patches.insert("p1", commit(ctx, "p1", main));
+        patches.insert("p2", commit(ctx, "p2", patches["p1"]));
+        patches.insert("p3", commit(ctx, "p3", patches["p2"]));
+        patches.insert("h", commit(ctx, "h", patches["p2"]));
+
+        let head = repo.find_commit(patches["p2"]).unwrap();
+        let mut feature = repo.branch("feature", &head, false).unwrap();
+        feature.set_upstream(Some("main")).unwrap();
+        for (name, oid) in &patches {
+            let refname = format!("refs/patches/feature/{}", name);
+            repo.reference(&refname, *oid, false, "stgit").unwrap();
+        }
+
+        let json = serde_json::json!({
+            "version": 5,
+            "prev": null,
+            "head": patches["p2"].to_string(),
+            "applied": ["p1", "p2"],
+            "unapplied": ["p3"],
+            "hidden": ["h"],
+            "patches": patches
+                .iter()
+                .map(|(name, oid)| (name.to_string(), serde_json::json!({ "oid": oid.to_string() })))
+                .collect::<serde_json::Map<_, _>>(),
+        });
+        let blob = repo.blob(json.to_string().as_bytes()).unwrap();
+        let mut builder = repo.treebuilder(None).unwrap();
+        builder.insert("stack.json", blob, 0o100644).unwrap();
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        repo.commit(
+            Some("refs/stacks/feature"),
+            &author,
+            &author,
+            "stack",
+            &tree,
+            &[],
+        )
+        .unwrap();
+
+        patches
+    }
+
+    fn refs_under(repo: &Repository, prefix: &str) -> Vec<(String, Oid)> {
+        let mut refs: Vec<_> = repo
+            .references_glob(&format!("{}*", prefix))
+            .unwrap()
+            .map(|r| {
+                let r = r.unwrap();
+                (r.name().unwrap().to_string(), r.target().unwrap())
+            })
+            .collect();
+        refs.sort();
+        refs
+    }
+
+    #[test]
+    fn import_leaves_stack_untouched() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let patches = stgit_stack(&ctx);
+        let repo = ctx.repo();
+        let stgit_refs = refs_under(repo, "refs/patches/feature/");
+        let stack_meta = repo.refname_to_id("refs/stacks/feature").unwrap();
+
+        let stack = StgitStack::read(repo, "feature").unwrap().unwrap();
+        assert_eq!(stack.branch(), "feature");
+        assert_eq!(stack.unapplied(), [("p3".to_string(), patches["p3"])]);
+        assert_eq!(stack.hidden(), [("h".to_string(), patches["h"])]);
+        let upstream = stack.upstream(repo).unwrap().unwrap();
+        assert_eq!(upstream.name().unwrap(), Some("main"));
+
+        let queue = import_stack(&ctx, stack, upstream).unwrap();
+        assert_eq!(queue.name(), "feature-stgit");
+        assert_eq!(queue.base_name(), "main");
+        let state = queue.state();
+        assert_eq!(state.base(), repo.refname_to_id("refs/heads/main").unwrap());
+        assert_eq!(state.head(), patches["p2"]);
+        let names = |patches: Vec<(&str, Oid)>| -> Vec<String> {
+            patches.into_iter().map(|(n, _)| n.to_string()).collect()
+        };
+        assert_eq!(names(state.applied().collect()), ["p1", "p2"]);
+        assert_eq!(names(crate::spec::series(state)), ["p1", "p2", "p3", "h"]);
+
+        // The queue has its own references, to the same commits.
+        let queue_refs = refs_under(repo, "refs/patches/feature-stgit/");
+        let expected: Vec<_> = stgit_refs
+            .iter()
+            .map(|(name, oid)| (name.replace("/feature/", "/feature-stgit/"), *oid))
+            .collect();
+        assert_eq!(queue_refs, expected);
+        assert_eq!(refs_under(repo, "refs/patches/feature/"), stgit_refs);
+        assert_eq!(
+            repo.refname_to_id("refs/stacks/feature").unwrap(),
+            stack_meta
+        );
+        assert_eq!(
+            repo.refname_to_id("refs/heads/feature").unwrap(),
+            patches["p2"]
+        );
+
+        let stack = StgitStack::read(repo, "feature").unwrap().unwrap();
+        let main = repo.find_branch("main", BranchType::Local).unwrap();
+        assert!(matches!(
+            import_stack(&ctx, stack, main),
+            Err(Error::AlreadyExists("queue"))
+        ));
+    }
+
+    #[test]
+    fn queue_names() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        assert_eq!(queue_name(&ctx, "feature"), "feature-stgit");
+        assert_eq!(queue_name(&ctx, "team/feature"), "team/feature-stgit");
+
+        let mut config = ctx.repo().config().unwrap();
+        config.set_str("qg.patchRef", "refs/qg-patches").unwrap();
+        let workdir = ctx.repo().workdir().unwrap();
+        let ctx = Ctx::from_repo(Repository::open(workdir).unwrap()).unwrap();
+        assert_eq!(queue_name(&ctx, "feature"), "feature");
+
+        config
+            .set_str("qg.patchRef", "refs/patches/feature")
+            .unwrap();
+        let ctx = Ctx::from_repo(Repository::open(workdir).unwrap()).unwrap();
+        assert_eq!(queue_name(&ctx, "feature"), "feature-stgit");
+        assert_eq!(queue_name(&ctx, "other"), "other");
+    }
+
+    #[test]
+    fn moved_branch_is_not_imported() {
+        let (dir, remote) = setup();
+        let ctx = clone_of(&dir, "repo", &remote);
+        let patches = stgit_stack(&ctx);
+        let repo = ctx.repo();
+        let moved = repo.find_commit(patches["p3"]).unwrap();
+        repo.branch("feature", &moved, true).unwrap();
+
+        let stack = StgitStack::read(repo, "feature").unwrap().unwrap();
+        let main = repo.find_branch("main", BranchType::Local).unwrap();
+        assert!(matches!(
+            import_stack(&ctx, stack, main),
+            Err(Error::Inconsistency("StGit stack head"))
+        ));
+        assert!(Queue::for_queue(&ctx, "feature-stgit").unwrap().is_none());
+        assert!(StgitStack::read(repo, "main").unwrap().is_none());
+    }
+
+    #[test]
+    fn read_stgit_1_meta() {
+        let (p1, p2, p3) = ("1".repeat(40), "2".repeat(40), "3".repeat(40));
+        let meta = format!(
+            "Version: 4\nPrevious: None\nHead: {p2}\nApplied:\n  p1: {p1}\n  p2: {p2}\n\
+             Unapplied:\n  p3: {p3}\nHidden:\n",
+            p1 = p1,
+            p2 = p2,
+            p3 = p3
+        );
+        let stack = StgitStack::from_meta(meta.as_bytes()).unwrap();
+        let oid = |hex: &str| Oid::from_str(hex).unwrap();
+        assert_eq!(stack.head, oid(&p2));
+        assert_eq!(
+            stack.applied(),
+            [("p1".to_string(), oid(&p1)), ("p2".to_string(), oid(&p2))]
+        );
+        assert_eq!(stack.unapplied(), [("p3".to_string(), oid(&p3))]);
+        assert!(stack.hidden().is_empty());
+
+        for invalid in &["Version: 3\n", "Applied:\n", "Head: 1234\n", "  p1: x\n"] {
+            assert!(
+                matches!(
+                    StgitStack::from_meta(invalid.as_bytes()),
+                    Err(Error::Inconsistency(_))
+                ),
+                "{:?}",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,462 @@
+//! # Log Synchronization
+//!
+//! Reconcile two diverged logs of a queue, e.g. after editing it in two
+//! repositories.
+//!
+//! The logs are merged from their common ancestor entry, found by following the
+//! `previous` entries of each one. Each patch is compared in the ancestor and in
+//! both logs: a patch changed, created or deleted in only one of them keeps that
+//! change, and a patch changed differently in both is a conflict that must be
+//! resolved by choosing one side. Patches are compared by their text, their
+//! message and diff, so a patch that was only rebased didn't change.
+//!
+//! The order of the stack is taken from the side that changed it, or from ours if
+//! both did, with the patches only in the other side inserted after their
+//! predecessors. The applied patches are then rebased on each other as needed,
+//! and the result is recorded in a merge entry, whose predecessors are the heads
+//! of both logs. The base and the description follow the same rules as patches,
+//! except that ours are kept when both changed.
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use git2::{Oid, Repository};
+
+use crate::{ctx::Ctx, error::Error, queue::log::QueueState, queue::Queue, range_diff};
+
+/// Which side of a merge to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The local log.
+    Ours,
+    /// The other log, e.g. the remote one.
+    Theirs,
+}
+
+/// A patch changed differently in both logs.
+pub struct PatchConflict {
+    name: String,
+    ours: Option<Oid>,
+    theirs: Option<Oid>,
+}
+
+impl PatchConflict {
+    /// Name of the patch.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Commit of the patch in our log, `None` if it was deleted.
+    pub fn ours(&self) -> Option<Oid> {
+        self.ours
+    }
+
+    /// Commit of the patch in their log, `None` if it was deleted.
+    pub fn theirs(&self) -> Option<Oid> {
+        self.theirs
+    }
+}
+
+/// The result of synchronizing a queue with another log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncOutcome {
+    /// Both logs are the same.
+    UpToDate,
+    /// The other log is an ancestor of ours.
+    Ahead,
+    /// Our log is an ancestor of the other one, the queue was moved to it.
+    FastForwarded,
+    /// The logs diverged and were merged.
+    Merged,
+}
+
+/// Find the nearest entry in the logs of both states, following the entries
+/// merged by previous syncs too.
+pub fn merge_base(
+    repo: &Repository,
+    ours: &QueueState,
+    theirs: &QueueState,
+) -> Result<Option<QueueState>, Error> {
+    let mut ancestors = HashSet::new();
+    ours.find_merged(repo, |s| {
+        ancestors.extend(s.oid());
+        false
+    })?;
+
+    theirs.find_merged(repo, |s| {
+        s.oid().is_some_and(|oid| ancestors.contains(&oid))
+    })
+}
+
+/// Synchronize a queue with another log of it, e.g. the one fetched from a remote.
+///
+/// `source` describes where the other log comes from in the log messages, and
+/// `resolve` is called for each patch changed differently in both logs, before
+/// the queue is modified, so an error returned by it aborts the merge.
+pub fn sync(
+    queue: &mut Queue<'_>,
+    theirs: Oid,
+    source: &str,
+    mut resolve: impl FnMut(&PatchConflict) -> Result<Side, Error>,
+) -> Result<SyncOutcome, Error> {
+    let ctx = queue.ctx();
+    let repo = ctx.repo();
+    let ours = queue.state().clone();
+    let ours_oid = ours.oid().expect("queue state is committed");
+
+    if theirs == ours_oid {
+        return Ok(SyncOutcome::UpToDate);
+    }
+    if repo.graph_descendant_of(ours_oid, theirs)? {
+        return Ok(SyncOutcome::Ahead);
+    }
+    if repo.graph_descendant_of(theirs, ours_oid)? {
+        queue.fast_forward(theirs, &format!("sync with {}", source))?;
+        return Ok(SyncOutcome::FastForwarded);
+    }
+
+    let theirs = ours.at_entry(repo, theirs)?;
+    let base = merge_base(repo, &ours, &theirs)?.ok_or(Error::Inconsistency("queuelog"))?;
+
+    let mut names = BTreeSet::new();
+    for state in &[&base, &ours, &theirs] {
+        names.extend(state.applied().chain(state.unapplied()).map(|(n, _)| n));
+    }
+    let mut patches = HashMap::new();
+    for name in names {
+        let (b, o, t) = (base.patch(name), ours.patch(name), theirs.patch(name));
+        let chosen = match three_way_patch(repo, b, o, t)? {
+            Some(chosen) => chosen,
+            None => {
+                let conflict = PatchConflict {
+                    name: name.to_string(),
+                    ours: o,
+                    theirs: t,
+                };
+                match resolve(&conflict)? {
+                    Side::Ours => o,
+                    Side::Theirs => t,
+                }
+            }
+        };
+        if let Some(oid) = chosen {
+            patches.insert(name.to_string(), oid);
+        }
+    }
+
+    let (applied, unapplied) = merge_stacks(&base, &ours, &theirs, &patches);
+    let base_oid = three_way(base.base(), ours.base(), theirs.base()).unwrap_or(ours.base());
+    let description = three_way(base.description(), ours.description(), theirs.description())
+        .unwrap_or(ours.description())
+        .map(str::to_string);
+
+    let mut tip = base_oid;
+    let mut applied_oids = Vec::with_capacity(applied.len());
+    for name in applied {
+        let commit = repo.find_commit(patches[&name])?;
+        tip = if commit.parent_id(0).ok() == Some(tip) {
+            commit.id()
+        } else {
+            rebase(ctx, &name, &commit, tip)?
+        };
+        applied_oids.push((name, tip));
+    }
+    let unapplied_oids = unapplied
+        .into_iter()
+        .map(|name| {
+            let oid = patches[&name];
+            (name, oid)
+        })
+        .collect();
+
+    queue.replace_stack(
+        format!("sync with {}", source),
+        base_oid,
+        applied_oids,
+        unapplied_oids,
+        |state| {
+            state.set_description(description);
+            state.set_merged(&theirs);
+        },
+    )?;
+
+    Ok(SyncOutcome::Merged)
+}
+
+/// Merge a value changed in two sides, `None` if both changed it differently.
+fn three_way<T: PartialEq>(base: T, ours: T, theirs: T) -> Option<T> {
+    if ours == theirs || theirs == base {
+        Some(ours)
+    } else if ours == base {
+        Some(theirs)
+    } else {
+        None
+    }
+}
+
+/// Merge a patch changed in two sides like [`three_way`], comparing the text of
+/// its commits instead of the commits themselves.
+fn three_way_patch(
+    repo: &Repository,
+    base: Option<Oid>,
+    ours: Option<Oid>,
+    theirs: Option<Oid>,
+) -> Result<Option<Option<Oid>>, Error> {
+    let same = |a: Option<Oid>, b: Option<Oid>| match (a, b) {
+        (Some(a), Some(b)) => Ok(range_diff::interdiff(repo, a, b)?.is_empty()),
+        (a, b) => Ok::<_, Error>(a == b),
+    };
+
+    Ok(if same(ours, theirs)? || same(theirs, base)? {
+        Some(ours)
+    } else if same(ours, base)? {
+        Some(theirs)
+    } else {
+        None
+    })
+}
+
+/// The names of the applied and unapplied patches after merging, in push order.
+fn merge_stacks(
+    base: &QueueState,
+    ours: &QueueState,
+    theirs: &QueueState,
+    patches: &HashMap<String, Oid>,
+) -> (Vec<String>, Vec<String>) {
+    let stack = |s: &QueueState| {
+        let applied: Vec<_> = s.applied().map(|(n, _)| n.to_string()).collect();
+        let mut unapplied: Vec<_> = s.unapplied().map(|(n, _)| n.to_string()).collect();
+        // Unapplied patches are kept as a stack, the next one to be pushed is the last.
+        unapplied.reverse();
+        (applied, unapplied)
+    };
+
+    let ours = stack(ours);
+    let theirs = stack(theirs);
+    let (merged, other) = if ours == stack(base) {
+        (theirs, ours)
+    } else {
+        (ours, theirs)
+    };
+    let (mut applied, mut unapplied) = merged;
+    applied.retain(|n| patches.contains_key(n));
+    unapplied.retain(|n| patches.contains_key(n));
+
+    // A patch applied in one side and unapplied in the other keeps the position
+    // it has in the chosen order.
+    insert_missing(&mut applied, &other.0, |n| {
+        patches.contains_key(n) && !unapplied.iter().any(|u| u == n)
+    });
+    insert_missing(&mut unapplied, &other.1, |n| {
+        patches.contains_key(n) && !applied.iter().any(|a| a == n)
+    });
+
+    (applied, unapplied)
+}
+
+/// Insert the patches of `other` missing in `list` after their nearest
+/// predecessor in `other`, or at the start if there is none.
+fn insert_missing(list: &mut Vec<String>, other: &[String], include: impl Fn(&str) -> bool) {
+    for (idx, name) in other.iter().enumerate() {
+        if list.contains(name) || !include(name) {
+            continue;
+        }
+        let position = other[..idx]
+            .iter()
+            .rev()
+            .find_map(|prev| list.iter().position(|n| n == prev))
+            .map_or(0, |pos| pos + 1);
+        list.insert(position, name.clone());
+    }
+}
+
+/// Create a copy of a patch commit on top of another commit.
+fn rebase(ctx: &Ctx, name: &str, commit: &git2::Commit<'_>, onto: Oid) -> Result<Oid, Error> {
+    let repo = ctx.repo();
+    let onto = repo.find_commit(onto)?;
+    let mut index = repo.cherrypick_commit(commit, &onto, 0, None)?;
+    if index.has_conflicts() {
+        return Err(Error::ApplyFailed(name.to_string()));
+    }
+    let tree = repo.find_tree(index.write_tree_to(repo)?)?;
+    let message = commit.message().ok_or(Error::NonUtf8)?;
+
+    ctx.commit(None, &commit.author(), message, &tree, &[&onto])
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{Repository, Signature};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::queue::log::PatchReview;
+    use crate::remote::QueueRemote;
+    use crate::testing::{add_patch, base_branch, clone_of, setup};
+
+    /// Commit the given files, on top of `parent` if any.
+    fn commit(
+        repo: &Repository,
+        parent: Option<Oid>,
+        files: &[(&str, &str)],
+        message: &str,
+    ) -> Oid {
+        let parent = parent.map(|oid| repo.find_commit(oid).unwrap());
+        let mut builder = repo
+            .treebuilder(parent.as_ref().map(|p| p.tree().unwrap()).as_ref())
+            .unwrap();
+        for (path, content) in files {
+            let blob = repo.blob(content.as_bytes()).unwrap();
+            builder.insert(path, blob, 0o100644).unwrap();
+        }
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(None, &author, &author, message, &tree, &parents)
+            .unwrap()
+    }
+
+    #[test]
+    fn rebased_patch_is_unchanged() {
+        let dir = TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let root = commit(&repo, None, &[("a", "1\n")], "root");
+        let other = commit(&repo, Some(root), &[("b", "1\n")], "other");
+
+        let base = commit(&repo, Some(root), &[("a", "2\n")], "patch");
+        let rebased = commit(&repo, Some(other), &[("a", "2\n")], "patch");
+        let changed = commit(&repo, Some(root), &[("a", "3\n")], "patch");
+        let reworded = commit(&repo, Some(root), &[("a", "2\n")], "reworded");
+
+        let merge = |o, t| three_way_patch(&repo, Some(base), o, t).unwrap();
+        assert_eq!(merge(Some(rebased), Some(changed)), Some(Some(changed)));
+        assert_eq!(merge(Some(changed), Some(rebased)), Some(Some(changed)));
+        assert_eq!(merge(Some(rebased), Some(base)), Some(Some(rebased)));
+        assert_eq!(merge(Some(rebased), None), Some(None));
+        assert_eq!(merge(Some(reworded), Some(changed)), None);
+        assert_eq!(merge(None, Some(changed)), None);
+    }
+
+    #[test]
+    fn merge_keeps_records_of_both_logs() {
+        let (dir, remote) = setup();
+        let ours = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ours, "q", base_branch(&ours))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "one");
+        let one = queue.state().patch("one").unwrap();
+        QueueRemote::connect(&ours, None)
+            .unwrap()
+            .publish(&queue)
+            .unwrap();
+
+        let theirs = clone_of(&dir, "theirs", &remote);
+        let origin = QueueRemote::connect(&theirs, None).unwrap();
+        origin.fetch("q").unwrap();
+        let mut their_queue = Queue::for_queue(&theirs, "q").unwrap().unwrap();
+        let review = PatchReview::new("7".to_string(), None, None, one);
+        their_queue
+            .record_branches("origin", "review", vec![("one".to_string(), one)])
+            .unwrap();
+        their_queue
+            .record_reviews(
+                "review".to_string(),
+                vec![("one".to_string(), Some(review))],
+            )
+            .unwrap();
+        assert_eq!(their_queue.bump_version().unwrap(), "v1");
+        origin.publish(&their_queue).unwrap();
+
+        add_patch(&mut queue, "two");
+        assert_eq!(queue.bump_version().unwrap(), "v1");
+        let their_log = QueueRemote::connect(&ours, None)
+            .unwrap()
+            .fetch_log("q")
+            .unwrap()
+            .unwrap();
+        let outcome = sync(&mut queue, their_log, "origin", |c| {
+            panic!("unexpected conflict in {}", c.name())
+        })
+        .unwrap();
+        assert_eq!(outcome, SyncOutcome::Merged);
+
+        let state = queue.state();
+        assert!(state.has_patch("one") && state.has_patch("two"));
+        assert_eq!(state.review("one").unwrap().id, "7");
+        assert_eq!(state.pushed_branches("origin", "review"), [("one", one)]);
+        let versions: Vec<_> = state.versions().map(|(name, _)| name).collect();
+        assert_eq!(versions, ["v1", "v2"]);
+    }
+
+    /// Reword a patch, to change it in one log.
+    fn reword(queue: &mut Queue<'_>, patch: &str, message: &str) {
+        queue
+            .reword_patches(format!("reword {}", patch), |name, _| {
+                (name == patch).then(|| message.to_string())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn sync_twice() {
+        let no_conflict = |c: &PatchConflict| -> Result<Side, Error> {
+            panic!("unexpected conflict in {}", c.name())
+        };
+        let (dir, remote) = setup();
+        let ours = clone_of(&dir, "ours", &remote);
+        let mut queue = Queue::initialize(&ours, "q", base_branch(&ours))
+            .unwrap()
+            .unwrap();
+        add_patch(&mut queue, "p");
+        QueueRemote::connect(&ours, None)
+            .unwrap()
+            .publish(&queue)
+            .unwrap();
+
+        let theirs = clone_of(&dir, "theirs", &remote);
+        let origin = QueueRemote::connect(&theirs, None).unwrap();
+        origin.fetch("q").unwrap();
+        let mut their_queue = Queue::for_queue(&theirs, "q").unwrap().unwrap();
+        reword(&mut their_queue, "p", "p theirs");
+        origin.publish(&their_queue).unwrap();
+
+        add_patch(&mut queue, "x");
+        let their_log = QueueRemote::connect(&ours, None)
+            .unwrap()
+            .fetch_log("q")
+            .unwrap()
+            .unwrap();
+        let outcome = sync(&mut queue, their_log, "origin", no_conflict).unwrap();
+        assert_eq!(outcome, SyncOutcome::Merged);
+
+        // Both sides change again, the patch changed by them before is only
+        // changed by us now.
+        add_patch(&mut their_queue, "y");
+        QueueRemote::connect(&theirs, None)
+            .unwrap()
+            .publish(&their_queue)
+            .unwrap();
+        reword(&mut queue, "p", "p ours");
+        let their_log = QueueRemote::connect(&ours, None)
+            .unwrap()
+            .fetch_log("q")
+            .unwrap()
+            .unwrap();
+        let base = merge_base(
+            ours.repo(),
+            queue.state(),
+            &queue.state().at_entry(ours.repo(), their_log).unwrap(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(base.oid(), their_queue.state().previous_oid());
+        let outcome = sync(&mut queue, their_log, "origin", no_conflict).unwrap();
+        assert_eq!(outcome, SyncOutcome::Merged);
+
+        let state = queue.state();
+        let names: Vec<_> = state.applied().map(|(name, _)| name).collect();
+        assert_eq!(names, ["p", "y", "x"]);
+        let p = ours.repo().find_commit(state.patch("p").unwrap()).unwrap();
+        assert_eq!(p.message(), Some("p ours"));
+    }
+}
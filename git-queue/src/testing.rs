@@ -0,0 +1,70 @@
+//! Repositories and queues for tests.
+
+use git2::{Repository, Signature};
+use tempfile::TempDir;
+
+use crate::{ctx::Ctx, queue::Queue};
+
+/// An empty repository in `dir`, with `remote` as `origin`.
+pub(crate) fn clone_of(dir: &TempDir, name: &str, remote: &TempDir) -> Ctx {
+    let repo = Repository::init(dir.path().join(name)).unwrap();
+    {
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "A").unwrap();
+        config.set_str("user.email", "a@example.com").unwrap();
+        repo.remote("origin", remote.path().to_str().unwrap())
+            .unwrap();
+    }
+    Ctx::from_repo(repo).unwrap()
+}
+
+/// Create a `main` branch with an empty commit, to base queues on.
+pub(crate) fn base_branch(ctx: &Ctx) -> git2::Branch<'_> {
+    let repo = ctx.repo();
+    let tree = repo.treebuilder(None).unwrap().write().unwrap();
+    let tree = repo.find_tree(tree).unwrap();
+    let author = Signature::now("A", "a@example.com").unwrap();
+    let oid = repo
+        .commit(None, &author, &author, "init", &tree, &[])
+        .unwrap();
+    let commit = repo.find_commit(oid).unwrap();
+    repo.branch("main", &commit, false).unwrap()
+}
+
+/// Add a patch to a queue, creating a file named like it.
+pub(crate) fn add_patch(queue: &mut Queue<'_>, name: &str) {
+    add_patch_with_message(queue, name, name);
+}
+
+/// Add a patch to a queue like [`add_patch`], with the given message.
+pub(crate) fn add_patch_with_message(queue: &mut Queue<'_>, name: &str, message: &str) {
+    let repo = queue.ctx().repo();
+    let head = repo.find_commit(queue.state().head()).unwrap();
+    let mut builder = repo.treebuilder(Some(&head.tree().unwrap())).unwrap();
+    let blob = repo.blob(name.as_bytes()).unwrap();
+    builder.insert(name, blob, 0o100644).unwrap();
+    let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+    let author = Signature::now("A", "a@example.com").unwrap();
+    queue.new_patch(name, &author, message, &tree).unwrap();
+}
+
+/// A directory for the clones and a bare remote with a `main` branch, as git2
+/// can't list the references of an empty remote.
+pub(crate) fn setup() -> (TempDir, TempDir) {
+    let dir = TempDir::new().unwrap();
+    let remote = TempDir::new().unwrap();
+    let repo = Repository::init_bare(remote.path()).unwrap();
+    let tree = repo.treebuilder(None).unwrap().write().unwrap();
+    let tree = repo.find_tree(tree).unwrap();
+    let author = Signature::now("A", "a@example.com").unwrap();
+    repo.commit(
+        Some("refs/heads/main"),
+        &author,
+        &author,
+        "init",
+        &tree,
+        &[],
+    )
+    .unwrap();
+    (dir, remote)
+}
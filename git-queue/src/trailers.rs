@@ -0,0 +1,367 @@
+//! # Commit Trailers
+//!
+//! Read and edit the trailers of commit messages, the `Key: value` lines in the
+//! last paragraph of the message, like `git interpret-trailers`. The last
+//! paragraph only has trailers if all of its lines are trailers, or continuation
+//! lines starting with whitespace, and it isn't the subject of the message.
+//! Keys are compared ignoring the case.
+//!
+//! When `qg.autoSignoff` is `true`, a `Signed-off-by` trailer with the identity
+//! of the user is added to new patches, and to patches when they are amended.
+//!
+//! ## Change-Id
+//!
+//! Gerrit identifies the reviews of patches with a `Change-Id: I<sha1>` trailer,
+//! which must be kept when a patch is changed. When `qg.changeId` is `true`, a
+//! Change-Id is added to every new patch that doesn't have one, and patches keep
+//! theirs when amended. Patches can be referred by their Change-Id in patch
+//! specs, see [`crate::spec`].
+
+use git2::{ObjectType, Oid, Signature};
+
+use crate::error::Error;
+
+/// Key of the trailer identifying changes in Gerrit.
+pub const CHANGE_ID: &str = "Change-Id";
+
+/// Key of the trailer certifying the origin of a patch.
+pub const SIGNED_OFF_BY: &str = "Signed-off-by";
+
+/// The trailers of a message, in order, as `(key, value)` pairs.
+///
+/// The value of trailers with continuation lines has the lines joined by a
+/// single space.
+pub fn parse(message: &str) -> Vec<(String, String)> {
+    let (_, block) = split(message);
+    let mut trailers: Vec<(String, String)> = Vec::new();
+    for line in block {
+        match trailer(line) {
+            Some((key, value)) => trailers.push((key.to_string(), value.to_string())),
+            None => {
+                if let Some((_, value)) = trailers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            }
+        }
+    }
+
+    trailers
+}
+
+/// The value of the first trailer with the given key, if any.
+pub fn find<'m>(message: &'m str, key: &str) -> Option<&'m str> {
+    let (_, block) = split(message);
+    block
+        .into_iter()
+        .filter_map(trailer)
+        .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        .map(|(_, v)| v)
+}
+
+/// Add a trailer to a message, unless it has the same trailer already.
+///
+/// The message is returned as it is if it has the trailer.
+pub fn add(message: &str, key: &str, value: &str) -> String {
+    let (body, mut block) = split(message);
+    let exists = block
+        .iter()
+        .filter_map(|line| trailer(line))
+        .any(|(k, v)| k.eq_ignore_ascii_case(key) && v == value);
+    if exists {
+        return message.to_string();
+    }
+    let line = format!("{}: {}", key, value);
+    block.push(&line);
+
+    join(body, &block)
+}
+
+/// Remove the trailers with the given key from a message, only the ones with the
+/// given value if any.
+///
+/// The message is returned as it is if it has no such trailer.
+pub fn remove(message: &str, key: &str, value: Option<&str>) -> String {
+    let (body, block) = split(message);
+    let block_len = block.len();
+    let mut kept = Vec::with_capacity(block_len);
+    let mut removing = false;
+    for line in block {
+        // Continuation lines go with their trailer.
+        if let Some((k, v)) = trailer(line) {
+            removing = k.eq_ignore_ascii_case(key) && value.is_none_or(|value| v == value);
+        }
+        if !removing {
+            kept.push(line);
+        }
+    }
+    if kept.len() == block_len {
+        return message.to_string();
+    }
+
+    join(body, &kept)
+}
+
+/// The Change-Id of a message, if any.
+pub fn change_id(message: &str) -> Option<&str> {
+    find(message, CHANGE_ID)
+}
+
+/// Generate a Change-Id for a new patch, from its contents and the current time.
+pub fn new_change_id(tree: Oid, parent: Oid, author: &Signature<'_>, message: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let data = format!(
+        "tree {}\nparent {}\nauthor {} {}\ntime {}\n\n{}",
+        tree,
+        parent,
+        String::from_utf8_lossy(author.name_bytes()),
+        String::from_utf8_lossy(author.email_bytes()),
+        now.as_nanos(),
+        message
+    );
+    let oid = Oid::hash_object(ObjectType::Blob, data.as_bytes()).expect("hashing can't fail");
+
+    format!("I{}", oid)
+}
+
+/// Should new patches get a Change-Id, from `qg.changeId`?
+pub fn change_id_enabled(config: &git2::Config) -> Result<bool, Error> {
+    flag(config, "qg.changeId")
+}
+
+/// Should patches be signed off, from `qg.autoSignoff`?
+pub fn auto_signoff_enabled(config: &git2::Config) -> Result<bool, Error> {
+    flag(config, "qg.autoSignoff")
+}
+
+/// The value of a `Signed-off-by` trailer for the given identity.
+pub fn signoff(user: &Signature<'_>) -> String {
+    format!(
+        "{} <{}>",
+        String::from_utf8_lossy(user.name_bytes()),
+        String::from_utf8_lossy(user.email_bytes())
+    )
+}
+
+/// Can the string be used as a trailer key?
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+}
+
+fn flag(config: &git2::Config, key: &str) -> Result<bool, Error> {
+    match config.get_bool(key) {
+        Ok(enabled) => Ok(enabled),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Is the string a full or abbreviated Change-Id, i.e. `I` and at least 7
+/// hexadecimal digits?
+pub fn is_change_id(s: &str) -> bool {
+    s.strip_prefix('I').is_some_and(|hex| {
+        (7..=40).contains(&hex.len()) && hex.bytes().all(|b| b.is_ascii_hexdigit())
+    })
+}
+
+/// Split a message in its body, without trailing blank lines, and the lines of
+/// its trailer block.
+fn split(message: &str) -> (&str, Vec<&str>) {
+    let message = message.trim_end();
+    let (body, last) = match message.rfind("\n\n") {
+        Some(idx) => (&message[..idx], &message[idx + 2..]),
+        None => return (message, Vec::new()),
+    };
+
+    let lines: Vec<_> = last.lines().collect();
+    let is_block = lines.first().is_some_and(|line| trailer(line).is_some())
+        && lines
+            .iter()
+            .all(|line| trailer(line).is_some() || line.starts_with(char::is_whitespace));
+    if is_block {
+        (body.trim_end(), lines)
+    } else {
+        (message, Vec::new())
+    }
+}
+
+fn join(body: &str, block: &[&str]) -> String {
+    if block.is_empty() {
+        format!("{}\n", body)
+    } else {
+        format!("{}\n\n{}\n", body, block.join("\n"))
+    }
+}
+
+/// Parse a trailer line, returning its key and value.
+fn trailer(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    if is_valid_key(key) {
+        Some((key, value.trim()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "\
+Subject
+
+First paragraph.
+
+Second paragraph
+with two lines.
+
+Acked-by: A <a@example.com>
+Link: https://example.com/a
+  continued
+Cc: B
+";
+
+    fn pairs(trailers: &[(&str, &str)]) -> Vec<(String, String)> {
+        trailers
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_last_paragraph() {
+        assert_eq!(
+            parse(MESSAGE),
+            pairs(&[
+                ("Acked-by", "A <a@example.com>"),
+                ("Link", "https://example.com/a continued"),
+                ("Cc", "B"),
+            ])
+        );
+        assert_eq!(
+            parse("Subject\n\nCc: A\nCc: B\ncc: A\n"),
+            pairs(&[("Cc", "A"), ("Cc", "B"), ("cc", "A")])
+        );
+
+        for message in &[
+            "Fix: the build\n",
+            "Subject\n\nCc: A\n\nNot trailers.\n",
+            "Subject\n\nCc: A\nand text\n",
+            "Subject\n\n  indented\nCc: A\n",
+            "Subject\n\nNot a key: A\n",
+        ] {
+            assert_eq!(parse(message), [], "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn find_first_trailer() {
+        assert_eq!(find(MESSAGE, "link"), Some("https://example.com/a"));
+        assert_eq!(find(MESSAGE, "CC"), Some("B"));
+        assert_eq!(find("Subject\n\nCc: A\nCc: B\n", "Cc"), Some("A"));
+        assert_eq!(find(MESSAGE, "Signed-off-by"), None);
+        assert_eq!(find("Subject\n\nCc: A\n\nNot trailers.\n", "Cc"), None);
+    }
+
+    #[test]
+    fn add_trailers() {
+        assert_eq!(add("Subject", "Cc", "B"), "Subject\n\nCc: B\n");
+        assert_eq!(
+            add("Subject\n\nBody.\n\n\n", "Cc", "B"),
+            "Subject\n\nBody.\n\nCc: B\n"
+        );
+        assert_eq!(add(MESSAGE, "Cc", "C"), format!("{}Cc: C\n", MESSAGE));
+        assert_eq!(
+            add("Subject\n\nCc: A\nCc: B", "cc", "C"),
+            "Subject\n\nCc: A\nCc: B\ncc: C\n"
+        );
+        assert_eq!(
+            add("Subject\n\nCc: A\n\nMore.", "Cc", "A"),
+            "Subject\n\nCc: A\n\nMore.\n\nCc: A\n"
+        );
+
+        // Nothing changes, not even the trailing newline.
+        assert_eq!(add(MESSAGE, "cc", "B"), MESSAGE);
+        assert_eq!(add("Subject\n\nCc: A", "Cc", "A"), "Subject\n\nCc: A");
+        assert_eq!(
+            add("Subject\n\nCc: A\n\n\n", "Cc", "A"),
+            "Subject\n\nCc: A\n\n\n"
+        );
+    }
+
+    #[test]
+    fn remove_trailers() {
+        let without_link = MESSAGE.replace("Link: https://example.com/a\n  continued\n", "");
+        assert_eq!(remove(MESSAGE, "link", None), without_link);
+        assert_eq!(
+            remove(MESSAGE, "Link", Some("https://example.com/a")),
+            without_link
+        );
+
+        let duplicates = "Subject\n\nCc: A\nCc: B\ncc: A\n";
+        assert_eq!(remove(duplicates, "Cc", Some("A")), "Subject\n\nCc: B\n");
+        assert_eq!(remove(duplicates, "CC", None), "Subject\n");
+        assert_eq!(
+            remove("Subject\n\nBody.\n\nCc: A", "Cc", None),
+            "Subject\n\nBody.\n"
+        );
+
+        // Nothing changes, not even the trailing newline.
+        assert_eq!(remove(MESSAGE, "Cc", Some("C")), MESSAGE);
+        assert_eq!(
+            remove("Subject\n\nCc: A", "Cc", Some("B")),
+            "Subject\n\nCc: A"
+        );
+        assert_eq!(
+            remove("Subject\n\nCc: A\n\nMore.", "Cc", None),
+            "Subject\n\nCc: A\n\nMore."
+        );
+    }
+
+    #[test]
+    fn change_ids() {
+        let id = "I0123456789abcdef0123456789abcdef01234567";
+        let message = format!("Subject\n\nBody.\n\nCc: A\nChange-Id: {}\n", id);
+        assert_eq!(change_id(&message), Some(id));
+        let message = format!("Subject\n\nChange-Id: {}\n\nBody.\n", id);
+        assert_eq!(change_id(&message), None);
+
+        assert!(is_change_id(id));
+        assert!(is_change_id("I0123456"));
+        assert!(is_change_id("Iabcdef0"));
+        assert!(!is_change_id("I012345"));
+        assert!(!is_change_id("0123456789"));
+        assert!(!is_change_id("Ighijklm"));
+        assert!(!is_change_id(&format!("{}8", id)));
+
+        let author = Signature::now("A", "a@example.com").unwrap();
+        let new = new_change_id(Oid::zero(), Oid::zero(), &author, "Subject");
+        assert!(is_change_id(&new));
+        assert_eq!(new.len(), 41);
+    }
+
+    #[test]
+    fn keys_and_flags() {
+        assert!(is_valid_key("Signed-off-by"));
+        assert!(is_valid_key("Cc"));
+        assert!(!is_valid_key(""));
+        assert!(!is_valid_key("Not a key"));
+        assert!(!is_valid_key("Key_x"));
+
+        let author = Signature::now("A", "a@example.com").unwrap();
+        assert_eq!(signoff(&author), "A <a@example.com>");
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut config = git2::Config::open(&dir.path().join("config")).unwrap();
+        assert!(!auto_signoff_enabled(&config).unwrap());
+        assert!(!change_id_enabled(&config).unwrap());
+        config.set_bool("qg.autoSignoff", true).unwrap();
+        config.set_str("qg.changeId", "yes").unwrap();
+        assert!(auto_signoff_enabled(&config).unwrap());
+        assert!(change_id_enabled(&config).unwrap());
+        config.set_str("qg.autoSignoff", "maybe").unwrap();
+        assert!(auto_signoff_enabled(&config).is_err());
+    }
+}
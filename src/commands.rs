@@ -2,19 +2,80 @@ use crate::{error::Error, App};
 use clap::{Arg, ArgMatches};
 
 mod close;
+mod describe;
+mod diff;
+mod export;
+mod fetch;
+mod import;
+mod log;
+mod mail;
+mod migrate;
+mod prompt;
+mod publish;
+mod push_branches;
 mod queues;
+mod range_diff;
+mod review;
+mod series;
+mod show;
+mod status;
 mod switch;
+mod sync;
+mod trailer;
+mod version;
 
 pub(crate) type CmdExecFn = for<'a> fn(&'a ArgMatches<'static>) -> Result<(), Error>;
 
 static EXECUTE_MAPS: phf::Map<&'static str, CmdExecFn> = phf::phf_map! {
     "close" => close::execute,
+    "describe" => describe::execute,
+    "diff" => diff::execute,
+    "export" => export::execute,
+    "fetch" => fetch::execute,
+    "import" => import::execute,
+    "log" => log::execute,
+    "mail" => mail::execute,
+    "migrate" => migrate::execute,
+    "publish" => publish::execute,
+    "prompt" => prompt::execute,
+    "push-branches" => push_branches::execute,
     "queues" => queues::execute,
+    "range-diff" => range_diff::execute,
+    "review" => review::execute,
+    "series" => series::execute,
+    "show" => show::execute,
+    "status" => status::execute,
     "switch" => switch::execute,
+    "sync" => sync::execute,
+    "trailer" => trailer::execute,
+    "version" => version::execute,
 };
 
 pub(crate) fn all() -> impl IntoIterator<Item = App> {
-    [switch::subcommand(), close::subcommand(), queues::subcommand()]
+    [
+        switch::subcommand(),
+        close::subcommand(),
+        queues::subcommand(),
+        describe::subcommand(),
+        series::subcommand(),
+        status::subcommand(),
+        prompt::subcommand(),
+        log::subcommand(),
+        export::subcommand(),
+        import::subcommand(),
+        mail::subcommand(),
+        version::subcommand(),
+        range_diff::subcommand(),
+        show::subcommand(),
+        diff::subcommand(),
+        migrate::subcommand(),
+        publish::subcommand(),
+        fetch::subcommand(),
+        sync::subcommand(),
+        push_branches::subcommand(),
+        review::subcommand(),
+        trailer::subcommand(),
+    ]
 }
 
 pub(crate) fn get_exec_fn(subcommand: &str) -> Option<CmdExecFn> {
@@ -27,3 +88,10 @@ pub(self) fn flag(name: &'static str, short: &'static str) -> Arg<'static, 'stat
         .long(name)
         .takes_value(false)
 }
+
+fn show_signature_flag() -> Arg<'static, 'static> {
+    Arg::with_name("show-signature")
+        .long("show-signature")
+        .takes_value(false)
+        .help("Verify and show the signature of each commit")
+}
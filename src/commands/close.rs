@@ -35,13 +35,14 @@ pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
 
 fn close(queues: Vec<String>, force: bool) -> Result<(), Error> {
     let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
 
     let mut git_queues = Vec::with_capacity(queues.len());
     for q in queues {
         match Queue::for_queue(&ctx, &q) {
             Ok(Some(q)) => git_queues.push(q),
             Ok(None) => throw!(DATAERR, "Queue `{}` not found", q),
-            Err(e) => return Err(e.into())
+            Err(e) => return Err(e.into()),
         }
     }
 
@@ -65,7 +66,11 @@ fn close_one(queue: Queue<'_>, force: bool) -> Result<(), Error> {
     } else if queue.can_close() {
         queue.close()?
     } else {
-        throw!(USAGE, "The queue contains {} patches, cannot close.");
+        throw!(
+            USAGE,
+            "The queue contains {} patches, cannot close.",
+            queue.patches_num()
+        );
     };
 
     Ok(())
@@ -0,0 +1,93 @@
+use std::io::Read;
+
+use clap::{Arg, ArgMatches, SubCommand};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("describe")
+        .about("Edit the description of a queue")
+        .long_about(
+            "\
+Edit the description of a queue, opening the description in the editor if \
+neither -m/--message nor -F/--file is given.
+
+The first line of the description is its subject, followed by an empty line and \
+the body. The description is used as the cover letter when the patches of the \
+queue are exported or sent by email. Each edit is recorded in the queue log.",
+        )
+        .args(&[
+            Arg::with_name("message")
+                .short("m")
+                .long("message")
+                .takes_value(true)
+                .conflicts_with_all(&["file", "clear"])
+                .help("Use the given description."),
+            Arg::with_name("file")
+                .short("F")
+                .long("file")
+                .takes_value(true)
+                .empty_values(false)
+                .conflicts_with("clear")
+                .help("Read the description from the file, or the standard input if `-`."),
+            Arg::with_name("clear")
+                .long("clear")
+                .takes_value(false)
+                .help("Remove the description."),
+            Arg::with_name("queue")
+                .required(false)
+                .empty_values(false)
+                .help("Queue to describe, defaults to the current one."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(queue = tracing::field::Empty))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let queue = args.value_of("queue");
+
+    tracing::Span::current().record("queue", tracing::field::debug(queue));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+    let mut queue = crate::git::queue_or_current(&ctx, queue)?;
+
+    let description = if args.is_present("clear") {
+        None
+    } else if let Some(message) = args.value_of("message") {
+        Some(message.to_string())
+    } else if let Some(file) = args.value_of("file") {
+        let mut text = String::new();
+        let res = if file == "-" {
+            std::io::stdin().read_to_string(&mut text).map(|_| ())
+        } else {
+            std::fs::read_to_string(file).map(|t| text = t)
+        };
+        if let Err(err) = res {
+            throw!(NOINPUT, "Failed to read `{}`: {}", file, err);
+        }
+        Some(text)
+    } else {
+        let template = format!(
+            "{}\n\
+             # Describe the queue `{}`. The first line is the subject of its cover\n\
+             # letter, the rest is the body. Lines starting with '#' are ignored, and\n\
+             # an empty description aborts the edit.\n",
+            queue.description().unwrap_or_default(),
+            queue.name()
+        );
+        let text = crate::editor::edit(&ctx, "DESCRIPTION_EDITMSG", &template)?;
+        if text.trim().is_empty() {
+            throw!(DATAERR, "Aborting due to empty description");
+        }
+        Some(text)
+    };
+
+    let description = description
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty());
+    if description.as_deref() == queue.description() {
+        return Ok(());
+    }
+
+    Ok(queue.describe(description)?)
+}
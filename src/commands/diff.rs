@@ -0,0 +1,190 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::{ctx::Ctx, queue::log::QueueState, range_diff, Oid};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("diff")
+        .about("Show changes of the working tree, patch ranges or a patch")
+        .long_about(
+            "\
+Without arguments, show the changes in the working tree and index that aren't in \
+the top patch of the current queue.
+
+With -r/--range, show the cumulative diff of a range of applied patches, e.g. \
+`top~2..top` or `<from>..<to>`, where either side may be omitted to mean the \
+first or last applied patch. A single patch shows the diff of that patch.
+
+With --since, show how a patch changed since a previous state of the queue, as \
+the diff between the old and new text of the patch (its message and diff), \
+rather than between the trees. By default the top patch is compared. The state \
+given to --since and --until can be:
+
+  * a log entry, by the OID shown by `log`, or any revision naming it, e.g. \
+`refs/queuelogs/<queue>~2` with the default `qg.logRef`;
+  * a version created by `version bump`, as `v<n>`;
+  * `mail`, the last state where the patch was sent by email;
+  * `export`, the last state where the patch was exported.
+
+--until defaults to the current state.",
+        )
+        .args(&[
+            Arg::with_name("range")
+                .short("r")
+                .long("range")
+                .takes_value(true)
+                .empty_values(false)
+                .value_name("patch-range")
+                .conflicts_with("since")
+                .help("Range of applied patches to show the cumulative diff of."),
+            Arg::with_name("since")
+                .long("since")
+                .takes_value(true)
+                .empty_values(false)
+                .value_name("state")
+                .help("State to compare the patch from."),
+            Arg::with_name("until")
+                .long("until")
+                .takes_value(true)
+                .empty_values(false)
+                .value_name("state")
+                .requires("since")
+                .help("State to compare the patch to, defaults to the current one."),
+            Arg::with_name("stat")
+                .long("stat")
+                .takes_value(false)
+                .conflicts_with("since")
+                .help("Show the diffstat instead of the diff."),
+            crate::diff::color_arg(),
+            Arg::with_name("patch")
+                .required(false)
+                .empty_values(false)
+                .requires("since")
+                .help("Patch to compare with --since, defaults to the top patch."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    range = tracing::field::Empty,
+    since = tracing::field::Empty,
+    until = tracing::field::Empty,
+    patch = tracing::field::Empty,
+    stat = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let range = args.value_of("range");
+    let since = args.value_of("since");
+    let until = args.value_of("until");
+    let patch = args.value_of("patch");
+    let stat = args.is_present("stat");
+
+    tracing::Span::current()
+        .record("range", tracing::field::debug(range))
+        .record("since", tracing::field::debug(since))
+        .record("until", tracing::field::debug(until))
+        .record("patch", tracing::field::debug(patch))
+        .record("stat", stat);
+
+    let ctx = crate::git::current_git_ctx()?;
+    // Only one of them can be given, --range conflicts with --since.
+    let spec = range.or(patch).map(crate::git::patch_spec).transpose()?;
+    let queue = crate::git::queue_for_spec(&ctx, spec.as_ref())?;
+    let state = queue.state();
+    let color = crate::diff::use_color(&ctx, args);
+    let repo = ctx.repo();
+
+    if let Some(since) = since {
+        let patch = match &spec {
+            Some(spec) => {
+                spec.resolve_one(repo, state)
+                    .map_err(git_queue::Error::from)?
+                    .0
+            }
+            None => match state.applied().last() {
+                Some((name, _)) => name,
+                None => throw!(USAGE, "There are no applied patches, specify the patch"),
+            },
+        };
+        return diff_since(&ctx, state, since, until, patch, color);
+    }
+
+    let diff = match range {
+        Some(range) => {
+            let patches = crate::git::applied_patches(&ctx, state, spec.as_ref())?;
+            match (patches.first(), patches.last()) {
+                (Some(&(_, first)), Some(&(_, last))) => {
+                    git_queue::export::series_diff(repo, first, last)?
+                }
+                _ => throw!(DATAERR, "No applied patches in `{}`", range),
+            }
+        }
+        None => repo
+            .find_commit(state.head())
+            .and_then(|c| c.tree())
+            .and_then(|tree| repo.diff_tree_to_workdir_with_index(Some(&tree), None))
+            .map_err(git_queue::Error::from)?,
+    };
+
+    crate::diff::print_diff(&diff, stat, color)
+}
+
+fn diff_since(
+    ctx: &Ctx,
+    state: &QueueState,
+    since: &str,
+    until: Option<&str>,
+    patch: &str,
+    color: bool,
+) -> Result<(), Error> {
+    let new_state = match until {
+        Some(until) => find_state(ctx, state, until, patch)?,
+        None => state.clone(),
+    };
+    let old_state = find_state(ctx, &new_state, since, patch)?;
+
+    let old = patch_in(&old_state, since, patch)?;
+    let new = patch_in(&new_state, until.unwrap_or("the current state"), patch)?;
+
+    let interdiff = range_diff::interdiff(ctx.repo(), old, new)?;
+    if interdiff.is_empty() {
+        println!("Patch `{}` did not change", patch);
+    } else {
+        let header = format!("{:.7}..{:.7} {}", old.to_string(), new.to_string(), patch);
+        println!("{}", crate::diff::paint(color, crate::diff::BOLD, &header));
+        crate::diff::print_interdiff(&interdiff, color);
+    }
+
+    Ok(())
+}
+
+/// Find a queue state by a log entry revision, version, `mail` or `export`.
+fn find_state(ctx: &Ctx, state: &QueueState, spec: &str, patch: &str) -> Result<QueueState, Error> {
+    let repo = ctx.repo();
+    let found = match spec {
+        "mail" => state.find(repo, |s| {
+            s.mail()
+                .is_some_and(|m| m.patches.iter().any(|(name, _)| name == patch))
+        })?,
+        "export" => state.find(repo, |s| s.exported().iter().any(|name| name == patch))?,
+        _ if state.versions().any(|(name, _)| name == spec) => state.version(repo, spec)?,
+        _ => match repo.revparse_single(spec).and_then(|o| o.peel_to_commit()) {
+            // Only entries of this log, not of other queues, have a state here.
+            Ok(commit) => state.find_merged(repo, |s| s.oid() == Some(commit.id()))?,
+            Err(_) => None,
+        },
+    };
+
+    match found {
+        Some(state) => Ok(state),
+        None if spec == "mail" => throw!(DATAERR, "Patch `{}` was never sent by email", patch),
+        None if spec == "export" => throw!(DATAERR, "Patch `{}` was never exported", patch),
+        None => throw!(DATAERR, "`{}` is not a log entry nor a version", spec),
+    }
+}
+
+fn patch_in(state: &QueueState, spec: &str, patch: &str) -> Result<Oid, Error> {
+    match state.patch(patch) {
+        Some(oid) => Ok(oid),
+        None => throw!(DATAERR, "Patch `{}` does not exist in {}", patch, spec),
+    }
+}
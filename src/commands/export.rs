@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::export;
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("export")
+        .about("Export applied patches as patch files")
+        .long_about(
+            "\
+Export the applied patches of the current queue as a numbered patch series. \
+Each patch is written in the same format of `git format-patch`, in a file \
+named `NNNN-<subject>.patch`, and can be applied with `git am`.
+
+A quilt-style `series` file listing the patch files in order is written along \
+the patches. With --cover-letter, a `0000-cover-letter.patch` file is written too, \
+using the queue description as subject and body, see `describe`.
+
+By default all applied patches are exported. A single patch or a range of \
+applied patches can be given in <patch-range>, e.g. `top~2..top` or `<from>..<to>`, \
+where either side may be omitted to mean the first or last applied patch. A \
+`<queue>:` prefix exports the patches of another queue.
+
+The export is recorded in the queue log, see `diff --since`.",
+        )
+        .args(&[
+            Arg::with_name("dir")
+                .short("d")
+                .long("dir")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Directory to write the patches to, defaults to the current directory."),
+            Arg::with_name("cover-letter")
+                .long("cover-letter")
+                .takes_value(false)
+                .help("Write a cover letter with the queue description."),
+            Arg::with_name("patch-range")
+                .required(false)
+                .empty_values(false)
+                .help("Patches to export."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    dir = tracing::field::Empty,
+    range = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let dir = Path::new(args.value_of("dir").unwrap_or("."));
+    let range = args.value_of("patch-range");
+
+    tracing::Span::current()
+        .record("dir", tracing::field::debug(dir))
+        .record("range", tracing::field::debug(range));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+    let spec = range.map(crate::git::patch_spec).transpose()?;
+    let mut queue = crate::git::queue_for_spec(&ctx, spec.as_ref())?;
+
+    let patches = crate::git::applied_patches(&ctx, queue.state(), spec.as_ref())?;
+    if patches.is_empty() {
+        throw!(DATAERR, "No applied patches to export");
+    }
+
+    let mut formatted = export::format_patches(ctx.repo(), patches)?;
+    if args.is_present("cover-letter") {
+        let cover_letter = export::queue_cover_letter(&queue, &formatted)?;
+        formatted.insert(0, cover_letter);
+    }
+    for path in export::write_series(dir, &formatted)? {
+        println!("{}", path.display());
+    }
+
+    let exported = formatted
+        .iter()
+        .filter(|p| !p.id().is_zero())
+        .map(|p| p.name().to_string())
+        .collect();
+    queue.record_export(exported)?;
+
+    Ok(())
+}
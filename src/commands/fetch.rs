@@ -0,0 +1,70 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::remote::{FetchOutcome, QueueRemote};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("fetch")
+        .about("Fetch queues from a remote")
+        .long_about(
+            "\
+Fetch the given queues, by default all of them, published in a remote with \
+`publish`. Queues that don't exist locally are created, and existing ones are \
+updated to the remote state. If the queue is the current one, the working tree \
+and index are updated too.
+
+The remote is given in -r/--remote, or `qg.remote`, or `origin`.
+
+A queue is left untouched if it has local changes that weren't published. If \
+both the local and remote queues have changes, the fetch is refused.",
+        )
+        .args(&[
+            Arg::with_name("remote")
+                .short("r")
+                .long("remote")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Remote to fetch the queues from."),
+            Arg::with_name("queue")
+                .multiple(true)
+                .empty_values(false)
+                .help("Queues to fetch, defaults to all queues in the remote."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    remote = tracing::field::Empty,
+    queues = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let remote = args.value_of("remote");
+    let queues: Vec<_> = args.values_of("queue").into_iter().flatten().collect();
+
+    tracing::Span::current()
+        .record("remote", tracing::field::debug(remote))
+        .record("queues", tracing::field::debug(&queues));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+
+    let remote = QueueRemote::connect(&ctx, remote)?;
+    let queues: Vec<String> = if queues.is_empty() {
+        remote.queues().map(str::to_string).collect()
+    } else {
+        queues.into_iter().map(str::to_string).collect()
+    };
+    if queues.is_empty() {
+        println!("No queues published in {}", remote.name());
+    }
+
+    for queue in &queues {
+        match remote.fetch(queue)? {
+            FetchOutcome::Created => println!("Created {} from {}", queue, remote.name()),
+            FetchOutcome::Updated => println!("Updated {} from {}", queue, remote.name()),
+            FetchOutcome::UpToDate => println!("{} is up to date", queue),
+            FetchOutcome::Ahead => println!("{} has changes that weren't published", queue),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use std::path::Path;
+
+use clap::{Arg, ArgGroup, ArgMatches, SubCommand};
+use git_queue::{import, stgit::StgitStack};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("import")
+        .about("Import patches into the current queue")
+        .long_about(
+            "\
+Import patches as new patches on top of the current queue. The patches can be \
+given as a mbox file, like the ones written by `git format-patch` or saved from \
+a mailing list, a single patch file, a directory or a quilt `series` file.
+
+If <path> is a directory containing a `series` file, it defines which patches \
+are imported and in which order, otherwise all `.patch` and `.diff` files in the \
+directory are imported, sorted by name.
+
+The author, date, subject and description of each patch are read from the \
+message headers. Plain patches without headers are authored by the current user \
+and use the first paragraph of the description, or the file name, as subject. \
+Patch names are derived from the subjects.
+
+If a patch fails to apply, the import stops. Apply the patch manually (e.g. with \
+`git apply --3way`), stage the changes and run the command with --continue to \
+create the patch from the index and import the remaining ones. --skip skips the \
+failed patch instead, and --abort discards the remaining patches.
+
+With --from-stgit, the StGit stack of <branch> is imported as a new queue with \
+the same name, or with `-stgit` added if its patch references would be the ones \
+of StGit, like in the default layout. The queue is based on --base, by default \
+the upstream of <branch>. The patches keep their commits, applied and unapplied \
+patches stay so, and hidden patches become the last unapplied ones. The StGit \
+stack is left untouched.",
+        )
+        .args(&[
+            Arg::with_name("series")
+                .short("s")
+                .long("series")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Quilt series file listing the patches to import."),
+            Arg::with_name("from-stgit")
+                .long("from-stgit")
+                .takes_value(true)
+                .value_name("branch")
+                .empty_values(false)
+                .help("Create a queue from the StGit stack of the branch."),
+            Arg::with_name("base")
+                .long("base")
+                .takes_value(true)
+                .value_name("branch")
+                .empty_values(false)
+                .requires("from-stgit")
+                .help("Base branch of the queue created with --from-stgit."),
+            Arg::with_name("continue")
+                .long("continue")
+                .takes_value(false)
+                .help("Continue the import after manually applying the failed patch."),
+            Arg::with_name("skip")
+                .long("skip")
+                .takes_value(false)
+                .help("Skip the failed patch and continue the import."),
+            Arg::with_name("abort")
+                .long("abort")
+                .takes_value(false)
+                .help("Discard the patches not yet imported."),
+            Arg::with_name("path")
+                .required(false)
+                .empty_values(false)
+                .help("A mbox, patch file or directory to import."),
+        ])
+        .group(
+            ArgGroup::with_name("action")
+                .args(&["series", "path", "from-stgit", "continue", "skip", "abort"])
+                .required(true),
+        )
+}
+
+#[tracing::instrument(skip(args), fields(
+    series = tracing::field::Empty,
+    path = tracing::field::Empty,
+    from_stgit = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let series = args.value_of("series");
+    let path = args.value_of("path");
+    let from_stgit = args.value_of("from-stgit");
+
+    tracing::Span::current()
+        .record("series", tracing::field::debug(series))
+        .record("path", tracing::field::debug(path))
+        .record("from_stgit", tracing::field::debug(from_stgit));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+
+    if let Some(branch) = from_stgit {
+        return import_stgit(&ctx, branch, args.value_of("base"));
+    }
+
+    if args.is_present("abort") {
+        return Ok(import::abort(&ctx)?);
+    }
+
+    let mut queue = crate::git::queue_or_current(&ctx, None)?;
+    let resuming = args.is_present("continue") || args.is_present("skip");
+    match import::pending_queue(&ctx)? {
+        Some(pending) if pending != queue.name() => throw!(
+            USAGE,
+            "An import into queue `{}` is in progress, switch to it to continue or use --abort",
+            pending
+        ),
+        Some(_) if !resuming => throw!(
+            USAGE,
+            "An import is in progress, use --continue, --skip or --abort"
+        ),
+        None if resuming => throw!(USAGE, "There is no import in progress"),
+        _ => {}
+    }
+
+    let res = if args.is_present("continue") {
+        import::resume(&mut queue)
+    } else if args.is_present("skip") {
+        import::skip(&mut queue)
+    } else {
+        let files = match (series, path) {
+            (Some(series), _) => import::read_series(Path::new(series))?,
+            (None, Some(path)) if Path::new(path).is_dir() => import::read_dir(Path::new(path))?,
+            (None, Some(path)) => vec![path.into()],
+            (None, None) => unreachable!("enforced by clap"),
+        };
+
+        let mut patches = Vec::new();
+        for file in files {
+            patches.extend(import::read_file(&file)?);
+        }
+        if patches.is_empty() {
+            throw!(DATAERR, "No patches found to import");
+        }
+
+        import::import(&mut queue, patches)
+    };
+
+    match res {
+        Ok(names) => {
+            for name in names {
+                println!("Imported {}", name);
+            }
+            Ok(())
+        }
+        Err(err @ git_queue::Error::ApplyFailed(_)) => {
+            if let Some(failed) = import::failed_patch_path(&ctx)? {
+                eprintln!(
+                    "Apply the patch in {} manually, stage the changes and run \
+                     `{} import --continue`.",
+                    failed.display(),
+                    clap::crate_name!()
+                );
+            }
+            Err(err.into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn import_stgit(ctx: &git_queue::ctx::Ctx, branch: &str, base: Option<&str>) -> Result<(), Error> {
+    let stack = match StgitStack::read(ctx.repo(), branch)? {
+        Some(stack) => stack,
+        None => throw!(USAGE, "Branch `{}` has no StGit stack", branch),
+    };
+    let base = match base {
+        Some(base) => match ctx.find_branch(base)? {
+            Some(base) => base,
+            None => throw!(DATAERR, "Branch `{}` does not exist", base),
+        },
+        None => match stack.upstream(ctx.repo())? {
+            Some(upstream) => upstream,
+            None => throw!(
+                USAGE,
+                "Branch `{}` has no local upstream, give the base of the queue with --base",
+                branch
+            ),
+        },
+    };
+    let (applied, unapplied) = (stack.applied().len(), stack.unapplied().len());
+    let hidden = stack.hidden().len();
+
+    let queue = match git_queue::stgit::import_stack(ctx, stack, base) {
+        Ok(queue) => queue,
+        Err(git_queue::Error::AlreadyExists("queue")) => throw!(
+            CANTCREAT,
+            "Queue `{}` already exists",
+            git_queue::stgit::queue_name(ctx, branch)
+        ),
+        Err(err) => return Err(err.into()),
+    };
+
+    println!(
+        "Created queue `{}` with {} applied, {} unapplied and {} hidden patches",
+        queue.name(),
+        applied,
+        unapplied,
+        hidden
+    );
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use clap::{Arg, ArgMatches, SubCommand};
+
+use git_queue::{queue::log::QueueState, report::LogEntryReport, Oid};
+use prettytable::{Cell, Row};
+
+use crate::{
+    error::Error,
+    format::{self, Format},
+    App,
+};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("log")
+        .about("Show the log of a queue")
+        .long_about(
+            "\
+Show the log of a queue, by default the current one. Each entry of the log \
+corresponds to an operation executed in the queue, the most recent first.
+
+With --show-signature, the signature of each log entry is verified and \
+reported as good, bad, unknown (when it could not be checked) or unsigned.
+
+With --format table, the entries are printed in a table, and with --format json, \
+as a JSON array of entries, each one with the patches of the queue after it.",
+        )
+        .args(&[
+            super::show_signature_flag(),
+            Arg::with_name("max-count")
+                .short("n")
+                .long("max-count")
+                .takes_value(true)
+                .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Limit the number of entries to show."),
+            Arg::with_name("queue")
+                .required(false)
+                .empty_values(false)
+                .help("Queue to show the log, defaults to the current queue."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    queue = tracing::field::Empty,
+    max_count = tracing::field::Empty,
+    show_signature = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let queue = args.value_of("queue");
+    let max_count = args
+        .value_of("max-count")
+        .map(|n| n.parse::<usize>().expect("validated by clap"));
+    let show_signature = args.is_present("show-signature");
+
+    tracing::Span::current()
+        .record("queue", tracing::field::debug(queue))
+        .record("max_count", tracing::field::debug(max_count))
+        .record("show_signature", show_signature);
+
+    let ctx = crate::git::current_git_ctx()?;
+    let queue = crate::git::queue_or_current(&ctx, queue)?;
+    let format = format::from_args(args, Format::Plain);
+
+    let mut entries = Vec::new();
+    let mut state = Some(queue.state().clone());
+    while let Some(entry) = state {
+        if max_count.is_some_and(|max| entries.len() >= max) {
+            break;
+        }
+
+        state = entry.previous(ctx.repo())?;
+        entries.push(entry);
+    }
+
+    match format {
+        Format::Json => {
+            let mut reports = Vec::with_capacity(entries.len());
+            for entry in &entries {
+                let mut report = LogEntryReport::new(entry)?;
+                if show_signature {
+                    report.set_signature(&ctx.verify_commit(oid(entry))?);
+                }
+                reports.push(report);
+            }
+            format::print_json(&reports)?;
+        }
+        Format::Table => {
+            let mut titles = vec!["Entry", "Message"];
+            if show_signature {
+                titles.push("Signature");
+            }
+
+            let mut table = crate::table::new(titles.into_iter());
+            for entry in &entries {
+                let mut row = Row::new(vec![
+                    Cell::new(&format!("{:.7}", oid(entry).to_string())),
+                    Cell::new(entry.message()),
+                ]);
+                if show_signature {
+                    row.add_cell(Cell::new(&ctx.verify_commit(oid(entry))?.to_string()));
+                }
+                table.add_row(row);
+            }
+            table.printstd();
+        }
+        Format::Plain => {
+            for entry in &entries {
+                println!("{:.7} {}", oid(entry).to_string(), entry.message());
+                if show_signature {
+                    println!("        {}", ctx.verify_commit(oid(entry))?);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn oid(entry: &QueueState) -> Oid {
+    entry.oid().expect("log entries are always committed")
+}
@@ -0,0 +1,126 @@
+use std::io::Write;
+
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::{export, mail::SendEmailConfig};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("mail")
+        .about("Send applied patches by email")
+        .long_about(
+            "\
+Send the applied patches of the current queue as a series of email messages, \
+in the same format of `git format-patch`. The messages are threaded as replies \
+to the cover letter, if --cover-letter is given, or to the first patch. The cover \
+letter uses the queue description as subject and body, see `describe`.
+
+The messages are sent like `git send-email` does, honoring its `sendemail.*` \
+configuration: through the SMTP server in `sendemail.smtpServer`, the command in \
+`sendemail.sendmailCmd`, or the local `sendmail` program. Recipients in \
+`sendemail.to` and `sendemail.cc` are added to the ones given in the command \
+line.
+
+The Message-ID of each sent message is recorded in the queue log.
+
+By default all applied patches are sent. A single patch or a range of applied \
+patches can be given in <patch-range>, e.g. `top~2..top` or `<from>..<to>`, where \
+either side may be omitted to mean the first or last applied patch. A `<queue>:` \
+prefix sends the patches of another queue.",
+        )
+        .args(&[
+            Arg::with_name("to")
+                .long("to")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("address")
+                .empty_values(false)
+                .help("Recipient of the messages, can be given multiple times."),
+            Arg::with_name("cc")
+                .long("cc")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("address")
+                .empty_values(false)
+                .help("Carbon copy recipient of the messages, can be given multiple times."),
+            Arg::with_name("cover-letter")
+                .long("cover-letter")
+                .takes_value(false)
+                .help("Send a cover letter before the patches."),
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .takes_value(false)
+                .help("Print the messages in the mbox format instead of sending them."),
+            Arg::with_name("patch-range")
+                .required(false)
+                .empty_values(false)
+                .help("Patches to send."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    range = tracing::field::Empty,
+    cover_letter = tracing::field::Empty,
+    dry_run = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let range = args.value_of("patch-range");
+    let cover_letter = args.is_present("cover-letter");
+    let dry_run = args.is_present("dry-run");
+
+    tracing::Span::current()
+        .record("range", tracing::field::debug(range))
+        .record("cover_letter", cover_letter)
+        .record("dry_run", dry_run);
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+    let spec = range.map(crate::git::patch_spec).transpose()?;
+    let mut queue = crate::git::queue_for_spec(&ctx, spec.as_ref())?;
+    let config = SendEmailConfig::from_config(ctx.config())?;
+
+    let values = |name| {
+        args.values_of(name)
+            .into_iter()
+            .flatten()
+            .map(str::to_string)
+    };
+    let to: Vec<_> = values("to").chain(config.to().iter().cloned()).collect();
+    let cc: Vec<_> = values("cc").chain(config.cc().iter().cloned()).collect();
+    if to.is_empty() && cc.is_empty() && !dry_run {
+        throw!(USAGE, "No recipients, use --to or set `sendemail.to`");
+    }
+
+    let patches = crate::git::applied_patches(&ctx, queue.state(), spec.as_ref())?;
+    if patches.is_empty() {
+        throw!(DATAERR, "No applied patches to send");
+    }
+
+    let patches = export::format_patches(ctx.repo(), patches)?;
+    let cover_letter = if cover_letter {
+        Some(export::queue_cover_letter(&queue, &patches)?)
+    } else {
+        None
+    };
+
+    let sender = config.sender(ctx.user());
+    let series = config.prepare(&sender, &to, &cc, cover_letter, patches);
+
+    if dry_run {
+        let mut stdout = std::io::stdout();
+        for message in series.messages() {
+            stdout
+                .write_all(&message.to_mbox())
+                .map_err(git_queue::Error::from)?;
+        }
+        return Ok(());
+    }
+
+    series.send(config.transport(), &mut queue, |message| {
+        println!("Sent {}", message.header("Subject").unwrap_or_default());
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::refs::{self, RefLayout};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("migrate")
+        .about("Move queues to the configured reference prefixes")
+        .long_about(
+            "\
+Move the queues stored with other reference prefixes to the ones configured in \
+`qg.branchPrefix`, `qg.logRef` and `qg.patchRef`. The branch, log and patch \
+references of each queue are renamed.
+
+By default the queues are moved from the default prefixes, `queues/`, \
+`refs/queuelogs/` and `refs/patches/`, so the usual way to change them is to set \
+the new prefixes in the configuration and run `migrate`. The previous prefixes \
+can be given with --from-branch-prefix, --from-log-ref and --from-patch-ref.",
+        )
+        .args(&[
+            Arg::with_name("from-branch-prefix")
+                .long("from-branch-prefix")
+                .takes_value(true)
+                .empty_values(false)
+                .value_name("prefix")
+                .help("Previous prefix of queue branches."),
+            Arg::with_name("from-log-ref")
+                .long("from-log-ref")
+                .takes_value(true)
+                .empty_values(false)
+                .value_name("prefix")
+                .help("Previous prefix of queue logs."),
+            Arg::with_name("from-patch-ref")
+                .long("from-patch-ref")
+                .takes_value(true)
+                .empty_values(false)
+                .value_name("prefix")
+                .help("Previous prefix of patch references."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(from = tracing::field::Empty))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let from = RefLayout::new(
+        args.value_of("from-branch-prefix")
+            .unwrap_or(refs::DEFAULT_BRANCH_PREFIX),
+        args.value_of("from-log-ref")
+            .unwrap_or(refs::DEFAULT_LOG_REF),
+        args.value_of("from-patch-ref")
+            .unwrap_or(refs::DEFAULT_PATCH_REF),
+    )?;
+
+    tracing::Span::current().record("from", tracing::field::debug(&from));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+
+    if &from == ctx.refs() {
+        throw!(
+            USAGE,
+            "The queues already use the configured prefixes, set the new ones in \
+            `qg.branchPrefix`, `qg.logRef` or `qg.patchRef`"
+        );
+    }
+
+    let queues = refs::migrate(ctx.repo(), &from, ctx.refs())?;
+    if queues.is_empty() {
+        println!("No queues to migrate");
+    }
+    for queue in queues {
+        println!("Migrated {}", queue);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::prompt::PromptInfo;
+
+use crate::{error::Error, App};
+
+const DEFAULT_FORMAT: &str = "%q %a/%n %t";
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("prompt")
+        .about("Show the current queue for shell prompts")
+        .long_about(
+            "\
+Print the current queue and its top patch, for shell prompts, e.g. in PS1 with \
+`$(qg prompt)`. Nothing is printed outside of a queue.
+
+The output is given by <format>, where `%q` is replaced by the queue, `%t` by the \
+top patch, or `-` if no patch is applied, `%a` by the number of applied patches, \
+`%n` by the total number of patches and `%%` by `%`. The default is `%q %a/%n %t`, \
+e.g. `feature 2/3 fix-typo`.
+
+To be fast enough to run on every command, only HEAD, the repository \
+configuration and the log of the current queue are read, not the global \
+configuration. Because of this, queues stored in a custom layout (see `migrate`) \
+set in the global configuration must be given by --branch-prefix and --log-ref.",
+        )
+        .args(&[
+            Arg::with_name("branch-prefix")
+                .long("branch-prefix")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Prefix of the queue branches, instead of `qg.branchPrefix`."),
+            Arg::with_name("log-ref")
+                .long("log-ref")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Prefix of the queue logs, instead of `qg.logRef`."),
+            // Not named `format`, that is the global output format.
+            Arg::with_name("template")
+                .index(1)
+                .value_name("format")
+                .help("Format of the output, defaults to `%q %a/%n %t`."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(format = tracing::field::Empty))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let format = args.value_of("template").unwrap_or(DEFAULT_FORMAT);
+
+    tracing::Span::current().record("format", format);
+
+    // Outside of a repository there is nothing to show, like outside of a queue.
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd,
+        Err(_) => return Ok(()),
+    };
+
+    if let Some(info) = PromptInfo::current(
+        &cwd,
+        args.value_of("branch-prefix"),
+        args.value_of("log-ref"),
+    )? {
+        println!("{}", info.format(format));
+    }
+
+    Ok(())
+}
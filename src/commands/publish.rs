@@ -0,0 +1,69 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::remote::{PublishOutcome, QueueRemote};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("publish")
+        .about("Publish queues to a remote")
+        .long_about(
+            "\
+Push the branch, log and patches of the given queues, by default the current \
+one, to a remote, so they can be fetched with `fetch` in other repositories.
+
+The queues are stored in the remote under the namespace in `qg.remoteNamespace`, \
+`refs/queues` by default. The remote is given in -r/--remote, or \
+`qg.remote`, or `origin`.
+
+A queue can't be published if the remote has changes to it that aren't in the \
+local queue, they must be fetched first.",
+        )
+        .args(&[
+            Arg::with_name("remote")
+                .short("r")
+                .long("remote")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Remote to publish the queues to."),
+            Arg::with_name("queue")
+                .multiple(true)
+                .empty_values(false)
+                .help("Queues to publish, defaults to the current queue."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    remote = tracing::field::Empty,
+    queues = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let remote = args.value_of("remote");
+    let queues: Vec<_> = args.values_of("queue").into_iter().flatten().collect();
+
+    tracing::Span::current()
+        .record("remote", tracing::field::debug(remote))
+        .record("queues", tracing::field::debug(&queues));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+
+    let mut to_publish = Vec::with_capacity(queues.len().max(1));
+    if queues.is_empty() {
+        to_publish.push(crate::git::queue_or_current(&ctx, None)?);
+    }
+    for queue in queues {
+        to_publish.push(crate::git::queue_or_current(&ctx, Some(queue))?);
+    }
+
+    let remote = QueueRemote::connect(&ctx, remote)?;
+    for queue in &to_publish {
+        match remote.publish(queue)? {
+            PublishOutcome::Published => {
+                println!("Published {} to {}", queue.name(), remote.name())
+            }
+            PublishOutcome::UpToDate => println!("{} is up to date", queue.name()),
+        }
+    }
+
+    Ok(())
+}
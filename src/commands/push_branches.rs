@@ -0,0 +1,77 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::remote::{BranchOutcome, QueueRemote};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("push-branches")
+        .about("Push each applied patch as its own branch")
+        .long_about(
+            "\
+Push each applied patch of a queue, by default the current one, as its own \
+branch named `<prefix>/<queue>/<patch>`, so each patch can be reviewed in a \
+separate pull request based on the branch of the previous patch.
+
+The prefix is given in --prefix, or `qg.reviewPrefix`, or `review`. The remote \
+is given in -r/--remote, or `qg.remote`, or `origin`.
+
+The commits pushed are recorded in the queue log, so only the branches of \
+patches that changed are pushed again, and the branches of patches removed \
+from the queue are deleted. A branch is only overwritten if it still has the \
+commit last pushed, like `git push --force-with-lease`.",
+        )
+        .args(&[
+            Arg::with_name("remote")
+                .short("r")
+                .long("remote")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Remote to push the branches to."),
+            Arg::with_name("prefix")
+                .long("prefix")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Prefix of the branch names."),
+            Arg::with_name("queue")
+                .empty_values(false)
+                .help("Queue to push, defaults to the current queue."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    remote = tracing::field::Empty,
+    prefix = tracing::field::Empty,
+    queue = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let remote = args.value_of("remote");
+    let prefix = args.value_of("prefix");
+    let queue = args.value_of("queue");
+
+    tracing::Span::current()
+        .record("remote", tracing::field::debug(remote))
+        .record("prefix", tracing::field::debug(prefix))
+        .record("queue", tracing::field::debug(queue));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+
+    let mut queue = crate::git::queue_or_current(&ctx, queue)?;
+    let remote = QueueRemote::connect(&ctx, remote)?;
+
+    let prefix = remote.review_prefix(prefix)?;
+    let outcomes = remote.push_branches(&mut queue, &prefix)?;
+    if outcomes.is_empty() {
+        println!("No applied patches to push");
+    }
+    for (branch, outcome) in outcomes {
+        match outcome {
+            BranchOutcome::Created => println!("Created {}", branch),
+            BranchOutcome::Updated => println!("Updated {}", branch),
+            BranchOutcome::UpToDate => println!("{} is up to date", branch),
+            BranchOutcome::Deleted => println!("Deleted {}", branch),
+        }
+    }
+
+    Ok(())
+}
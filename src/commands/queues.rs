@@ -1,8 +1,12 @@
-use clap::{ArgMatches, SubCommand};
-use git_queue::queue::Queue;
-use prettytable::{Attr, Cell, Row, Table, color::BRIGHT_GREEN};
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::{naming, queue::Queue, report::QueueReport};
+use prettytable::{color::BRIGHT_GREEN, Attr, Cell, Row, Table};
 
-use crate::{error::Error, App};
+use crate::{
+    error::Error,
+    format::{self, Format},
+    App,
+};
 
 pub(super) fn subcommand() -> App {
     SubCommand::with_name("queues")
@@ -13,31 +17,73 @@ List all available queues, showing individual information about each queue.
 
 The queues will be printed in a table, showing first the name (in green if it is \
 the current queue), followed by the base (if -B/--no-base is not specified).
+
+With -l/--long, each queue is printed in its own paragraph, with its base, \
+number of patches and description. With --format json, the queues are printed \
+as a JSON array, each one with its patches.
+
+Queue names may be hierarchical, like `team/feature`. If <namespace> is given, \
+only the queues in it are listed, e.g. `team` lists `team/feature`.
 ",
         )
         .args(&[
             super::flag("no-base", "B").help("Do not show the base for each queue"),
-            super::flag("no-patches", "P").help("Do not show description of patches for each queue"),
+            super::flag("no-patches", "P")
+                .help("Do not show description of patches for each queue"),
+            super::flag("long", "l").help("Show the description of each queue"),
+            Arg::with_name("namespace")
+                .required(false)
+                .empty_values(false)
+                .help("Only list the queues in this namespace."),
         ])
 }
 
 #[tracing::instrument(skip(args), fields(
     base = tracing::field::Empty,
     patches = tracing::field::Empty,
+    namespace = tracing::field::Empty,
 ))]
 pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
     let base = !args.is_present("no-base");
     let patches = !args.is_present("no-patches");
+    let namespace = args.value_of("namespace");
 
     tracing::Span::current()
         .record("base", &base)
-        .record("patches", &patches);
+        .record("patches", &patches)
+        .record("namespace", tracing::field::debug(namespace));
 
     let ctx = crate::git::current_git_ctx()?;
 
-    let mut queues = Queue::list(&ctx)?;
+    let mut queues = Queue::list(&ctx)?.filter(|q| match (q, namespace) {
+        (Ok(q), Some(namespace)) => {
+            let name = q.name();
+            name == namespace.trim_end_matches('/') || naming::in_namespace(name, namespace)
+        }
+        _ => true,
+    });
+
+    let default = if base || patches {
+        Format::Table
+    } else {
+        Format::Plain
+    };
+    let format = format::from_args(args, default);
 
-    if base || patches {
+    if format == Format::Json {
+        let queues = queues.collect::<Result<Vec<_>, _>>()?;
+        let reports: Vec<_> = queues.iter().map(QueueReport::new).collect();
+        format::print_json(&reports)?;
+    } else if args.is_present("long") {
+        let mut first = true;
+        while let Some(q) = queues.next().transpose()? {
+            if !first {
+                println!();
+            }
+            first = false;
+            print_queue_long(&q);
+        }
+    } else if format == Format::Table {
         let mut titles = vec!["Name"];
         if base {
             titles.push("Base");
@@ -62,12 +108,7 @@ pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
     Ok(())
 }
 
-fn print_queue(
-    q: Queue<'_>,
-    table: &mut Table,
-    base: bool,
-    patches: bool,
-) {
+fn print_queue(q: Queue<'_>, table: &mut Table, base: bool, patches: bool) {
     let mut name_cell = Cell::new(q.name());
     if q.is_current() {
         name_cell.style(Attr::ForegroundColor(BRIGHT_GREEN));
@@ -80,3 +121,21 @@ fn print_queue(
 
     table.add_row(row);
 }
+
+fn print_queue_long(q: &Queue<'_>) {
+    let marker = if q.is_current() { '*' } else { ' ' };
+    println!("{} {}", marker, q.name());
+    println!("  Base: {}", q.base_name());
+    println!("  Patches: {}", q.patches_num());
+
+    if let Some(description) = q.description() {
+        println!();
+        for line in description.lines() {
+            if line.is_empty() {
+                println!();
+            } else {
+                println!("    {}", line);
+            }
+        }
+    }
+}
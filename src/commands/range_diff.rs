@@ -0,0 +1,109 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::{queue::log::QueueState, range_diff, Oid};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("range-diff")
+        .about("Compare two versions of the patch series")
+        .long_about(
+            "\
+Compare two versions of the patch series of the current queue, patch-by-patch, \
+like `git range-diff`. Versions are created with `version bump`, and can be given \
+as `v<n>` or `<n>`.
+
+Without arguments, the last version is compared with the current patches. With \
+a single version, that version is compared with the current patches.
+
+Each patch is shown with its position and commit in both versions, and a marker \
+telling if it is unchanged (`=`), modified (`!`), added (`>`) or removed (`<`). \
+Modified patches are followed by the diff between their old and new text.",
+        )
+        .args(&[
+            Arg::with_name("old")
+                .required(false)
+                .empty_values(false)
+                .help("Old version, defaults to the last one."),
+            Arg::with_name("new")
+                .required(false)
+                .empty_values(false)
+                .help("New version, defaults to the current patches."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    old = tracing::field::Empty,
+    new = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let old = args.value_of("old");
+    let new = args.value_of("new");
+
+    tracing::Span::current()
+        .record("old", tracing::field::debug(old))
+        .record("new", tracing::field::debug(new));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let queue = crate::git::queue_or_current(&ctx, None)?;
+    let state = queue.state();
+
+    let old = match old {
+        Some(old) => find_version(&ctx, state, old)?,
+        None => match state.versions().last() {
+            Some((name, _)) => find_version(&ctx, state, name)?,
+            None => throw!(
+                USAGE,
+                "There are no versions, create one with `version bump`"
+            ),
+        },
+    };
+    let new = match new {
+        Some(new) => find_version(&ctx, state, new)?,
+        None => state.clone(),
+    };
+
+    for cmp in range_diff::range_diff(ctx.repo(), &old, &new)? {
+        let marker = match (cmp.before(), cmp.after()) {
+            (Some(_), Some(_)) if cmp.is_unchanged() => '=',
+            (Some(_), Some(_)) => '!',
+            (None, _) => '>',
+            (_, None) => '<',
+        };
+        println!(
+            "{} {} {} {}",
+            position(cmp.before()),
+            marker,
+            position(cmp.after()),
+            cmp.name()
+        );
+        for line in cmp.interdiff().lines() {
+            println!("    {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+fn find_version(
+    ctx: &git_queue::ctx::Ctx,
+    state: &QueueState,
+    version: &str,
+) -> Result<QueueState, Error> {
+    let name = if version.starts_with('v') {
+        version.to_string()
+    } else {
+        format!("v{}", version)
+    };
+
+    match state.version(ctx.repo(), &name)? {
+        Some(version) => Ok(version),
+        None => throw!(DATAERR, "Version `{}` does not exist", version),
+    }
+}
+
+fn position(patch: Option<(usize, Oid)>) -> String {
+    match patch {
+        Some((idx, oid)) => format!("{:<3} {:.7}", format!("{}:", idx), oid.to_string()),
+        None => "-:  -------".to_string(),
+    }
+}
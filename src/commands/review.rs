@@ -0,0 +1,94 @@
+use clap::{Arg, ArgMatches, SubCommand};
+use git_queue::{
+    remote::QueueRemote,
+    review::{self, ReviewOutcome},
+};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("review")
+        .about("Send the applied patches for review in a forge")
+        .long_about(
+            "\
+Send each applied patch of a queue, by default the current one, for review in \
+a forge, as a stack of reviews where each one is based on the previous. The \
+patches are pushed as review branches first, see `push-branches`.
+
+Reviews are created for new patches, updated for patches that changed since \
+they were last sent, and closed for patches removed from the queue. The review \
+of each patch is recorded in the queue log and shown by `series`. With \
+--refresh, only the status of the reviews is updated.
+
+The forge is configured in `qg.review.*`, see the documentation of the \
+`git_queue::review` module. The remote is given in -r/--remote, or \
+`qg.remote`, or `origin`, and the prefix of the branches in --prefix, or \
+`qg.reviewPrefix`, or `review`.",
+        )
+        .args(&[
+            Arg::with_name("remote")
+                .short("r")
+                .long("remote")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Remote to push the review branches to."),
+            Arg::with_name("prefix")
+                .long("prefix")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Prefix of the review branch names."),
+            Arg::with_name("refresh")
+                .long("refresh")
+                .conflicts_with_all(&["remote", "prefix"])
+                .help("Only update the status of the reviews."),
+            Arg::with_name("queue")
+                .empty_values(false)
+                .help("Queue to send for review, defaults to the current queue."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    remote = tracing::field::Empty,
+    prefix = tracing::field::Empty,
+    queue = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let remote = args.value_of("remote");
+    let prefix = args.value_of("prefix");
+    let queue = args.value_of("queue");
+
+    tracing::Span::current()
+        .record("remote", tracing::field::debug(remote))
+        .record("prefix", tracing::field::debug(prefix))
+        .record("queue", tracing::field::debug(queue));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+
+    let mut queue = crate::git::queue_or_current(&ctx, queue)?;
+    let provider = review::provider(ctx.config())?;
+
+    if args.is_present("refresh") {
+        review::refresh(provider.as_ref(), &mut queue)?;
+        return Ok(());
+    }
+
+    let remote = QueueRemote::connect(&ctx, remote)?;
+    let prefix = remote.review_prefix(prefix)?;
+    let outcomes = review::send(provider.as_ref(), &remote, &mut queue, &prefix)?;
+    if outcomes.is_empty() {
+        println!("No applied patches to review");
+    }
+    for (patch, outcome) in outcomes {
+        let review = queue.state().review(&patch);
+        let url = review.and_then(|r| r.url.as_deref()).unwrap_or_default();
+        match outcome {
+            ReviewOutcome::Created => println!("Created review of {} {}", patch, url),
+            ReviewOutcome::Updated => println!("Updated review of {} {}", patch, url),
+            ReviewOutcome::UpToDate => println!("Review of {} is up to date", patch),
+            ReviewOutcome::Closed => println!("Closed review of {}", patch),
+        }
+    }
+
+    Ok(())
+}
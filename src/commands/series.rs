@@ -0,0 +1,139 @@
+use clap::{Arg, ArgMatches, SubCommand};
+
+use git_queue::report::{PatchReport, PatchState, QueueReport};
+use prettytable::{Cell, Row};
+
+use crate::{
+    error::Error,
+    format::{self, Format},
+    App,
+};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("series")
+        .about("List the patches of a queue")
+        .long_about(
+            "\
+List the patches of a queue, by default the current one. Applied patches are \
+prefixed by `+`, the top patch by `>` and unapplied patches by `-`.
+
+With --show-signature, the signature of each patch commit is verified and \
+reported as good, bad, unknown (when it could not be checked) or unsigned.
+
+Patches sent for review with `review` are followed by the identifier of their \
+review and its status when it was last updated, see `review --refresh`.
+
+With --format table, the patches are printed in a table with their commits, and \
+with --format json, the queue is printed as a JSON object with its patches.",
+        )
+        .args(&[
+            super::show_signature_flag(),
+            Arg::with_name("queue")
+                .required(false)
+                .empty_values(false)
+                .help("Queue to list, defaults to the current queue."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    queue = tracing::field::Empty,
+    show_signature = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let queue = args.value_of("queue");
+    let show_signature = args.is_present("show-signature");
+
+    tracing::Span::current()
+        .record("queue", tracing::field::debug(queue))
+        .record("show_signature", show_signature);
+
+    let ctx = crate::git::current_git_ctx()?;
+    let queue = crate::git::queue_or_current(&ctx, queue)?;
+    let state = queue.state();
+
+    match format::from_args(args, Format::Plain) {
+        Format::Json => {
+            let mut report = QueueReport::new(&queue);
+            if show_signature {
+                for patch in report.patches_mut() {
+                    patch.set_signature(&ctx.verify_commit(patch.commit())?);
+                }
+            }
+            return format::print_json(&report);
+        }
+        Format::Table => {
+            let mut titles = vec!["", "Name", "Commit"];
+            if show_signature {
+                titles.push("Signature");
+            }
+            titles.push("Review");
+
+            let mut table = crate::table::new(titles.into_iter());
+            for patch in PatchReport::all(state) {
+                let marker = match patch.state() {
+                    PatchState::Applied if patch.is_top() => ">",
+                    PatchState::Applied => "+",
+                    PatchState::Unapplied => "-",
+                };
+                let mut row = Row::new(vec![
+                    Cell::new(marker),
+                    Cell::new(patch.name()),
+                    Cell::new(&format!("{:.7}", patch.commit().to_string())),
+                ]);
+                if show_signature {
+                    row.add_cell(Cell::new(&ctx.verify_commit(patch.commit())?.to_string()));
+                }
+                let review = state.review(patch.name()).map(|review| {
+                    let status = review.status.as_deref().unwrap_or("unknown");
+                    format!("#{} ({})", review.id, status)
+                });
+                row.add_cell(Cell::new(review.as_deref().unwrap_or_default()));
+                table.add_row(row);
+            }
+
+            table.printstd();
+            return Ok(());
+        }
+        Format::Plain => {}
+    }
+
+    let applied: Vec<_> = state.applied().collect();
+    // Unapplied patches are kept as a stack, the next one to be pushed is the last.
+    let mut unapplied: Vec<_> = state.unapplied().collect();
+    unapplied.reverse();
+
+    let top = applied.len().checked_sub(1);
+    let patches = applied
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, oid))| (if Some(i) == top { '>' } else { '+' }, name, oid))
+        .chain(unapplied.into_iter().map(|(name, oid)| ('-', name, oid)));
+
+    let width = state
+        .applied()
+        .chain(state.unapplied())
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or_default();
+
+    for (marker, name, oid) in patches {
+        let mut line = format!("{} {}", marker, name);
+        if show_signature {
+            let status = ctx.verify_commit(oid)?;
+            line = format!("{:width$}  {}", line, status, width = width + 2);
+        }
+        if let Some(review) = state.review(name) {
+            let status = review.status.as_deref().unwrap_or("unknown");
+            line = format!(
+                "{:width$}  #{} ({})",
+                line,
+                review.id,
+                status,
+                width = width + 2
+            );
+        }
+        println!("{}", line);
+    }
+
+    Ok(())
+}
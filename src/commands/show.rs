@@ -0,0 +1,93 @@
+use clap::{Arg, ArgMatches, SubCommand};
+
+use git_queue::report::CommitReport;
+
+use crate::{
+    error::Error,
+    format::{self, Format},
+    App,
+};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("show")
+        .about("Show patches of the current queue")
+        .long_about(
+            "\
+Show the given patches of the current queue, by default the top patch. Patches \
+can be given by name, position or range, e.g. `top~1`, `3` or `<from>..<to>`, and \
+prefixed by `<queue>:` to show patches of another queue. Each patch is shown \
+with its commit, author, date and message, followed by its diff against its \
+parent, or its diffstat with --stat.
+
+With --format json, the patches are printed as a JSON array of commits, each one \
+with the files it changes and its diff, unless --stat is given.",
+        )
+        .args(&[
+            Arg::with_name("stat")
+                .long("stat")
+                .takes_value(false)
+                .help("Show the diffstat instead of the diff."),
+            crate::diff::color_arg(),
+            Arg::with_name("patches")
+                .required(false)
+                .multiple(true)
+                .empty_values(false)
+                .help("Patches to show, defaults to the top patch."),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    patches = tracing::field::Empty,
+    stat = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let patches: Vec<_> = args.values_of("patches").into_iter().flatten().collect();
+    let stat = args.is_present("stat");
+
+    tracing::Span::current()
+        .record("patches", tracing::field::debug(&patches))
+        .record("stat", stat);
+
+    let ctx = crate::git::current_git_ctx()?;
+    let color = crate::diff::use_color(&ctx, args);
+
+    let mut oids = Vec::with_capacity(patches.len().max(1));
+    if patches.is_empty() {
+        let queue = crate::git::queue_or_current(&ctx, None)?;
+        match queue.state().applied().last() {
+            Some((_, oid)) => oids.push(oid),
+            None => throw!(USAGE, "There are no applied patches, specify the patches"),
+        }
+    }
+    for patch in patches {
+        let spec = crate::git::patch_spec(patch)?;
+        let queue = crate::git::queue_for_spec(&ctx, Some(&spec))?;
+        let selection = spec
+            .resolve(ctx.repo(), queue.state())
+            .map_err(git_queue::Error::from)?;
+        oids.extend(selection.patches().iter().map(|&(_, oid)| oid));
+    }
+
+    let repo = ctx.repo();
+    if format::from_args(args, Format::Plain) == Format::Json {
+        let mut reports = Vec::with_capacity(oids.len());
+        for oid in oids {
+            let commit = repo.find_commit(oid).map_err(git_queue::Error::from)?;
+            reports.push(CommitReport::new(repo, &commit, !stat)?);
+        }
+        return format::print_json(&reports);
+    }
+
+    for (idx, oid) in oids.into_iter().enumerate() {
+        if idx > 0 {
+            println!();
+        }
+
+        let commit = repo.find_commit(oid).map_err(git_queue::Error::from)?;
+        let diff = git_queue::export::commit_diff(repo, &commit)?;
+        crate::diff::print_commit_header(&commit, color);
+        crate::diff::print_diff(&diff, stat, color)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use clap::{ArgMatches, SubCommand};
+use git_queue::{
+    report::StatusReport,
+    status::{self, FileStatus, Operation},
+};
+
+use crate::{
+    error::Error,
+    format::{self, Format},
+    App,
+};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("status")
+        .about("Show the status of the current queue and the working tree")
+        .long_about(
+            "\
+Show the current queue, its base and how many commits the base branch has that \
+the base of the queue hasn't, the top patch and the number of applied and \
+unapplied patches. Then the Git operation in progress, like a rebase or a \
+cherry-pick, and the changed and conflicted files, like `git status`, except \
+untracked files.
+
+With -s/--short, everything is summarized in a single line, suitable for shell \
+prompts: the queue, the applied and total patches, the top patch, and then \
+`behind:<n>` if the base is behind, the operation in progress, and `conflicts` \
+or `dirty` if there are conflicted or changed files, e.g. \
+`feature 2/3 fix-typo behind:4 dirty`.
+
+With --format json, the status is printed as a JSON object.",
+        )
+        .args(&[super::flag("short", "s").help("Show the status in a single line.")])
+}
+
+#[tracing::instrument(skip(args), fields(short = tracing::field::Empty))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let short = args.is_present("short");
+
+    tracing::Span::current().record("short", short);
+
+    let ctx = crate::git::current_git_ctx()?;
+    let queue = crate::git::queue_or_current(&ctx, None)?;
+
+    let behind = status::behind_base(&queue)?;
+    let operation = Operation::in_progress(&ctx);
+    let files = status::changed_files(&ctx)?;
+
+    if format::from_args(args, Format::Plain) == Format::Json {
+        return format::print_json(&StatusReport::new(&queue, behind, operation, &files));
+    }
+
+    let state = queue.state();
+    let applied = state.applied().count();
+    let unapplied = state.unapplied().count();
+    let top = state.applied().last().map(|(name, _)| name);
+
+    if short {
+        let mut line = format!(
+            "{} {}/{} {}",
+            queue.name(),
+            applied,
+            applied + unapplied,
+            top.unwrap_or("-")
+        );
+        if let Some(behind) = behind.filter(|&n| n > 0) {
+            line.push_str(&format!(" behind:{}", behind));
+        }
+        if let Some(operation) = operation {
+            line.push(' ');
+            line.push_str(operation.name());
+        }
+        if files.iter().any(|f| f.conflicted) {
+            line.push_str(" conflicts");
+        } else if !files.is_empty() {
+            line.push_str(" dirty");
+        }
+        println!("{}", line);
+        return Ok(());
+    }
+
+    println!("On queue {}", queue.name());
+    match behind {
+        Some(0) => println!("Based on {}, up to date", queue.base_name()),
+        Some(behind) => println!(
+            "Based on {}, behind by {} commit{}",
+            queue.base_name(),
+            behind,
+            if behind == 1 { "" } else { "s" }
+        ),
+        None => println!("Based on {}, which doesn't exist", queue.base_name()),
+    }
+    match top {
+        Some(top) => println!("Top patch: {}", top),
+        None => println!("No patches applied"),
+    }
+    println!("Patches: {} applied, {} unapplied", applied, unapplied);
+    if let Some(operation) = operation {
+        println!();
+        println!("In progress: {}", operation.name());
+    }
+
+    print_files("Conflicted files:", files.iter().filter(|f| f.conflicted));
+    print_files(
+        "Staged changes:",
+        files.iter().filter(|f| !f.conflicted && f.staged),
+    );
+    print_files(
+        "Unstaged changes:",
+        files.iter().filter(|f| !f.conflicted && f.modified),
+    );
+
+    Ok(())
+}
+
+fn print_files<'f>(title: &str, files: impl Iterator<Item = &'f FileStatus>) {
+    let mut files = files.peekable();
+    if files.peek().is_none() {
+        return;
+    }
+
+    println!();
+    println!("{}", title);
+    for file in files {
+        println!("    {}", file.path);
+    }
+}
@@ -17,12 +17,14 @@ By default, the queue will be created using the same base used for the current \
 queue, if you are not in a queue, the default value will be the current branch. \
 Both of these can be overwritten passing the desired branch in <branch>.
 
+Queue names may be hierarchical, like `team/feature`, but like Git branches, a \
+queue can't be named like the namespace of another one, e.g. `team`.
+
 Switching queues does not require a clean index and working tree. The \
 operation is aborted however if the operation leads to conflicts.",
         )
         .args(&[
-            super::flag("create", "c")
-                .help("Create a new queue with name given by <queue>."),
+            super::flag("create", "c").help("Create a new queue with name given by <queue>."),
             Arg::with_name("queue")
                 .required(true)
                 .empty_values(false)
@@ -55,6 +57,7 @@ pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
 
 fn switch(queue: &str, create: bool, branch: Option<&str>) -> Result<(), Error> {
     let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
 
     let queue = match Queue::for_queue(&ctx, queue) {
         Ok(Some(queue)) => queue,
@@ -67,7 +70,7 @@ fn switch(queue: &str, create: bool, branch: Option<&str>) -> Result<(), Error>
                 match ctx.find_branch(branch) {
                     Ok(Some(branch)) => branch,
                     Ok(None) => throw!(DATAERR, "Branch {} does not exist", branch),
-                    Err(err) => return Err(err.into())
+                    Err(err) => return Err(err.into()),
                 }
             } else if let Some(branch) = ctx.current_branch()? {
                 branch
@@ -78,7 +81,7 @@ fn switch(queue: &str, create: bool, branch: Option<&str>) -> Result<(), Error>
             // We did just check that the queue didn't exist, so this cannot return Ok(None).
             Queue::initialize(&ctx, queue, base_branch)?.unwrap()
         }
-        Err(err) => return Err(err.into())
+        Err(err) => return Err(err.into()),
     };
 
     queue.switch_to()?;
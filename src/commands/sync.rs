@@ -0,0 +1,154 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use clap::{Arg, ArgGroup, ArgMatches, SubCommand};
+use git_queue::{
+    remote::{PublishOutcome, QueueRemote},
+    sync::{self, PatchConflict, Side, SyncOutcome},
+    Oid,
+};
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("sync")
+        .about("Merge a queue with its published version")
+        .long_about(
+            "\
+Fetch the log of a queue, by default the current one, from a remote and merge it \
+with the local one, then publish the result. Unlike `fetch`, this works when both \
+the local and remote queues have changes.
+
+The logs are merged from their last common entry: the changes made to each patch \
+in only one of them are kept, and the patches changed in both are asked for, \
+unless --ours or --theirs is given. The applied patches are rebased as needed, \
+and the merge is recorded in a log entry following both logs.
+
+The remote is given in -r/--remote, or `qg.remote`, or `origin`.",
+        )
+        .args(&[
+            Arg::with_name("remote")
+                .short("r")
+                .long("remote")
+                .takes_value(true)
+                .empty_values(false)
+                .help("Remote to sync the queue with."),
+            Arg::with_name("ours")
+                .long("ours")
+                .help("Keep the local version of patches changed in both logs."),
+            Arg::with_name("theirs")
+                .long("theirs")
+                .help("Keep the remote version of patches changed in both logs."),
+            Arg::with_name("no-publish")
+                .long("no-publish")
+                .help("Don't publish the queue after merging."),
+            Arg::with_name("queue")
+                .empty_values(false)
+                .help("Queue to sync, defaults to the current queue."),
+        ])
+        .group(ArgGroup::with_name("side").args(&["ours", "theirs"]))
+}
+
+#[tracing::instrument(skip(args), fields(
+    remote = tracing::field::Empty,
+    queue = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let remote = args.value_of("remote");
+    let queue = args.value_of("queue");
+    let side = if args.is_present("ours") {
+        Some(Side::Ours)
+    } else if args.is_present("theirs") {
+        Some(Side::Theirs)
+    } else {
+        None
+    };
+
+    tracing::Span::current()
+        .record("remote", tracing::field::debug(remote))
+        .record("queue", tracing::field::debug(queue));
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+
+    let mut queue = crate::git::queue_or_current(&ctx, queue)?;
+    let remote = QueueRemote::connect(&ctx, remote)?;
+
+    if let Some(theirs) = remote.fetch_log(queue.name())? {
+        // Why the merge was aborted while resolving a conflict, if it was.
+        let mut aborted = None;
+        let outcome = sync::sync(&mut queue, theirs, remote.name(), |conflict| {
+            let answer = match side {
+                Some(side) => return Ok(side),
+                None if std::io::stdin().is_terminal() => ask(conflict),
+                None => Ok(None),
+            };
+            aborted = Some(match answer {
+                Ok(Some(side)) => return Ok(side),
+                Ok(None) => (
+                    exitcode::DATAERR,
+                    format!(
+                        "Patch `{}` changed in both logs, use --ours or --theirs to choose one",
+                        conflict.name()
+                    ),
+                ),
+                Err(err) => (
+                    exitcode::IOERR,
+                    format!("Failed to read the answer: {}", err),
+                ),
+            });
+            Err(git_queue::Error::Diverged(conflict.name().to_string()))
+        });
+        if let Some((code, message)) = aborted {
+            return Err(Error::new(code, anyhow::anyhow!(message)));
+        }
+        let outcome = outcome?;
+
+        match outcome {
+            SyncOutcome::UpToDate | SyncOutcome::Ahead => {}
+            SyncOutcome::FastForwarded => {
+                println!("Updated {} from {}", queue.name(), remote.name())
+            }
+            SyncOutcome::Merged => println!("Merged {} with {}", queue.name(), remote.name()),
+        }
+    }
+
+    if args.is_present("no-publish") {
+        return Ok(());
+    }
+    match remote.publish(&queue)? {
+        PublishOutcome::Published => println!("Published {} to {}", queue.name(), remote.name()),
+        PublishOutcome::UpToDate => println!("{} is up to date", queue.name()),
+    }
+
+    Ok(())
+}
+
+/// Ask which side of a conflicting patch to keep, `None` if there is no answer.
+fn ask(conflict: &PatchConflict) -> std::io::Result<Option<Side>> {
+    let describe = |oid: Option<Oid>| match oid {
+        Some(oid) => format!("{:.7}", oid.to_string()),
+        None => "deleted".to_string(),
+    };
+    let stdin = std::io::stdin();
+    let mut stderr = std::io::stderr();
+    loop {
+        write!(
+            stderr,
+            "Patch `{}` changed in both logs (ours: {}, theirs: {}), keep [o]urs or [t]heirs? ",
+            conflict.name(),
+            describe(conflict.ours()),
+            describe(conflict.theirs()),
+        )?;
+        stderr.flush()?;
+
+        let mut answer = String::new();
+        if stdin.lock().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+        match answer.trim() {
+            "o" | "ours" => return Ok(Some(Side::Ours)),
+            "t" | "theirs" => return Ok(Some(Side::Theirs)),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use clap::{AppSettings, Arg, ArgMatches, SubCommand};
+use git_queue::trailers;
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    let args = [
+        Arg::with_name("key")
+            .required(true)
+            .empty_values(false)
+            .help("Key of the trailer, e.g. `Reviewed-by`."),
+        Arg::with_name("value")
+            .required(true)
+            .empty_values(false)
+            .help("Value of the trailer."),
+        Arg::with_name("patch-range")
+            .required(false)
+            .empty_values(false)
+            .help("Patches to edit, defaults to all applied patches."),
+    ];
+
+    SubCommand::with_name("trailer")
+        .about("Add or remove trailers of patches")
+        .long_about(
+            "\
+Add or remove a trailer, like `Reviewed-by: Name <email>`, in the messages of \
+the applied patches of the current queue, or the ones in <patch-range>, e.g. \
+`top~2..top`. A `<queue>:` prefix edits the patches of another queue.
+
+Trailers are added at the end of the last paragraph of the message if it only \
+has trailers, otherwise in a new paragraph, unless the patch has the same \
+trailer already. The applied patches above an edited one are recreated on top \
+of it.
+
+With `qg.autoSignoff` set to true, new patches and amended patches are signed \
+off with a `Signed-off-by` trailer with the identity of the user.",
+        )
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommands(vec![
+            SubCommand::with_name("add")
+                .about("Add a trailer to patches")
+                .args(&args),
+            SubCommand::with_name("remove")
+                .about("Remove a trailer from patches")
+                .args(&args),
+        ])
+}
+
+#[tracing::instrument(skip(args), fields(
+    action = tracing::field::Empty,
+    key = tracing::field::Empty,
+    value = tracing::field::Empty,
+    range = tracing::field::Empty,
+))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let (action, args) = match args.subcommand() {
+        (action, Some(args)) => (action, args),
+        _ => throw!(USAGE, "Specify whether to add or remove the trailer"),
+    };
+    let key = args.value_of("key").unwrap();
+    let value = args.value_of("value").unwrap();
+    let range = args.value_of("patch-range");
+
+    tracing::Span::current()
+        .record("action", action)
+        .record("key", key)
+        .record("value", value)
+        .record("range", tracing::field::debug(range));
+
+    if !trailers::is_valid_key(key) {
+        throw!(
+            DATAERR,
+            "Invalid trailer key `{}`, it must only have letters, digits and `-`",
+            key
+        );
+    }
+    if value.contains('\n') {
+        throw!(DATAERR, "Trailer values can't have multiple lines");
+    }
+
+    let ctx = crate::git::current_git_ctx()?;
+    let _lock = ctx.lock()?;
+    let spec = range.map(crate::git::patch_spec).transpose()?;
+    let mut queue = crate::git::queue_for_spec(&ctx, spec.as_ref())?;
+
+    let selected: Vec<String> = match &spec {
+        Some(spec) => spec
+            .resolve(ctx.repo(), queue.state())
+            .map_err(git_queue::Error::from)?
+            .patches()
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+        None => queue
+            .state()
+            .applied()
+            .map(|(name, _)| name.to_string())
+            .collect(),
+    };
+    if selected.is_empty() {
+        throw!(DATAERR, "No applied patches to edit");
+    }
+
+    let message = format!("trailer {} {}", action, key);
+    let edited = queue.reword_patches(message, |name, message| {
+        if !selected.iter().any(|s| s == name) {
+            return None;
+        }
+        let edited = match action {
+            "add" => trailers::add(message, key, value),
+            _ => trailers::remove(message, key, Some(value)),
+        };
+        Some(edited).filter(|edited| edited != message)
+    })?;
+
+    if edited.is_empty() {
+        println!("No patches changed");
+    }
+    for patch in edited {
+        println!("Edited {}", patch);
+    }
+
+    Ok(())
+}
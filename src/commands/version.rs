@@ -0,0 +1,66 @@
+use clap::{ArgMatches, SubCommand};
+use git_queue::queue::log::QueueState;
+
+use crate::{error::Error, App};
+
+pub(super) fn subcommand() -> App {
+    SubCommand::with_name("version")
+        .about("List or create versions of the patch series")
+        .long_about(
+            "\
+List the versions of the patch series of the current queue, i.e. snapshots of \
+its patches sent for review, with the number of patches in each one.
+
+`version bump` snapshots the current patches as the next version, named `v1`, \
+`v2` and so on. The snapshots are stored in the queue log, and can be compared \
+with `range-diff`.",
+        )
+        .subcommand(
+            SubCommand::with_name("bump")
+                .about("Snapshot the current patches as the next version of the series"),
+        )
+}
+
+#[tracing::instrument(skip(args), fields(bump = tracing::field::Empty))]
+pub(super) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let bump = args.subcommand_matches("bump").is_some();
+
+    tracing::Span::current().record("bump", bump);
+
+    let ctx = crate::git::current_git_ctx()?;
+
+    if bump {
+        let _lock = ctx.lock()?;
+        let mut queue = crate::git::queue_or_current(&ctx, None)?;
+        let name = queue.bump_version()?;
+        println!("Created version {}", name);
+        return Ok(());
+    }
+
+    let queue = crate::git::queue_or_current(&ctx, None)?;
+    let state = queue.state();
+    for (name, _) in state.versions() {
+        let version = match state.version(ctx.repo(), name)? {
+            Some(version) => version,
+            None => unreachable!("version {} listed by the state", name),
+        };
+        println!("{} {}", name, patches_summary(&version));
+    }
+
+    Ok(())
+}
+
+fn patches_summary(state: &QueueState) -> String {
+    let (applied, unapplied) = (state.applied().count(), state.unapplied().count());
+    let plural = |n| if n == 1 { "" } else { "es" };
+    if unapplied == 0 {
+        format!("{} patch{}", applied, plural(applied))
+    } else {
+        format!(
+            "{} patch{}, {} unapplied",
+            applied + unapplied,
+            plural(applied + unapplied),
+            unapplied
+        )
+    }
+}
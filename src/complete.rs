@@ -0,0 +1,439 @@
+use clap::{AppSettings, Arg, ArgMatches, Shell, SubCommand};
+use git_queue::{ctx::Ctx, queue::Queue};
+
+use crate::{error::Error, App};
+
+pub(crate) const COMPLETE: &str = "__complete";
+
+/// What a command argument is completed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Queue,
+    /// Patches of the current queue, or of the queue before a `:`, and ranges of
+    /// them.
+    Patch,
+}
+
+/// The arguments of a command completed dynamically.
+struct Completion {
+    command: &'static [&'static str],
+    /// The kind of each positional argument, the last one is repeated if
+    /// `multiple` is set.
+    positionals: &'static [Option<Kind>],
+    multiple: bool,
+    /// Options whose value is a patch range.
+    patch_options: &'static [&'static str],
+}
+
+const fn queue(command: &'static [&'static str]) -> Completion {
+    Completion {
+        command,
+        positionals: &[Some(Kind::Queue)],
+        multiple: false,
+        patch_options: &[],
+    }
+}
+
+const fn patch(command: &'static [&'static str]) -> Completion {
+    Completion {
+        command,
+        positionals: &[Some(Kind::Patch)],
+        multiple: false,
+        patch_options: &[],
+    }
+}
+
+const COMPLETIONS: &[Completion] = &[
+    queue(&["close"]),
+    queue(&["describe"]),
+    queue(&["fetch"]),
+    queue(&["log"]),
+    queue(&["publish"]),
+    queue(&["push-branches"]),
+    queue(&["queues"]),
+    queue(&["review"]),
+    queue(&["series"]),
+    queue(&["switch"]),
+    queue(&["sync"]),
+    Completion {
+        command: &["diff"],
+        positionals: &[Some(Kind::Patch)],
+        multiple: false,
+        patch_options: &["-r", "--range"],
+    },
+    patch(&["export"]),
+    patch(&["mail"]),
+    Completion {
+        command: &["show"],
+        positionals: &[Some(Kind::Patch)],
+        multiple: true,
+        patch_options: &[],
+    },
+    Completion {
+        command: &["trailer", "add"],
+        positionals: &[None, None, Some(Kind::Patch)],
+        multiple: false,
+        patch_options: &[],
+    },
+    Completion {
+        command: &["trailer", "remove"],
+        positionals: &[None, None, Some(Kind::Patch)],
+        multiple: false,
+        patch_options: &[],
+    },
+];
+
+pub(crate) fn subcommand() -> App {
+    SubCommand::with_name(COMPLETE)
+        .about("Complete queue and patch names, for the shell completions.")
+        .setting(AppSettings::Hidden)
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name("words")
+                .multiple(true)
+                .allow_hyphen_values(true)
+                .help("The command line, the last word is the one to complete."),
+        )
+}
+
+/// Print the completions of the last word of a command line, one per line.
+///
+/// Nothing is printed if the word isn't a queue or patch argument, so the
+/// scripts fall back to the static completions.
+pub(crate) fn execute(args: &ArgMatches<'static>) -> Result<(), Error> {
+    let words: Vec<_> = args.values_of("words").into_iter().flatten().collect();
+    // The first word is the program.
+    let (current, before) = match words.split_first().and_then(|(_, w)| w.split_last()) {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    let kind = match argument_kind(before, current) {
+        Some(kind) => kind,
+        None => return Ok(()),
+    };
+    let ctx = match Ctx::current()? {
+        Some(ctx) => ctx,
+        None => return Ok(()),
+    };
+
+    let candidates = match kind {
+        Kind::Queue => queue_names(&ctx)?,
+        Kind::Patch => patch_names(&ctx, current)?,
+    };
+    for candidate in candidates.iter().filter(|c| c.starts_with(current)) {
+        println!("{}", candidate);
+    }
+
+    Ok(())
+}
+
+/// The kind of the argument being completed, after the given words.
+fn argument_kind(before: &[&str], current: &str) -> Option<Kind> {
+    let mut command: Vec<&str> = Vec::new();
+    let mut positional = 0;
+    let mut option_value = None;
+    let mut only_positionals = false;
+    for &word in before {
+        if option_value.take().is_some() {
+            continue;
+        }
+        if !only_positionals && word == "--" {
+            only_positionals = true;
+        } else if !only_positionals && word.starts_with('-') && word.len() > 1 {
+            if !word.contains('=') && takes_value(&command, word) {
+                option_value = Some(word);
+            }
+        } else if command.is_empty() || positional == 0 && is_subcommand(&command, word) {
+            command.push(word);
+        } else {
+            positional += 1;
+        }
+    }
+
+    let completion = COMPLETIONS
+        .iter()
+        .find(|c| c.command == command.as_slice())?;
+    if let Some(option) = option_value {
+        return completion
+            .patch_options
+            .contains(&option)
+            .then_some(Kind::Patch);
+    }
+    if !only_positionals && current.starts_with('-') {
+        return None;
+    }
+
+    let positionals = completion.positionals;
+    match positionals.get(positional) {
+        Some(&kind) => kind,
+        None if completion.multiple => *positionals.last()?,
+        None => None,
+    }
+}
+
+/// Is the word a subcommand of the given command, e.g. `add` of `trailer`?
+fn is_subcommand(command: &[&str], word: &str) -> bool {
+    COMPLETIONS
+        .iter()
+        .any(|c| c.command.starts_with(command) && c.command.get(command.len()) == Some(&word))
+}
+
+/// Does the option of the given command take a value?
+///
+/// Clap knows, so ask it to parse the option alone: if it takes a value, it
+/// complains the value is missing.
+fn takes_value(command: &[&str], option: &str) -> bool {
+    if matches!(option, "-h" | "--help" | "-V" | "--version") {
+        return false;
+    }
+
+    let argv = std::iter::once(clap::crate_name!())
+        .chain(command.iter().copied())
+        .chain(std::iter::once(option));
+    match crate::build_app().get_matches_from_safe(argv) {
+        Err(err) => err.kind == clap::ErrorKind::EmptyValue,
+        Ok(_) => false,
+    }
+}
+
+fn queue_names(ctx: &Ctx) -> Result<Vec<String>, Error> {
+    let mut names = Vec::new();
+    for queue in Queue::list(ctx)? {
+        names.push(queue?.name().to_string());
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+/// The patch names completing a patch spec, with the queue and the start of the
+/// range of the spec, if any.
+fn patch_names(ctx: &Ctx, current: &str) -> Result<Vec<String>, Error> {
+    let (queue, rest) = match current.split_once(':') {
+        Some((queue, rest)) => (Some(queue), rest),
+        None => (None, current),
+    };
+    let start = rest.rfind("..").map_or(0, |idx| idx + 2);
+    let prefix = &current[..current.len() - rest.len() + start];
+
+    let queue = match queue {
+        Some(name) => Queue::for_queue(ctx, name)?,
+        None => Queue::current(ctx)?,
+    };
+    let state = match &queue {
+        Some(queue) => queue.state(),
+        None => return Ok(Vec::new()),
+    };
+
+    let applied = state.applied();
+    // Unapplied patches are kept as a stack, the next one to be pushed is the last.
+    let mut unapplied: Vec<_> = state.unapplied().collect();
+    unapplied.reverse();
+
+    Ok(applied
+        .chain(unapplied)
+        .map(|(name, _)| format!("{}{}", prefix, name))
+        .collect())
+}
+
+/// The completion script for a shell, extending the static one generated by clap
+/// to call `__complete` for queue and patch arguments.
+pub(crate) fn script(shell: Shell, generated: String) -> String {
+    let bin = clap::crate_name!();
+    match shell {
+        Shell::Bash => {
+            let register = format!("complete -F _{0} -o bashdefault -o default {0}", bin);
+            let dynamic = format!(
+                r#"_{0}_dynamic() {{
+    local line="${{COMP_LINE:0:COMP_POINT}}"
+    local -a words candidates
+    read -ra words <<< "$line"
+    if [[ -z "$line" || "$line" == *[[:space:]] ]]; then
+        words+=("")
+    fi
+
+    mapfile -t candidates < <({0} {1} -- "${{words[@]}}" 2>/dev/null)
+    if [[ ${{#candidates[@]}} -eq 0 ]]; then
+        _{0} "$@"
+        return
+    fi
+
+    # Bash splits words at colons, only the part after the last one is completed.
+    local current="${{words[${{#words[@]}}-1]}}"
+    if [[ "$current" == *:* && "$COMP_WORDBREAKS" == *:* ]]; then
+        candidates=("${{candidates[@]#"${{current%:*}}:"}}")
+    fi
+    COMPREPLY=("${{candidates[@]}}")
+}}
+
+complete -F _{0}_dynamic -o bashdefault -o default {0}"#,
+                bin, COMPLETE
+            );
+            generated.replace(&register, &dynamic)
+        }
+        Shell::Zsh => {
+            let call = format!("_{} \"$@\"", bin);
+            let static_fn = generated.replacen(
+                &format!("\n_{}() {{", bin),
+                &format!("\n_{}_static() {{", bin),
+                1,
+            );
+            let dynamic = format!(
+                r#"_{0}() {{
+    local -a candidates
+    candidates=("${{(@f)$({0} {1} -- "${{(@)words[1,CURRENT]}}" 2>/dev/null)}}")
+    candidates=(${{candidates:#}})
+    if (( ${{#candidates}} )); then
+        compadd -a candidates
+    else
+        _{0}_static "$@"
+    fi
+}}
+
+{2}"#,
+                bin, COMPLETE, call
+            );
+            match static_fn.rfind(&call) {
+                Some(idx) => format!(
+                    "{}{}{}",
+                    &static_fn[..idx],
+                    dynamic,
+                    &static_fn[idx + call.len()..]
+                ),
+                None => static_fn,
+            }
+        }
+        Shell::Fish => {
+            let mut commands: Vec<_> = COMPLETIONS.iter().map(|c| c.command[0]).collect();
+            commands.dedup();
+            format!(
+                r#"{generated}
+function __{0}_complete
+    set -l current (commandline -ct)
+    {0} {1} -- (commandline -opc) "$current" 2>/dev/null
+end
+complete -c {0} -n "__fish_seen_subcommand_from {2}" -f -a "(__{0}_complete)"
+"#,
+                bin,
+                COMPLETE,
+                commands.join(" "),
+                generated = generated,
+            )
+        }
+        _ => generated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use git2::{Repository, Signature};
+
+    #[test]
+    fn argument_kinds() {
+        let kind = |line: &[&str]| {
+            let (current, before) = line.split_last().unwrap();
+            argument_kind(before, current)
+        };
+
+        assert_eq!(kind(&["switch", ""]), Some(Kind::Queue));
+        assert_eq!(kind(&["switch", "q", ""]), None);
+        assert_eq!(kind(&["switch", "-"]), None);
+        assert_eq!(kind(&["switch", "--", "-q"]), Some(Kind::Queue));
+        assert_eq!(kind(&["--format", "json", "queues", ""]), Some(Kind::Queue));
+        assert_eq!(kind(&["show", "one", "t"]), Some(Kind::Patch));
+        assert_eq!(kind(&["export", "one", ""]), None);
+        assert_eq!(kind(&["diff", "-r", ""]), Some(Kind::Patch));
+        assert_eq!(kind(&["diff", "--range=one..two", ""]), Some(Kind::Patch));
+        assert_eq!(kind(&["trailer", "add", "Acked-by", ""]), None);
+        assert_eq!(
+            kind(&["trailer", "add", "Acked-by", "A", ""]),
+            Some(Kind::Patch)
+        );
+        assert_eq!(kind(&["trailer", ""]), None);
+        assert_eq!(kind(&["sw"]), None);
+        assert_eq!(kind(&["unknown", ""]), None);
+    }
+
+    #[test]
+    fn queue_and_patch_names() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        {
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "A").unwrap();
+            config.set_str("user.email", "a@example.com").unwrap();
+        }
+        let ctx = Ctx::from_repo(repo).unwrap();
+        let repo = ctx.repo();
+        let author = Signature::now("A", "a@example.com").unwrap();
+        let tree = repo.treebuilder(None).unwrap().write().unwrap();
+        let tree = repo.find_tree(tree).unwrap();
+        let base = repo
+            .commit(None, &author, &author, "init", &tree, &[])
+            .unwrap();
+        let base = repo.find_commit(base).unwrap();
+        let branch = |name| repo.branch(name, &base, false).unwrap();
+
+        Queue::initialize(&ctx, "b", branch("other"))
+            .unwrap()
+            .unwrap();
+        let mut queue = Queue::initialize(&ctx, "a", branch("main"))
+            .unwrap()
+            .unwrap();
+        for name in ["one", "two", "three"] {
+            queue.new_patch(name, &author, name, &tree).unwrap();
+        }
+        let mut patches: Vec<_> = queue
+            .state()
+            .applied()
+            .map(|(name, oid)| (name.to_string(), oid))
+            .collect();
+        let unapplied = patches.split_off(1);
+        let base = queue.state().base();
+        queue
+            .replace_stack("pop".to_string(), base, patches, unapplied, |_| {})
+            .unwrap();
+        queue.switch_to().unwrap();
+
+        assert_eq!(queue_names(&ctx).unwrap(), ["a", "b"]);
+        // Unapplied patches come after the applied ones, in push order.
+        assert_eq!(patch_names(&ctx, "t").unwrap(), ["one", "two", "three"]);
+        assert_eq!(
+            patch_names(&ctx, "one..t").unwrap(),
+            ["one..one", "one..two", "one..three"]
+        );
+        assert_eq!(
+            patch_names(&ctx, "a:one..").unwrap(),
+            ["a:one..one", "a:one..two", "a:one..three"]
+        );
+        assert!(patch_names(&ctx, "b:").unwrap().is_empty());
+        assert!(patch_names(&ctx, "missing:").unwrap().is_empty());
+    }
+
+    #[test]
+    fn completion_scripts() {
+        let script = |shell| {
+            let mut generated = Vec::new();
+            crate::build_app().gen_completions_to("qg", shell, &mut generated);
+            script(shell, String::from_utf8(generated).unwrap())
+        };
+
+        let bash = script(Shell::Bash);
+        assert!(bash.contains("qg __complete -- \"${words[@]}\""));
+        assert!(bash.contains("\ncomplete -F _qg_dynamic -o bashdefault -o default qg\n"));
+        assert!(!bash.contains("complete -F _qg "));
+
+        let zsh = script(Shell::Zsh);
+        assert!(zsh.contains("\n_qg_static() {"));
+        assert!(zsh.contains("qg __complete -- \"${(@)words[1,CURRENT]}\""));
+        assert!(zsh.contains("_qg_static \"$@\""));
+
+        let fish = script(Shell::Fish);
+        let subcommands = "close describe fetch log publish push-branches queues review \
+            series switch sync diff export mail show trailer";
+        let condition = format!("__fish_seen_subcommand_from {}", subcommands);
+        assert!(fish.contains(&condition), "{}", fish);
+    }
+}
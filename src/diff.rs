@@ -0,0 +1,186 @@
+use std::io::IsTerminal;
+
+use clap::{Arg, ArgMatches};
+use git2::{DiffFormat, DiffStatsFormat};
+use git_queue::ctx::Ctx;
+
+use crate::error::Error;
+
+const RESET: &str = "\x1b[m";
+pub const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+
+/// The `--color` argument, shared by commands printing diffs.
+pub fn color_arg() -> Arg<'static, 'static> {
+    Arg::with_name("color")
+        .long("color")
+        .takes_value(true)
+        .value_name("when")
+        .possible_values(&["always", "never", "auto"])
+        .help("When to colour the output, defaults to `color.diff` or `color.ui`.")
+}
+
+/// Should the output be coloured?
+///
+/// Like Git, the `--color` argument overrides `color.diff`, which overrides
+/// `color.ui`. If none of them is given, the output is coloured if the standard
+/// output is a terminal.
+pub fn use_color(ctx: &Ctx, args: &ArgMatches<'static>) -> bool {
+    let config = ctx.config();
+    let when = args
+        .value_of("color")
+        .map(str::to_string)
+        .or_else(|| config.get_string("color.diff").ok())
+        .or_else(|| config.get_string("color.ui").ok());
+
+    match when.as_deref() {
+        Some("always") => true,
+        Some("never" | "false") => false,
+        _ => std::io::stdout().is_terminal(),
+    }
+}
+
+/// Paint the text with the given style, if colours are enabled.
+pub fn paint(color: bool, style: &str, text: &str) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Print the header of a commit, as `git show` does.
+pub fn print_commit_header(commit: &git2::Commit<'_>, color: bool) {
+    println!(
+        "{}",
+        paint(color, YELLOW, &format!("commit {}", commit.id()))
+    );
+    let author = commit.author();
+    println!(
+        "Author: {} <{}>",
+        author.name().unwrap_or_default(),
+        author.email().unwrap_or_default()
+    );
+    println!("Date:   {}", git_queue::rfc2822::format_date(author.when()));
+    println!();
+    for line in String::from_utf8_lossy(commit.message_bytes()).lines() {
+        println!("    {}", line);
+    }
+    println!();
+}
+
+/// Print a diff as a patch, or as a diffstat if `stat` is set.
+pub fn print_diff(diff: &git2::Diff<'_>, stat: bool, color: bool) -> Result<(), Error> {
+    print!("{}", render_diff(diff, stat, color)?);
+    Ok(())
+}
+
+/// Print a diff of texts, like the interdiff of a patch.
+pub fn print_interdiff(interdiff: &str, color: bool) {
+    print!("{}", render_interdiff(interdiff, color));
+}
+
+fn render_diff(diff: &git2::Diff<'_>, stat: bool, color: bool) -> Result<String, Error> {
+    if stat {
+        let stats = diff
+            .stats()
+            .and_then(|s| s.to_buf(DiffStatsFormat::FULL | DiffStatsFormat::INCLUDE_SUMMARY, 80))
+            .map_err(git_queue::Error::from)?;
+        return Ok(String::from_utf8_lossy(&stats).into_owned());
+    }
+
+    let mut rendered = String::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        let (prefix, style) = match line.origin() {
+            'F' => ("", BOLD),
+            'H' => ("", CYAN),
+            '+' => ("+", GREEN),
+            '-' => ("-", RED),
+            ' ' => (" ", ""),
+            _ => ("", ""),
+        };
+
+        let content = String::from_utf8_lossy(line.content());
+        for text in content.lines() {
+            let text = format!("{}{}", prefix, text);
+            if style.is_empty() {
+                rendered.push_str(&text);
+            } else {
+                rendered.push_str(&paint(color, style, &text));
+            }
+            rendered.push('\n');
+        }
+        true
+    })
+    .map_err(git_queue::Error::from)?;
+
+    Ok(rendered)
+}
+
+fn render_interdiff(interdiff: &str, color: bool) -> String {
+    let mut rendered = String::with_capacity(interdiff.len());
+    for line in interdiff.lines() {
+        let style = match line.as_bytes().first() {
+            Some(b'@') => CYAN,
+            Some(b'+') => GREEN,
+            Some(b'-') => RED,
+            _ => "",
+        };
+        if style.is_empty() {
+            rendered.push_str(line);
+        } else {
+            rendered.push_str(&paint(color, style, line));
+        }
+        rendered.push('\n');
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATCH: &str = "\
+diff --git a/file b/file
+index 1111111..2222222 100644
+--- a/file
++++ b/file
+@@ -1,2 +1,2 @@
+ same
+-old
++new
+";
+
+    #[test]
+    fn render_patches() {
+        let diff = git2::Diff::from_buffer(PATCH.as_bytes()).unwrap();
+        assert_eq!(render_diff(&diff, false, false).unwrap(), PATCH);
+
+        let colored = render_diff(&diff, false, true).unwrap();
+        let lines: Vec<_> = colored.lines().collect();
+        assert_eq!(lines[0], "\x1b[1mdiff --git a/file b/file\x1b[m");
+        assert_eq!(lines[3], "\x1b[1m+++ b/file\x1b[m");
+        assert_eq!(lines[4], "\x1b[36m@@ -1,2 +1,2 @@\x1b[m");
+        assert_eq!(lines[5], " same");
+        assert_eq!(lines[6], "\x1b[31m-old\x1b[m");
+        assert_eq!(lines[7], "\x1b[32m+new\x1b[m");
+
+        assert_eq!(
+            render_diff(&diff, true, true).unwrap(),
+            " file | 2 +-\n 1 file changed, 1 insertion(+), 1 deletion(-)\n"
+        );
+    }
+
+    #[test]
+    fn render_interdiffs() {
+        let interdiff = "@@ -1,2 +1,2 @@\n context\n-old\n+new\n";
+        assert_eq!(render_interdiff(interdiff, false), interdiff);
+        assert_eq!(
+            render_interdiff(interdiff, true),
+            "\x1b[36m@@ -1,2 +1,2 @@\x1b[m\n context\n\x1b[31m-old\x1b[m\n\x1b[32m+new\x1b[m\n"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+use std::process::Command;
+
+use git_queue::ctx::Ctx;
+
+use crate::error::Error;
+
+/// Let the user edit a text in their editor.
+///
+/// The editor is chosen like Git does: `GIT_EDITOR`, `core.editor`, `VISUAL`,
+/// `EDITOR` and finally `vi`. The text is written to `<git dir>/qg/<file_name>`,
+/// and lines starting with `#` are removed from the edited text.
+pub fn edit(ctx: &Ctx, file_name: &str, text: &str) -> Result<String, Error> {
+    let editor = std::env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| ctx.config().get_string("core.editor").ok())
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .unwrap_or_else(|| "vi".to_string());
+
+    let dir = ctx.repo().path().join("qg");
+    let path = dir.join(file_name);
+    std::fs::create_dir_all(&dir)
+        .and_then(|()| std::fs::write(&path, text))
+        .map_err(git_queue::Error::from)?;
+
+    // The editor may have arguments, so it is run by the shell, like Git does.
+    let status = Command::new("sh")
+        .args(["-c", &format!("{} \"$@\"", editor), &editor])
+        .arg(&path)
+        .status();
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(_) => throw!(SOFTWARE, "The editor `{}` exited with an error", editor),
+        Err(err) => throw!(OSERR, "Failed to run the editor `{}`: {}", editor, err),
+    }
+
+    let edited = std::fs::read_to_string(&path).map_err(git_queue::Error::from)?;
+    let mut text = String::with_capacity(edited.len());
+    for line in edited.lines().filter(|l| !l.starts_with('#')) {
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    Ok(text)
+}
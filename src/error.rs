@@ -37,8 +37,14 @@ impl From<git_queue::Error> for Error {
         use git_queue::Error::*;
         let code = match &err {
             NotInRepository | NotInitialized => exitcode::USAGE,
-            Inconsistency(_) | InvalidName | NonUtf8 => exitcode::DATAERR,
-            AlreadyExists(_) => exitcode::CANTCREAT,
+            Inconsistency(_) | InvalidName(_) | NonUtf8 | ApplyFailed(_) | Spec(_) => {
+                exitcode::DATAERR
+            }
+            AlreadyExists(_) | NestedQueue(..) => exitcode::CANTCREAT,
+            Locked(_) | Modified(_) => exitcode::TEMPFAIL,
+            Diverged(_) | NotPublished(_) | BranchChanged(_) => exitcode::DATAERR,
+            Signing(_) | Mail(_) | Review(_) => exitcode::UNAVAILABLE,
+            Config(_) => exitcode::CONFIG,
             Git(err) => match err.class() {
                 ErrorClass::Reference if err.code() == ErrorCode::UnbornBranch => {
                     return Error::new(
@@ -46,6 +52,8 @@ impl From<git_queue::Error> for Error {
                         anyhow::anyhow!("The current branch is not initialized"),
                     )
                 }
+                ErrorClass::Checkout => exitcode::DATAERR,
+                ErrorClass::Config => exitcode::CONFIG,
                 ErrorClass::Os => exitcode::OSERR,
                 ErrorClass::Filesystem | ErrorClass::Net => exitcode::IOERR,
                 ErrorClass::NoMemory => exitcode::TEMPFAIL,
@@ -60,7 +68,7 @@ impl From<git_queue::Error> for Error {
 #[macro_export]
 macro_rules! throw {
     ($code: ident, $($t: tt)+) => {
-        return Err($crate::error::Error::new(::exitcode::$code, ::anyhow::anyhow!($($t)+)));
+        return Err($crate::error::Error::new(::exitcode::$code, ::anyhow::anyhow!($($t)+)))
     }
 }
 
@@ -0,0 +1,56 @@
+use std::io::Write;
+
+use clap::{Arg, ArgMatches};
+
+use crate::error::Error;
+
+/// How listing commands print their output, from the global `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Stable JSON, see [`git_queue::report`].
+    Json,
+    /// An aligned table with a header.
+    Table,
+    /// Plain lines, for humans and simple scripts.
+    Plain,
+}
+
+/// The global `--format` argument.
+pub fn arg() -> Arg<'static, 'static> {
+    Arg::with_name("format")
+        .long("format")
+        .global(true)
+        .takes_value(true)
+        .value_name("format")
+        .possible_values(&["json", "table", "plain"])
+        .help("Output format of listing commands, defaults to the usual one of each command.")
+        .long_help(
+            "\
+Output format of listing commands: `queues`, `series`, `log`, `show` and \
+`status`. Each command has its usual format, used when this is not given.
+
+With `json`, a single JSON document is printed, whose fields are stable and \
+documented in the `report` module of git-queue. Object IDs are full hexadecimal \
+strings and absent values are `null`.",
+        )
+}
+
+/// The format requested in the arguments, or `default`.
+pub fn from_args(args: &ArgMatches<'static>, default: Format) -> Format {
+    match args.value_of("format") {
+        Some("json") => Format::Json,
+        Some("table") => Format::Table,
+        Some("plain") => Format::Plain,
+        _ => default,
+    }
+}
+
+/// Print a value as JSON to the standard output.
+pub fn print_json(value: &impl serde::Serialize) -> Result<(), Error> {
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer_pretty(&mut stdout, value)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| writeln!(stdout).map_err(anyhow::Error::from))
+        .map_err(|err| Error::new(exitcode::IOERR, err))
+}
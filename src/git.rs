@@ -1,6 +1,13 @@
-use git_queue::ctx::Ctx;
+use git_queue::{
+    ctx::Ctx,
+    queue::{log::QueueState, Queue},
+    spec::PatchSpec,
+    Oid,
+};
 
-pub fn current_git_ctx() -> Result<Ctx, crate::error::Error> {
+use crate::error::Error;
+
+pub fn current_git_ctx() -> Result<Ctx, Error> {
     if let Some(ctx) = Ctx::current()? {
         Ok(ctx)
     } else {
@@ -14,3 +21,47 @@ pub fn current_git_ctx() -> Result<Ctx, crate::error::Error> {
         )
     }
 }
+
+/// Get the queue with the given name or, if no name is given, the current queue.
+pub fn queue_or_current<'c>(ctx: &'c Ctx, name: Option<&str>) -> Result<Queue<'c>, Error> {
+    match name {
+        Some(name) => match Queue::for_queue(ctx, name)? {
+            Some(queue) => Ok(queue),
+            None => throw!(DATAERR, "Queue `{}` does not exist", name),
+        },
+        None => match Queue::current(ctx)? {
+            Some(queue) => Ok(queue),
+            None => throw!(USAGE, "Not in a queue, switch to one or specify the queue"),
+        },
+    }
+}
+
+/// Parse a patch spec given in the command line, see `git_queue::spec`.
+pub fn patch_spec(spec: &str) -> Result<PatchSpec<'_>, Error> {
+    Ok(PatchSpec::parse(spec).map_err(git_queue::Error::from)?)
+}
+
+/// Get the queue of a patch spec or, if it has none, the current queue.
+pub fn queue_for_spec<'c>(ctx: &'c Ctx, spec: Option<&PatchSpec<'_>>) -> Result<Queue<'c>, Error> {
+    queue_or_current(ctx, spec.and_then(PatchSpec::queue))
+}
+
+/// Resolve a spec that must select applied patches, all of them if no spec is given.
+pub fn applied_patches<'s>(
+    ctx: &Ctx,
+    state: &'s QueueState,
+    spec: Option<&PatchSpec<'_>>,
+) -> Result<Vec<(&'s str, Oid)>, Error> {
+    let spec = match spec {
+        Some(spec) => spec,
+        None => return Ok(state.applied().collect()),
+    };
+
+    let selection = spec
+        .resolve(ctx.repo(), state)
+        .map_err(git_queue::Error::from)?;
+    if !selection.is_applied() {
+        throw!(DATAERR, "Patches in `{}` are not applied", spec);
+    }
+    Ok(selection.into_patches())
+}
@@ -7,6 +7,10 @@ use clap::{Arg, Shell, SubCommand};
 #[macro_use]
 pub(crate) mod error;
 mod commands;
+mod complete;
+mod diff;
+mod editor;
+mod format;
 mod git;
 mod table;
 
@@ -16,6 +20,7 @@ const GENERATE_COMPLETIONS: &'static str = "generate-completions";
 
 fn build_app() -> App {
     clap::app_from_crate!()
+        .arg(crate::format::arg())
         .subcommands(crate::commands::all())
         .subcommand(
             SubCommand::with_name(GENERATE_COMPLETIONS)
@@ -30,7 +35,10 @@ fn build_app() -> App {
                 .long_about(
                     "Generate shell completions for a specific shell.
 
-The completions are written to the standard output, redirect to a file to persist it.",
+The completions are written to the standard output, redirect to a file to persist it.
+
+In Bash, Zsh and Fish, queue and patch names are completed too, by calling \
+`qg __complete` with the command line.",
                 ),
         )
 }
@@ -42,7 +50,11 @@ fn generate_completions(matches: &clap::ArgMatches<'_>) {
         .parse()
         .expect("Invalid value for `shell` argument");
 
-    build_app().gen_completions_to(clap::crate_name!(), shell, &mut std::io::stdout());
+    let mut generated = Vec::new();
+    build_app().gen_completions_to(clap::crate_name!(), shell, &mut generated);
+    let generated = String::from_utf8(generated).expect("completions are valid UTF-8");
+
+    print!("{}", crate::complete::script(shell, generated));
 }
 
 fn init_logging() {
@@ -55,7 +67,9 @@ fn init_logging() {
 fn main() {
     human_panic::setup_panic!();
     init_logging();
-    let app = build_app();
+    // Not in `build_app`, clap can't generate completions for a subcommand named
+    // like this, and it is hidden anyway.
+    let app = build_app().subcommand(crate::complete::subcommand());
     let matches = app.get_matches();
 
     if let (subcmd, Some(submatches)) = matches.subcommand() {
@@ -65,6 +79,10 @@ fn main() {
             }
         } else if subcmd == GENERATE_COMPLETIONS {
             generate_completions(submatches);
+        } else if subcmd == complete::COMPLETE {
+            if let Err(err) = complete::execute(submatches) {
+                err.report(&mut std::io::stderr());
+            }
         }
     } else {
         std::process::exit(exitcode::USAGE);